@@ -0,0 +1,505 @@
+// Copyright 2020 Lyndon Brown
+//
+// This file is part of the PulseAudio Rust language binding.
+//
+// Licensed under the MIT license or the Apache license (version 2.0), at your option. You may not
+// copy, modify, or distribute this file except in compliance with said license. You can find copies
+// of these licenses either in the LICENSE-MIT and LICENSE-APACHE files, or alternatively at
+// <http://opensource.org/licenses/MIT> and <http://www.apache.org/licenses/LICENSE-2.0>
+// respectively.
+//
+// Portions of documentation are copied from the LGPL 2.1+ licensed PulseAudio C headers on a
+// fair-use basis, as discussed in the overall project readme (available in the git repository).
+
+//! Interoperability with other channel layout conventions.
+//!
+//! # Overview
+//!
+//! This module adds conversions between [`Map`]s and the channel layout descriptions used by other
+//! systems and formats:
+//!
+//!  * The `dwChannelMask` bit mask of the WAVE format’s `WAVEFORMATEXTENSIBLE` structure, with
+//!    [`Map::new_from_wave_mask`] and [`Map::to_wave_mask`].
+//!  * ALSA channel map position IDs (`SND_CHMAP_*`, see the [`alsa`] module), with
+//!    [`Map::new_from_alsa`] and [`Map::to_alsa`].
+//!  * The standard channel orders of common codecs, as described by [`Order`], with
+//!    [`Map::new_from_order`].
+//!
+//! Finally, [`Map::get_permutation`] computes how to reorder the channels of interleaved data from
+//! one map to another, which [`reorder`] can then apply.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use pulse::channelmap::Map;
+//! use pulse::channelmap::interop::{self, Order};
+//!
+//! // Vorbis 5.1 order to WAVE order
+//! let vorbis = Map::new_from_order(Order::Vorbis, 6).unwrap();
+//! let wave = Map::new_from_wave_mask(0x3f, 6).unwrap();
+//! let permutation = vorbis.get_permutation(&wave).unwrap();
+//! interop::reorder(&vorbis_data, &mut wave_data, Format::S16le, &permutation).unwrap();
+//! ```
+//!
+//! [`Map`]: ../struct.Map.html
+//! [`Map::new_from_wave_mask`]: ../struct.Map.html#method.new_from_wave_mask
+//! [`Map::to_wave_mask`]: ../struct.Map.html#method.to_wave_mask
+//! [`Map::new_from_alsa`]: ../struct.Map.html#method.new_from_alsa
+//! [`Map::to_alsa`]: ../struct.Map.html#method.to_alsa
+//! [`Map::new_from_order`]: ../struct.Map.html#method.new_from_order
+//! [`Map::get_permutation`]: ../struct.Map.html#method.get_permutation
+//! [`alsa`]: alsa/index.html
+//! [`Order`]: enum.Order.html
+//! [`reorder`]: fn.reorder.html
+
+use crate::error::{Code, PAErr};
+use crate::sample::{self, Format};
+use crate::sample::convert;
+use super::{Map, Position};
+use super::Position::*;
+
+/// ALSA channel map position IDs.
+///
+/// These are the values of ALSA’s `SND_CHMAP_*` constants.
+pub mod alsa {
+    /// Unspecified.
+    pub const UNKNOWN: u32 = 0;
+    /// N/A, silent.
+    pub const NA: u32 = 1;
+    /// Mono stream.
+    pub const MONO: u32 = 2;
+    /// Front left.
+    pub const FL: u32 = 3;
+    /// Front right.
+    pub const FR: u32 = 4;
+    /// Rear left.
+    pub const RL: u32 = 5;
+    /// Rear right.
+    pub const RR: u32 = 6;
+    /// Front center.
+    pub const FC: u32 = 7;
+    /// LFE.
+    pub const LFE: u32 = 8;
+    /// Side left.
+    pub const SL: u32 = 9;
+    /// Side right.
+    pub const SR: u32 = 10;
+    /// Rear center.
+    pub const RC: u32 = 11;
+    /// Front left center.
+    pub const FLC: u32 = 12;
+    /// Front right center.
+    pub const FRC: u32 = 13;
+    /// Rear left center.
+    pub const RLC: u32 = 14;
+    /// Rear right center.
+    pub const RRC: u32 = 15;
+    /// Front left wide.
+    pub const FLW: u32 = 16;
+    /// Front right wide.
+    pub const FRW: u32 = 17;
+    /// Front left high.
+    pub const FLH: u32 = 18;
+    /// Front center high.
+    pub const FCH: u32 = 19;
+    /// Front right high.
+    pub const FRH: u32 = 20;
+    /// Top center.
+    pub const TC: u32 = 21;
+    /// Top front left.
+    pub const TFL: u32 = 22;
+    /// Top front right.
+    pub const TFR: u32 = 23;
+    /// Top front center.
+    pub const TFC: u32 = 24;
+    /// Top rear left.
+    pub const TRL: u32 = 25;
+    /// Top rear right.
+    pub const TRR: u32 = 26;
+    /// Top rear center.
+    pub const TRC: u32 = 27;
+    /// Top front left center.
+    pub const TFLC: u32 = 28;
+    /// Top front right center.
+    pub const TFRC: u32 = 29;
+    /// Top side left.
+    pub const TSL: u32 = 30;
+    /// Top side right.
+    pub const TSR: u32 = 31;
+    /// Left LFE.
+    pub const LLFE: u32 = 32;
+    /// Right LFE.
+    pub const RLFE: u32 = 33;
+    /// Bottom center.
+    pub const BC: u32 = 34;
+    /// Bottom left center.
+    pub const BLC: u32 = 35;
+    /// Bottom right center.
+    pub const BRC: u32 = 36;
+}
+
+/// Positions of the WAVE `dwChannelMask` bits, from the least significant.
+const WAVE_POSITIONS: [Position; 18] = [FrontLeft, FrontRight, FrontCenter, Lfe, RearLeft,
+    RearRight, FrontLeftOfCenter, FrontRightOfCenter, RearCenter, SideLeft, SideRight, TopCenter,
+    TopFrontLeft, TopFrontCenter, TopFrontRight, TopRearLeft, TopRearCenter, TopRearRight];
+
+/// Positions of ALSA position IDs, indexed by ID.
+const ALSA_POSITIONS: [Option<Position>; 37] = [
+    None, None, Some(Mono), Some(FrontLeft), Some(FrontRight), Some(RearLeft), Some(RearRight),
+    Some(FrontCenter), Some(Lfe), Some(SideLeft), Some(SideRight), Some(RearCenter),
+    Some(FrontLeftOfCenter), Some(FrontRightOfCenter), None, None, None, None,
+    Some(TopFrontLeft), Some(TopFrontCenter), Some(TopFrontRight), Some(TopCenter),
+    Some(TopFrontLeft), Some(TopFrontRight), Some(TopFrontCenter), Some(TopRearLeft),
+    Some(TopRearRight), Some(TopRearCenter), None, None, None, None, None, None, None, None, None,
+];
+
+/// A standard channel order.
+///
+/// Each defines the layout for a given number of channels, from one up to eight.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Order {
+    /// SMPTE/ITU-R order, as also used by WAVE files (by default) and many others.
+    Smpte,
+    /// Vorbis order, as also used by Opus (channel mapping family 1).
+    Vorbis,
+    /// AAC (MPEG-4 channel configuration) order. There is no seven channel layout.
+    Aac,
+    /// FLAC order. This is identical to the SMPTE/ITU-R order for the channel counts it defines.
+    Flac,
+}
+
+const SMPTE_ORDERS: [&[Position]; 8] = [
+    &[Mono],
+    &[FrontLeft, FrontRight],
+    &[FrontLeft, FrontRight, FrontCenter],
+    &[FrontLeft, FrontRight, RearLeft, RearRight],
+    &[FrontLeft, FrontRight, FrontCenter, RearLeft, RearRight],
+    &[FrontLeft, FrontRight, FrontCenter, Lfe, RearLeft, RearRight],
+    &[FrontLeft, FrontRight, FrontCenter, Lfe, RearCenter, SideLeft, SideRight],
+    &[FrontLeft, FrontRight, FrontCenter, Lfe, RearLeft, RearRight, SideLeft, SideRight],
+];
+
+const VORBIS_ORDERS: [&[Position]; 8] = [
+    &[Mono],
+    &[FrontLeft, FrontRight],
+    &[FrontLeft, FrontCenter, FrontRight],
+    &[FrontLeft, FrontRight, RearLeft, RearRight],
+    &[FrontLeft, FrontCenter, FrontRight, RearLeft, RearRight],
+    &[FrontLeft, FrontCenter, FrontRight, RearLeft, RearRight, Lfe],
+    &[FrontLeft, FrontCenter, FrontRight, SideLeft, SideRight, RearCenter, Lfe],
+    &[FrontLeft, FrontCenter, FrontRight, SideLeft, SideRight, RearLeft, RearRight, Lfe],
+];
+
+const AAC_ORDERS: [&[Position]; 8] = [
+    &[Mono],
+    &[FrontLeft, FrontRight],
+    &[FrontCenter, FrontLeft, FrontRight],
+    &[FrontCenter, FrontLeft, FrontRight, RearCenter],
+    &[FrontCenter, FrontLeft, FrontRight, RearLeft, RearRight],
+    &[FrontCenter, FrontLeft, FrontRight, RearLeft, RearRight, Lfe],
+    &[],
+    &[FrontCenter, FrontLeftOfCenter, FrontRightOfCenter, FrontLeft, FrontRight, RearLeft,
+      RearRight, Lfe],
+];
+
+impl Position {
+    /// Gets the WAVE `dwChannelMask` bit for the position, if it has one.
+    ///
+    /// Mono is treated as front center.
+    pub fn to_wave_mask_bit(self) -> Option<u32> {
+        let pos = match self {
+            Mono => FrontCenter,
+            p => p,
+        };
+        WAVE_POSITIONS.iter().position(|p| *p == pos).map(|bit| 1 << bit)
+    }
+
+    /// Gets the position for an ALSA channel map position ID, if it has an equivalent.
+    ///
+    /// The “high” positions are treated as their “top” equivalents.
+    pub fn from_alsa(id: u32) -> Option<Self> {
+        ALSA_POSITIONS.get(id as usize).cloned().unwrap_or(None)
+    }
+
+    /// Gets the ALSA channel map position ID for the position, if it has an equivalent.
+    pub fn to_alsa(self) -> Option<u32> {
+        match self {
+            TopFrontLeft => Some(alsa::TFL),
+            TopFrontRight => Some(alsa::TFR),
+            TopFrontCenter => Some(alsa::TFC),
+            p => ALSA_POSITIONS.iter().position(|a| *a == Some(p)).map(|id| id as u32),
+        }
+    }
+}
+
+impl Map {
+    /// Creates a map from a WAVE `dwChannelMask` bit mask.
+    ///
+    /// Channels are assigned the positions of the set bits, from the least significant. Any
+    /// channels beyond those described by the mask are given aux positions, and any bits beyond the
+    /// number of channels are ignored, as per the WAVE specification. A mono stream with no mask
+    /// gets the mono position.
+    ///
+    /// Returns `None` if the number of channels is zero or too large.
+    pub fn new_from_wave_mask(mask: u32, channels: u8) -> Option<Self> {
+        if channels == 0 || channels as usize > sample::CHANNELS_MAX {
+            return None;
+        }
+        let mut positions = WAVE_POSITIONS.iter().enumerate()
+            .filter(|(bit, _)| mask & (1 << bit) != 0)
+            .map(|(_, p)| *p);
+        let mut aux = (Aux0 as i32)..;
+        let mut map = Self::default();
+        map.set_len(channels);
+        for p in map.get_mut().iter_mut() {
+            *p = match positions.next() {
+                Some(p) => p,
+                None => position_from_raw(aux.next().unwrap()),
+            };
+        }
+        if channels == 1 && mask == 0 {
+            map.get_mut()[0] = Mono;
+        }
+        Some(map)
+    }
+
+    /// Gets the WAVE `dwChannelMask` bit mask describing the map.
+    ///
+    /// Returns `None` if the map cannot be described by a mask, i.e. if the channels are not in
+    /// the order of their mask bits, a position is used twice, or a position has no mask bit (other
+    /// than aux positions at the end, which are left out of the mask).
+    pub fn to_wave_mask(&self) -> Option<u32> {
+        let mut mask = 0u32;
+        let mut aux = false;
+        for p in self.get() {
+            match p.to_wave_mask_bit() {
+                Some(bit) if !aux && bit > mask => mask |= bit,
+                None if is_aux(*p) => aux = true,
+                _ => return None,
+            }
+        }
+        Some(mask)
+    }
+
+    /// Creates a map from a list of ALSA channel map position IDs.
+    ///
+    /// Positions with no equivalent (including `UNKNOWN` and `NA`) are given aux positions.
+    ///
+    /// Returns `None` if the number of channels is zero or too large.
+    pub fn new_from_alsa(ids: &[u32]) -> Option<Self> {
+        if ids.is_empty() || ids.len() > sample::CHANNELS_MAX {
+            return None;
+        }
+        let mut aux = (Aux0 as i32)..;
+        let mut map = Self::default();
+        map.set_len(ids.len() as u8);
+        for (p, id) in map.get_mut().iter_mut().zip(ids) {
+            *p = match Position::from_alsa(*id) {
+                Some(p) => p,
+                None => position_from_raw(aux.next().unwrap()),
+            };
+        }
+        Some(map)
+    }
+
+    /// Gets the list of ALSA channel map position IDs describing the map.
+    ///
+    /// Aux positions are given as `UNKNOWN`. Returns `None` if any position is invalid.
+    pub fn to_alsa(&self) -> Option<Vec<u32>> {
+        self.get().iter().map(|p| match p.to_alsa() {
+            Some(id) => Some(id),
+            None if is_aux(*p) => Some(alsa::UNKNOWN),
+            None => None,
+        }).collect()
+    }
+
+    /// Creates a map for the given standard order and number of channels.
+    ///
+    /// Returns `None` if the order does not define a layout for the number of channels.
+    pub fn new_from_order(order: Order, channels: u8) -> Option<Self> {
+        let orders = match order {
+            Order::Smpte | Order::Flac => &SMPTE_ORDERS,
+            Order::Vorbis => &VORBIS_ORDERS,
+            Order::Aac => &AAC_ORDERS,
+        };
+        let positions = match channels {
+            0 => return None,
+            c => orders.get(c as usize - 1)?,
+        };
+        if positions.is_empty() {
+            return None;
+        }
+        let mut map = Self::default();
+        map.set_len(channels);
+        map.get_mut().copy_from_slice(positions);
+        Some(map)
+    }
+
+    /// Computes the permutation that reorders interleaved data from this map to the given map.
+    ///
+    /// Element `i` of the result is the index of the channel in this map that provides channel `i`
+    /// of the other map. Should a position be used more than once, occurrences are matched up in
+    /// order.
+    ///
+    /// Returns `None` if the maps do not contain the same positions.
+    pub fn get_permutation(&self, to: &Self) -> Option<Vec<usize>> {
+        if self.len() != to.len() {
+            return None;
+        }
+        let mut used = [false; sample::CHANNELS_MAX];
+        to.get().iter().map(|p| {
+            let i = self.get().iter().enumerate().position(|(i, q)| !used[i] && q == p)?;
+            used[i] = true;
+            Some(i)
+        }).collect()
+    }
+}
+
+/// Reorders the channels of interleaved frames, as described by a permutation from
+/// [`Map::get_permutation`].
+///
+/// The input buffer must contain a whole number of frames, and the output buffer must be at least
+/// as large. Returns the number of frames reordered.
+///
+/// [`Map::get_permutation`]: ../struct.Map.html#method.get_permutation
+pub fn reorder(src: &[u8], dst: &mut [u8], format: Format, permutation: &[usize])
+    -> Result<usize, PAErr>
+{
+    let sample_size = convert::sample_size(format).ok_or(PAErr::from(Code::Invalid))?;
+    let channels = permutation.len();
+    if channels == 0 || permutation.iter().any(|c| *c >= channels) {
+        return Err(PAErr::from(Code::Invalid));
+    }
+    let frame_size = sample_size * channels;
+    if src.len() % frame_size != 0 {
+        return Err(PAErr::from(Code::Invalid));
+    }
+    if dst.len() < src.len() {
+        return Err(PAErr::from(Code::TooLarge));
+    }
+    for (s, d) in src.chunks_exact(frame_size).zip(dst.chunks_exact_mut(frame_size)) {
+        for (out, from) in d.chunks_exact_mut(sample_size).zip(permutation) {
+            let start = from * sample_size;
+            out.copy_from_slice(&s[start..(start + sample_size)]);
+        }
+    }
+    Ok(src.len() / frame_size)
+}
+
+fn is_aux(p: Position) -> bool {
+    (p as i32) >= (Aux0 as i32) && (p as i32) <= (Aux31 as i32)
+}
+
+/// Converts a raw position value, giving `Position::Invalid` for values outside of the range of
+/// positions.
+fn position_from_raw(p: i32) -> Position {
+    match p >= Position::Invalid as i32 && p <= Aux31 as i32 {
+        // SAFETY: `Position` is a `#[repr(C)]` enum, thus represented as a C `int`, whose
+        // discriminants run contiguously from `Invalid` (-1) and `Mono` (0) through the named
+        // positions and `Aux0` to `Aux31`, thus every value in the range checked is a valid
+        // discriminant.
+        true => unsafe { std::mem::transmute::<i32, Position>(p) },
+        false => Position::Invalid,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raw_positions() {
+        assert_eq!(position_from_raw(Aux0 as i32), Aux0);
+        assert_eq!(position_from_raw(Aux31 as i32), Aux31);
+        assert_eq!(position_from_raw(Mono as i32), Mono);
+        assert_eq!(position_from_raw(Position::Invalid as i32), Position::Invalid);
+        assert_eq!(position_from_raw(Aux31 as i32 + 1), Position::Invalid);
+        assert_eq!(position_from_raw(-2), Position::Invalid);
+    }
+
+    #[test]
+    fn wave_mask() {
+        let map = Map::new_from_wave_mask(0x3F, 6).unwrap();
+        assert_eq!(map.get(), &[FrontLeft, FrontRight, FrontCenter, Lfe, RearLeft, RearRight]);
+        assert_eq!(map.to_wave_mask(), Some(0x3F));
+
+        let map = Map::new_from_wave_mask(0x60F, 6).unwrap();
+        assert_eq!(map.get(), &[FrontLeft, FrontRight, FrontCenter, Lfe, SideLeft, SideRight]);
+        assert_eq!(map.to_wave_mask(), Some(0x60F));
+
+        // Extra channels, and extra bits
+        let map = Map::new_from_wave_mask(0x3, 4).unwrap();
+        assert_eq!(map.get(), &[FrontLeft, FrontRight, Aux0, Aux1]);
+        assert_eq!(map.to_wave_mask(), Some(0x3));
+        let map = Map::new_from_wave_mask(0x3F, 2).unwrap();
+        assert_eq!(map.get(), &[FrontLeft, FrontRight]);
+
+        assert_eq!(Map::new_from_wave_mask(0, 1).unwrap().get(), &[Mono]);
+        assert_eq!(Map::new_from_wave_mask(0x4, 1).unwrap().to_wave_mask(), Some(0x4));
+        assert!(Map::new_from_wave_mask(0x3, 0).is_none());
+
+        // Not in mask order
+        assert_eq!(Map::new_from_order(Order::Vorbis, 3).unwrap().to_wave_mask(), None);
+    }
+
+    #[test]
+    fn alsa() {
+        let map = Map::new_from_alsa(&[alsa::FL, alsa::FR, alsa::RL, alsa::RR, alsa::FC, alsa::LFE])
+            .unwrap();
+        assert_eq!(map.get(), &[FrontLeft, FrontRight, RearLeft, RearRight, FrontCenter, Lfe]);
+        assert_eq!(map.to_alsa().unwrap(),
+            vec![alsa::FL, alsa::FR, alsa::RL, alsa::RR, alsa::FC, alsa::LFE]);
+
+        let map = Map::new_from_alsa(&[alsa::MONO, alsa::NA, alsa::FLW, alsa::FCH]).unwrap();
+        assert_eq!(map.get(), &[Mono, Aux0, Aux1, TopFrontCenter]);
+        assert_eq!(map.to_alsa().unwrap(),
+            vec![alsa::MONO, alsa::UNKNOWN, alsa::UNKNOWN, alsa::TFC]);
+        assert_eq!(Position::from_alsa(1000), None);
+        assert!(Map::new_from_alsa(&[]).is_none());
+    }
+
+    #[test]
+    fn orders() {
+        for order in [Order::Smpte, Order::Vorbis, Order::Aac, Order::Flac].iter() {
+            for channels in 1..=8 {
+                match Map::new_from_order(*order, channels) {
+                    Some(map) => {
+                        assert_eq!(map.len(), channels);
+                        // Every order is a permutation of the SMPTE order, except AAC with 4
+                        // and 8 channels, which uses rear center and front left/right of center
+                        // positions instead of rear and side pairs
+                        let smpte = Map::new_from_order(Order::Smpte, channels).unwrap();
+                        if *order != Order::Aac || (channels != 4 && channels != 8) {
+                            assert!(map.get_permutation(&smpte).is_some(), "{:?} {}", order,
+                                channels);
+                        }
+                    },
+                    None => assert!(*order == Order::Aac && channels == 7),
+                }
+            }
+            assert!(Map::new_from_order(*order, 0).is_none());
+            assert!(Map::new_from_order(*order, 9).is_none());
+        }
+    }
+
+    #[test]
+    fn permutation_and_reorder() {
+        let vorbis = Map::new_from_order(Order::Vorbis, 6).unwrap();
+        let smpte = Map::new_from_order(Order::Smpte, 6).unwrap();
+        let permutation = vorbis.get_permutation(&smpte).unwrap();
+        assert_eq!(permutation, vec![0, 2, 1, 5, 3, 4]);
+
+        // Vorbis frame: FL=1 FC=2 FR=3 RL=4 RR=5 LFE=6
+        let src: Vec<u8> = [1i16, 2, 3, 4, 5, 6, 11, 12, 13, 14, 15, 16].iter()
+            .flat_map(|v| v.to_le_bytes().to_vec())
+            .collect();
+        let mut dst = vec![0u8; src.len()];
+        assert_eq!(reorder(&src, &mut dst, Format::S16le, &permutation).unwrap(), 2);
+        let out: Vec<i16> = dst.chunks(2).map(|c| i16::from_le_bytes([c[0], c[1]])).collect();
+        assert_eq!(out, vec![1, 3, 2, 6, 4, 5, 11, 13, 12, 16, 14, 15]);
+
+        let stereo = Map::new_from_order(Order::Smpte, 2).unwrap();
+        assert!(vorbis.get_permutation(&stereo).is_none());
+        assert!(reorder(&src, &mut dst, Format::S16le, &[0, 6]).is_err());
+    }
+}
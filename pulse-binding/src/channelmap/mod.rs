@@ -41,9 +41,14 @@
 //! [`Map::init_auto`]: struct.Map.html#method.init_auto
 //! [`Map::init_extend`]: struct.Map.html#method.init_extend
 
+pub mod interop;
+pub mod remix;
+
 use std::borrow::{Borrow, BorrowMut};
 use std::ffi::{CStr, CString};
 use std::borrow::Cow;
+use std::str::FromStr;
+use crate::error::{Code, PAErr};
 use crate::sample;
 
 pub use capi::pa_channel_map_def_t as MapDef;
@@ -59,7 +64,7 @@ pub const POSITION_MASK_ALL: PositionMask = 0xffffffffffffffffu64;
 /// Note, certain aliases, specifically `Left`, `Right`, `Center` and `Subwoofer`, available in the
 /// equivalent C enum are not provided here, since Rust does not allow aliases.
 #[repr(C)]
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Position {
     /* NOTE: This enum’s variants and variant values **must** remain identical to the `sys` crate
        (C API) equivalent */
@@ -472,3 +477,52 @@ impl Map {
         unsafe { capi::pa_channel_map_mask(self.as_ref()) }
     }
 }
+
+impl std::fmt::Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match Position::to_string(*self) {
+            Some(s) => f.write_str(&s),
+            None => f.write_str("(invalid)"),
+        }
+    }
+}
+
+/// Parses a channel position label, as accepted by
+/// [`Position::from_string`](enum.Position.html#method.from_string).
+///
+/// Fails with `Code::Invalid` if the label is not recognised.
+impl FromStr for Position {
+    type Err = PAErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match Position::from_string(s) {
+            Position::Invalid => Err(PAErr::from(Code::Invalid)),
+            pos => Ok(pos),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+serde_via_string!(Position, "channel position");
+
+impl std::fmt::Display for Map {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&self.print())
+    }
+}
+
+/// Parses a channel position list or well-known mapping name, as accepted by
+/// [`Map::new_from_string`](struct.Map.html#method.new_from_string).
+///
+/// Fails with `Code::Invalid` if the string cannot be parsed.
+impl FromStr for Map {
+    type Err = PAErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Map::new_from_string(s).map_err(|_| PAErr::from(Code::Invalid))
+    }
+}
+
+#[cfg(feature = "serde")]
+serde_via_string!(Map, "channel map");
+
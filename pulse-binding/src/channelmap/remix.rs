@@ -0,0 +1,720 @@
+// Copyright 2020 Lyndon Brown
+//
+// This file is part of the PulseAudio Rust language binding.
+//
+// Licensed under the MIT license or the Apache license (version 2.0), at your option. You may not
+// copy, modify, or distribute this file except in compliance with said license. You can find copies
+// of these licenses either in the LICENSE-MIT and LICENSE-APACHE files, or alternatively at
+// <http://opensource.org/licenses/MIT> and <http://www.apache.org/licenses/LICENSE-2.0>
+// respectively.
+//
+// Portions of documentation are copied from the LGPL 2.1+ licensed PulseAudio C headers on a
+// fair-use basis, as discussed in the overall project readme (available in the git repository).
+
+//! Channel remixing (upmixing and downmixing) of audio data.
+//!
+//! # Overview
+//!
+//! A [`Matrix`] describes how each output channel is formed from the input channels, as a set of
+//! gain coefficients. A matrix can be built from a pair of channel maps with [`Matrix::from_maps`],
+//! following the same rules the server uses when remixing between streams and devices:
+//!
+//!  * Channels with identical positions are connected directly.
+//!  * A mono input is copied to all non-LFE outputs, and a mono output takes the average of all
+//!    inputs.
+//!  * Rear and side channels are treated as interchangeable when otherwise unconnected.
+//!  * Unconnected left, right and center outputs take the average of the inputs on the same side,
+//!    with center falling back to left and right.
+//!  * Left, right and center inputs that remain unconnected are mixed in at a low level to outputs
+//!    on the same side; a center input with no center output to go to is mixed into left and right
+//!    outputs at half gain.
+//!  * Aux channels are only ever connected directly.
+//!  * Finally, each output is normalized such that its gains sum to no more than one, to avoid
+//!    clipping.
+//!
+//! Whether LFE is produced or consumed, and whether remixing is done at all, is controlled with
+//! [`Options`]. The server’s optional LFE crossover filter is not applied.
+//!
+//! Matrices are plain data and can be inspected and adjusted freely before use.
+//!
+//! A [`Remixer`] applies a matrix to interleaved buffers of any sample format, converting through
+//! floating point internally.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use pulse::channelmap::remix::{Remixer, Options};
+//! use pulse::sample::Format;
+//!
+//! let mut remixer = Remixer::new(&surround_map, &stereo_map, Format::S16le, &Options::default())
+//!     .unwrap();
+//! // Boost the center channel in the left output
+//! remixer.get_matrix_mut().set(0, 2, 0.7);
+//! let stereo = remixer.remix_to_vec(&surround_data).unwrap();
+//! ```
+//!
+//! [`Matrix`]: struct.Matrix.html
+//! [`Matrix::from_maps`]: struct.Matrix.html#method.from_maps
+//! [`Options`]: struct.Options.html
+//! [`Remixer`]: struct.Remixer.html
+
+use crate::error::{Code, PAErr};
+use crate::sample::{self, Format};
+use crate::sample::convert;
+use super::{Map, Position};
+
+/// Number of frames processed at a time.
+const BLOCK_FRAMES: usize = 128;
+
+/// How channels are to be matched up.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Mode {
+    /// Full remixing, as described in the module documentation.
+    Remix,
+    /// Connect only channels with identical positions; no mixing.
+    NoRemix,
+    /// Ignore positions, connecting channels by index.
+    NoRemap,
+}
+
+/// Options controlling how a matrix is built from channel maps.
+///
+/// The defaults match the server’s default configuration.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Options {
+    /// How channels are to be matched up.
+    pub mode: Mode,
+    /// Whether to produce an LFE output from all inputs when the input has no LFE channel.
+    pub produce_lfe: bool,
+    /// Whether to mix an LFE input into all outputs when the output has no LFE channel.
+    pub consume_lfe: bool,
+    /// Whether a mono input is copied to all outputs, rather than only to front channels.
+    pub fill_outputs: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            mode: Mode::Remix,
+            produce_lfe: false,
+            consume_lfe: false,
+            fill_outputs: true,
+        }
+    }
+}
+
+/// A remixing matrix.
+///
+/// Holds one gain coefficient for each combination of output and input channel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Matrix {
+    inputs: usize,
+    outputs: usize,
+    /// Coefficients, stored by output channel.
+    coefficients: Vec<f32>,
+}
+
+impl Matrix {
+    /// Creates a new matrix, with all coefficients zero.
+    ///
+    /// **Panics** if either number of channels exceeds [`sample::CHANNELS_MAX`].
+    ///
+    /// [`sample::CHANNELS_MAX`]: ../../sample/constant.CHANNELS_MAX.html
+    pub fn new(inputs: u8, outputs: u8) -> Self {
+        assert!(inputs as usize <= sample::CHANNELS_MAX);
+        assert!(outputs as usize <= sample::CHANNELS_MAX);
+        Self {
+            inputs: inputs as usize,
+            outputs: outputs as usize,
+            coefficients: vec![0.0; inputs as usize * outputs as usize],
+        }
+    }
+
+    /// Builds a matrix for remixing between the given channel maps.
+    pub fn from_maps(from: &Map, to: &Map, options: &Options) -> Self {
+        let from = from.get();
+        let to = to.get();
+        let mut m = Self::new(from.len() as u8, to.len() as u8);
+        match options.mode {
+            Mode::NoRemap => {
+                for c in 0..(from.len().min(to.len())) {
+                    m.set(c as u8, c as u8, 1.0);
+                }
+            },
+            Mode::NoRemix => {
+                for (oc, b) in to.iter().enumerate() {
+                    for (ic, a) in from.iter().enumerate() {
+                        if a == b {
+                            m.set(oc as u8, ic as u8, 1.0);
+                        }
+                    }
+                }
+            },
+            Mode::Remix => m.build_remix(from, to, options),
+        }
+        m.normalize();
+        m
+    }
+
+    /// Gets the number of input channels.
+    #[inline]
+    pub fn get_inputs(&self) -> u8 {
+        self.inputs as u8
+    }
+
+    /// Gets the number of output channels.
+    #[inline]
+    pub fn get_outputs(&self) -> u8 {
+        self.outputs as u8
+    }
+
+    /// Gets the gain applied to input channel `input` in output channel `output`.
+    ///
+    /// **Panics** if either channel is out of range.
+    #[inline]
+    pub fn get(&self, output: u8, input: u8) -> f32 {
+        self.get_row(output)[input as usize]
+    }
+
+    /// Sets the gain applied to input channel `input` in output channel `output`.
+    ///
+    /// **Panics** if either channel is out of range.
+    #[inline]
+    pub fn set(&mut self, output: u8, input: u8, gain: f32) {
+        self.get_row_mut(output)[input as usize] = gain;
+    }
+
+    /// Gets the gains of all input channels for output channel `output`.
+    ///
+    /// **Panics** if the channel is out of range.
+    #[inline]
+    pub fn get_row(&self, output: u8) -> &[f32] {
+        assert!((output as usize) < self.outputs);
+        let start = output as usize * self.inputs;
+        &self.coefficients[start..(start + self.inputs)]
+    }
+
+    /// Gets the gains of all input channels for output channel `output`, mutably.
+    ///
+    /// **Panics** if the channel is out of range.
+    #[inline]
+    pub fn get_row_mut(&mut self, output: u8) -> &mut [f32] {
+        assert!((output as usize) < self.outputs);
+        let start = output as usize * self.inputs;
+        &mut self.coefficients[start..(start + self.inputs)]
+    }
+
+    /// Scales down any output whose gains sum to more than one, such that they sum to one.
+    pub fn normalize(&mut self) {
+        if self.inputs == 0 {
+            return;
+        }
+        for row in self.coefficients.chunks_mut(self.inputs) {
+            let sum: f32 = row.iter().sum();
+            if sum > 1.0 {
+                for v in row.iter_mut() {
+                    *v /= sum;
+                }
+            }
+        }
+    }
+
+    /// Checks whether the matrix simply passes each channel through unchanged.
+    pub fn is_identity(&self) -> bool {
+        if self.inputs != self.outputs {
+            return false;
+        }
+        self.coefficients.chunks(self.inputs).enumerate().all(|(oc, row)| {
+            row.iter().enumerate().all(|(ic, v)| *v == if ic == oc { 1.0 } else { 0.0 })
+        })
+    }
+
+    /// Applies the matrix to interleaved floating point frames.
+    fn apply(&self, src: &[f32], dst: &mut [f32]) {
+        for (i, o) in src.chunks_exact(self.inputs).zip(dst.chunks_exact_mut(self.outputs)) {
+            for (out, row) in o.iter_mut().zip(self.coefficients.chunks_exact(self.inputs)) {
+                *out = row.iter().zip(i).map(|(g, v)| g * v).sum();
+            }
+        }
+    }
+
+    /// Builds a full remixing matrix, following the server’s rules.
+    fn build_remix(&mut self, from: &[Position], to: &[Position], options: &Options) {
+        let n_ic = from.len();
+        let mut ic_connected = [false; sample::CHANNELS_MAX];
+
+        let ic_left = from.iter().filter(|p| on_left(**p)).count();
+        let ic_right = from.iter().filter(|p| on_right(**p)).count();
+        let ic_center = from.iter().filter(|p| on_center(**p)).count();
+
+        let mono_map = mono_output_map(to, options.fill_outputs);
+
+        for (oc, b) in to.iter().enumerate() {
+            let b = *b;
+            let row = self.get_row_mut(oc as u8);
+            let mut oc_connected = false;
+
+            for (ic, a) in from.iter().enumerate() {
+                let a = *a;
+                if a == b {
+                    row[ic] = 1.0;
+                }
+                else if a == Position::Mono && mono_map[oc] > 0.0 {
+                    row[ic] = mono_map[oc];
+                }
+                else if b == Position::Mono {
+                    row[ic] = 1.0 / n_ic as f32;
+                }
+                else {
+                    continue;
+                }
+                oc_connected = true;
+                ic_connected[ic] = true;
+            }
+
+            if !oc_connected {
+                // Maybe it is due to 5.1 rear/side confusion?
+                for (ic, a) in from.iter().enumerate() {
+                    if ic_connected[ic] {
+                        continue;
+                    }
+                    let swapped = match (*a, b) {
+                        (Position::RearLeft, Position::SideLeft) |
+                        (Position::SideLeft, Position::RearLeft) |
+                        (Position::RearRight, Position::SideRight) |
+                        (Position::SideRight, Position::RearRight) => true,
+                        _ => false,
+                    };
+                    if swapped {
+                        row[ic] = 1.0;
+                        oc_connected = true;
+                        ic_connected[ic] = true;
+                    }
+                }
+            }
+
+            if oc_connected {
+                continue;
+            }
+
+            // Try to find matching inputs for this output
+            let (filter, count): (fn(Position) -> bool, usize) = if on_left(b) {
+                (on_left, ic_left)
+            }
+            else if on_right(b) {
+                (on_right, ic_right)
+            }
+            else if on_center(b) && ic_center > 0 {
+                (on_center, ic_center)
+            }
+            else if on_center(b) {
+                (|p| on_left(p) || on_right(p), ic_left + ic_right)
+            }
+            else if on_lfe(b) && options.produce_lfe {
+                // An LFE fed from all inputs does not count as connecting them
+                for v in row.iter_mut() {
+                    *v = 1.0 / n_ic as f32;
+                }
+                continue;
+            }
+            else {
+                continue;
+            };
+            for (ic, a) in from.iter().enumerate() {
+                if filter(*a) {
+                    row[ic] = 1.0 / count as f32;
+                    ic_connected[ic] = true;
+                }
+            }
+        }
+
+        let unconnected = |f: fn(Position) -> bool| {
+            from.iter().enumerate().filter(|(ic, a)| !ic_connected[*ic] && f(**a)).count()
+        };
+        let ic_unconnected_left = unconnected(on_left);
+        let ic_unconnected_right = unconnected(on_right);
+        let ic_unconnected_center = unconnected(on_center);
+        let ic_unconnected_lfe = unconnected(on_lfe);
+        let mut ic_unconnected_center_mixed_in = false;
+
+        for (ic, a) in from.iter().enumerate() {
+            let a = *a;
+            if ic_connected[ic] {
+                continue;
+            }
+            for (oc, b) in to.iter().enumerate() {
+                let b = *b;
+                let gain = if on_left(a) && on_left(b) {
+                    (1.0 / 9.0) / ic_unconnected_left as f32
+                }
+                else if on_right(a) && on_right(b) {
+                    (1.0 / 9.0) / ic_unconnected_right as f32
+                }
+                else if on_center(a) && on_center(b) {
+                    ic_unconnected_center_mixed_in = true;
+                    (1.0 / 9.0) / ic_unconnected_center as f32
+                }
+                else if on_lfe(a) && options.consume_lfe {
+                    0.375 / ic_unconnected_lfe as f32
+                }
+                else {
+                    continue;
+                };
+                self.set(oc as u8, ic as u8, gain);
+            }
+        }
+
+        if ic_unconnected_center > 0 && !ic_unconnected_center_mixed_in {
+            // No center output to mix unconnected center inputs into, so mix them into left and
+            // right, preferring outputs on the same front/rear/side plane.
+            let mut ncenter = [0usize; sample::CHANNELS_MAX];
+            let mut found_frs = [false; sample::CHANNELS_MAX];
+            let lr = |p: Position| on_left(p) || on_right(p);
+
+            for (ic, a) in from.iter().enumerate() {
+                if ic_connected[ic] || !on_center(*a) {
+                    continue;
+                }
+                found_frs[ic] = to.iter().any(|b| lr(*b) && plane(*a) == plane(*b));
+                for (oc, b) in to.iter().enumerate() {
+                    if lr(*b) && (!found_frs[ic] || plane(*a) == plane(*b)) {
+                        ncenter[oc] += 1;
+                    }
+                }
+            }
+
+            for (oc, b) in to.iter().enumerate() {
+                if !lr(*b) || ncenter[oc] == 0 {
+                    continue;
+                }
+                for (ic, a) in from.iter().enumerate() {
+                    if ic_connected[ic] || !on_center(*a) {
+                        continue;
+                    }
+                    if !found_frs[ic] || plane(*a) == plane(*b) {
+                        self.set(oc as u8, ic as u8, 0.5 / ncenter[oc] as f32);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A channel remixer for interleaved audio data.
+#[derive(Debug, Clone)]
+pub struct Remixer {
+    matrix: Matrix,
+    format: Format,
+    input: Vec<f32>,
+    output: Vec<f32>,
+}
+
+impl Remixer {
+    /// Creates a new remixer between the given channel maps, for data of the given sample format.
+    ///
+    /// Returns an error if the format is invalid, or either map is empty.
+    pub fn new(from: &Map, to: &Map, format: Format, options: &Options) -> Result<Self, PAErr> {
+        Self::with_matrix(Matrix::from_maps(from, to, options), format)
+    }
+
+    /// Creates a new remixer using the given matrix, for data of the given sample format.
+    ///
+    /// Returns an error if the format is invalid, or the matrix has no inputs or outputs.
+    pub fn with_matrix(matrix: Matrix, format: Format) -> Result<Self, PAErr> {
+        if convert::sample_size(format).is_none() || matrix.inputs == 0 || matrix.outputs == 0 {
+            return Err(PAErr::from(Code::Invalid));
+        }
+        Ok(Self { matrix: matrix, format: format, input: Vec::new(), output: Vec::new() })
+    }
+
+    /// Gets the matrix in use.
+    #[inline]
+    pub fn get_matrix(&self) -> &Matrix {
+        &self.matrix
+    }
+
+    /// Gets the matrix in use, mutably, allowing coefficients to be overridden.
+    #[inline]
+    pub fn get_matrix_mut(&mut self) -> &mut Matrix {
+        &mut self.matrix
+    }
+
+    /// Gets the sample format handled.
+    #[inline]
+    pub fn get_format(&self) -> Format {
+        self.format
+    }
+
+    /// Remixes a buffer of interleaved frames.
+    ///
+    /// The input buffer must contain a whole number of frames, and the output buffer must be large
+    /// enough to hold the remixed result. Returns the number of frames remixed.
+    pub fn remix(&mut self, src: &[u8], dst: &mut [u8]) -> Result<usize, PAErr> {
+        let sample_size = convert::sample_size(self.format).unwrap();
+        let in_frame = sample_size * self.matrix.inputs;
+        let out_frame = sample_size * self.matrix.outputs;
+        if src.len() % in_frame != 0 {
+            return Err(PAErr::from(Code::Invalid));
+        }
+        let frames = src.len() / in_frame;
+        if dst.len() < frames * out_frame {
+            return Err(PAErr::from(Code::TooLarge));
+        }
+
+        self.input.resize(BLOCK_FRAMES * self.matrix.inputs, 0.0);
+        self.output.resize(BLOCK_FRAMES * self.matrix.outputs, 0.0);
+        let chunks = src.chunks(BLOCK_FRAMES * in_frame)
+            .zip(dst[..(frames * out_frame)].chunks_mut(BLOCK_FRAMES * out_frame));
+        for (s, d) in chunks {
+            let n = s.len() / in_frame;
+            let input = &mut self.input[..(n * self.matrix.inputs)];
+            let output = &mut self.output[..(n * self.matrix.outputs)];
+            convert::to_f32(s, self.format, input)?;
+            self.matrix.apply(input, output);
+            convert::from_f32(output, self.format, d)?;
+        }
+        Ok(frames)
+    }
+
+    /// Remixes a buffer of interleaved frames, returning the result in a new buffer.
+    ///
+    /// The input buffer must contain a whole number of frames.
+    pub fn remix_to_vec(&mut self, src: &[u8]) -> Result<Vec<u8>, PAErr> {
+        let sample_size = convert::sample_size(self.format).unwrap();
+        let frames = src.len() / (sample_size * self.matrix.inputs);
+        let mut dst = vec![0u8; frames * sample_size * self.matrix.outputs];
+        self.remix(src, &mut dst)?;
+        Ok(dst)
+    }
+
+    /// Remixes a buffer of interleaved native floating point frames, regardless of the sample
+    /// format this remixer was created for.
+    ///
+    /// The input buffer must contain a whole number of frames, and the output buffer must be large
+    /// enough to hold the remixed result. Returns the number of frames remixed.
+    pub fn remix_f32(&self, src: &[f32], dst: &mut [f32]) -> Result<usize, PAErr> {
+        if src.len() % self.matrix.inputs != 0 {
+            return Err(PAErr::from(Code::Invalid));
+        }
+        let frames = src.len() / self.matrix.inputs;
+        if dst.len() < frames * self.matrix.outputs {
+            return Err(PAErr::from(Code::TooLarge));
+        }
+        self.matrix.apply(src, &mut dst[..(frames * self.matrix.outputs)]);
+        Ok(frames)
+    }
+}
+
+/// Works out the gain with which a mono input is copied to each output.
+fn mono_output_map(to: &[Position], fill: bool) -> [f32; sample::CHANNELS_MAX] {
+    let mut map = [0.0; sample::CHANNELS_MAX];
+    let non_lfe = |map: &mut [f32; sample::CHANNELS_MAX]| {
+        for (oc, b) in to.iter().enumerate() {
+            map[oc] = if on_lfe(*b) { 0.0 } else { 1.0 };
+        }
+    };
+    if fill {
+        // Mono goes to all non-LFE outputs
+        non_lfe(&mut map);
+        return map;
+    }
+    let preferences: [&dyn Fn(Position) -> bool; 3] = [
+        &|p| p == Position::Mono,
+        &|p| p == Position::FrontCenter,
+        &|p| p == Position::FrontLeft || p == Position::FrontRight,
+    ];
+    for wanted in preferences.iter() {
+        let mut found = false;
+        for (oc, b) in to.iter().enumerate() {
+            if wanted(*b) {
+                map[oc] = 1.0;
+                found = true;
+            }
+        }
+        if found {
+            return map;
+        }
+    }
+    // No center, front left or front right, so just copy to all non-LFE outputs
+    non_lfe(&mut map);
+    map
+}
+
+fn on_left(p: Position) -> bool {
+    match p {
+        Position::FrontLeft | Position::RearLeft | Position::FrontLeftOfCenter |
+        Position::SideLeft | Position::TopFrontLeft | Position::TopRearLeft => true,
+        _ => false,
+    }
+}
+
+fn on_right(p: Position) -> bool {
+    match p {
+        Position::FrontRight | Position::RearRight | Position::FrontRightOfCenter |
+        Position::SideRight | Position::TopFrontRight | Position::TopRearRight => true,
+        _ => false,
+    }
+}
+
+fn on_center(p: Position) -> bool {
+    match p {
+        Position::FrontCenter | Position::RearCenter | Position::TopCenter |
+        Position::TopFrontCenter | Position::TopRearCenter => true,
+        _ => false,
+    }
+}
+
+fn on_lfe(p: Position) -> bool {
+    p == Position::Lfe
+}
+
+/// Plane on which a speaker lies.
+#[derive(PartialEq)]
+enum Plane {
+    Front,
+    Rear,
+    Side,
+    Other,
+}
+
+fn plane(p: Position) -> Plane {
+    match p {
+        Position::FrontLeft | Position::FrontRight | Position::FrontCenter |
+        Position::TopFrontLeft | Position::TopFrontRight | Position::TopFrontCenter |
+        Position::FrontLeftOfCenter | Position::FrontRightOfCenter => Plane::Front,
+        Position::RearLeft | Position::RearRight | Position::RearCenter |
+        Position::TopRearLeft | Position::TopRearRight | Position::TopRearCenter => Plane::Rear,
+        Position::SideLeft | Position::SideRight | Position::TopCenter => Plane::Side,
+        _ => Plane::Other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::Position::*;
+    use crate::test_util::map;
+
+    fn rows(m: &Matrix) -> Vec<Vec<f32>> {
+        (0..m.get_outputs()).map(|oc| m.get_row(oc).to_vec()).collect()
+    }
+
+    fn assert_close(a: &[Vec<f32>], b: &[Vec<f32>]) {
+        assert_eq!(a.len(), b.len());
+        for (ra, rb) in a.iter().zip(b) {
+            for (va, vb) in ra.iter().zip(rb) {
+                assert!((va - vb).abs() < 1e-6, "{:?} != {:?}", a, b);
+            }
+        }
+    }
+
+    const STEREO: [Position; 2] = [FrontLeft, FrontRight];
+    const SURROUND_51: [Position; 6] = [FrontLeft, FrontRight, FrontCenter, Lfe, RearLeft,
+        RearRight];
+
+    #[test]
+    fn mono_stereo() {
+        let m = Matrix::from_maps(&map(&[Mono]), &map(&STEREO), &Options::default());
+        assert_close(&rows(&m), &[vec![1.0], vec![1.0]]);
+
+        let m = Matrix::from_maps(&map(&STEREO), &map(&[Mono]), &Options::default());
+        assert_close(&rows(&m), &[vec![0.5, 0.5]]);
+    }
+
+    #[test]
+    fn identity() {
+        let m = Matrix::from_maps(&map(&SURROUND_51), &map(&SURROUND_51), &Options::default());
+        assert!(m.is_identity());
+
+        let reordered = [FrontRight, FrontLeft];
+        let m = Matrix::from_maps(&map(&STEREO), &map(&reordered), &Options::default());
+        assert_close(&rows(&m), &[vec![0.0, 1.0], vec![1.0, 0.0]]);
+    }
+
+    #[test]
+    fn downmix_51_to_stereo() {
+        let m = Matrix::from_maps(&map(&SURROUND_51), &map(&STEREO), &Options::default());
+        // FL + RL/9 + FC/2, normalized; LFE dropped
+        let sum = 1.0 + 1.0 / 9.0 + 0.5;
+        assert_close(&rows(&m), &[
+            vec![1.0 / sum, 0.0, 0.5 / sum, 0.0, (1.0 / 9.0) / sum, 0.0],
+            vec![0.0, 1.0 / sum, 0.5 / sum, 0.0, 0.0, (1.0 / 9.0) / sum],
+        ]);
+
+        let options = Options { consume_lfe: true, ..Options::default() };
+        let m = Matrix::from_maps(&map(&SURROUND_51), &map(&STEREO), &options);
+        let sum = 1.0 + 1.0 / 9.0 + 0.5 + 0.375;
+        assert!((m.get(0, 3) - 0.375 / sum).abs() < 1e-6);
+    }
+
+    #[test]
+    fn upmix_stereo_to_51() {
+        let m = Matrix::from_maps(&map(&STEREO), &map(&SURROUND_51), &Options::default());
+        assert_close(&rows(&m), &[
+            vec![1.0, 0.0],
+            vec![0.0, 1.0],
+            vec![0.5, 0.5],
+            vec![0.0, 0.0],
+            vec![1.0, 0.0],
+            vec![0.0, 1.0],
+        ]);
+
+        let options = Options { produce_lfe: true, ..Options::default() };
+        let m = Matrix::from_maps(&map(&STEREO), &map(&SURROUND_51), &options);
+        assert_close(&rows(&m)[3..4], &[vec![0.5, 0.5]]);
+    }
+
+    #[test]
+    fn rear_side_confusion() {
+        let side = [FrontLeft, FrontRight, FrontCenter, Lfe, SideLeft, SideRight];
+        let m = Matrix::from_maps(&map(&SURROUND_51), &map(&side), &Options::default());
+        assert!(m.is_identity());
+    }
+
+    #[test]
+    fn modes() {
+        let aux = [Aux0, Aux1];
+        let options = Options { mode: Mode::NoRemix, ..Options::default() };
+        let m = Matrix::from_maps(&map(&STEREO), &map(&aux), &options);
+        assert_close(&rows(&m), &[vec![0.0, 0.0], vec![0.0, 0.0]]);
+
+        let options = Options { mode: Mode::NoRemap, ..Options::default() };
+        let m = Matrix::from_maps(&map(&STEREO), &map(&aux), &options);
+        assert!(m.is_identity());
+    }
+
+    #[test]
+    fn mono_without_fill() {
+        let quad = [FrontLeft, FrontRight, RearLeft, RearRight];
+        let options = Options { fill_outputs: false, ..Options::default() };
+        let m = Matrix::from_maps(&map(&[Mono]), &map(&quad), &options);
+        assert_close(&rows(&m), &[vec![1.0], vec![1.0], vec![0.0], vec![0.0]]);
+    }
+
+    #[test]
+    fn remix_data() {
+        let mut remixer = Remixer::new(&map(&STEREO), &map(&[Mono]), Format::S16le,
+            &Options::default()).unwrap();
+        let src: Vec<u8> = [1000i16, 3000, -200, -400].iter()
+            .flat_map(|v| v.to_le_bytes().to_vec())
+            .collect();
+        let out = remixer.remix_to_vec(&src).unwrap();
+        assert_eq!(out, [2000i16, -300].iter().flat_map(|v| v.to_le_bytes().to_vec())
+            .collect::<Vec<u8>>());
+
+        remixer.get_matrix_mut().set(0, 1, 0.0);
+        let out = remixer.remix_to_vec(&src).unwrap();
+        assert_eq!(out, [500i16, -100].iter().flat_map(|v| v.to_le_bytes().to_vec())
+            .collect::<Vec<u8>>());
+
+        // Multiple blocks
+        let src = vec![0x40u8; BLOCK_FRAMES * 3 + 1];
+        let mut remixer = Remixer::new(&map(&[Mono]), &map(&STEREO), Format::U8,
+            &Options::default()).unwrap();
+        let out = remixer.remix_to_vec(&src).unwrap();
+        assert_eq!(out, vec![0x40u8; (BLOCK_FRAMES * 3 + 1) * 2]);
+
+        assert!(remixer.remix(&[0u8; 2], &mut [0u8; 3]).is_err());
+        assert!(Remixer::new(&map(&[]), &map(&STEREO), Format::U8, &Options::default()).is_err());
+    }
+}
@@ -0,0 +1,342 @@
+// Copyright 2020 Lyndon Brown
+//
+// This file is part of the PulseAudio Rust language binding.
+//
+// Licensed under the MIT license or the Apache license (version 2.0), at your option. You may not
+// copy, modify, or distribute this file except in compliance with said license. You can find copies
+// of these licenses either in the LICENSE-MIT and LICENSE-APACHE files, or alternatively at
+// <http://opensource.org/licenses/MIT> and <http://www.apache.org/licenses/LICENSE-2.0>
+// respectively.
+//
+// Portions of documentation are copied from the LGPL 2.1+ licensed PulseAudio C headers on a
+// fair-use basis, as discussed in the overall project readme (available in the git repository).
+
+//! Card profile and port selection.
+//!
+//! # Overview
+//!
+//! The ports of a card (e.g. speakers, headphones, microphone) are each exposed by some of the
+//! card’s profiles, and a port can only be used on a sink or source while a profile exposing it is
+//! active. The helpers here rank profiles and ports by availability and priority, resolve the
+//! profile to use for a given port, and switch a card over to a port.
+//!
+//! Ranking puts available ports (`PortAvailable::Yes`) first, then those whose availability is
+//! unknown (i.e. without jack detection), then unavailable ones, with ties broken by priority,
+//! highest first. Profiles are likewise ranked available first, then by priority; profile
+//! availability is only known with PA version 5 or newer, before which all profiles are considered
+//! available.
+//!
+//! When resolving the profile for a port, the card’s active profile is kept if it exposes the port,
+//! since switching profile interrupts streams on the card. Otherwise the best ranked available
+//! profile exposing the port is used.
+//!
+//! A [`Switcher`] applies a [`Selection`], switching the card profile first and then the port of
+//! the sink or source that the new profile created for it.
+//!
+//! # Example
+//!
+//! Switching a card to the best profile that has an available headphone output:
+//!
+//! ```rust,ignore
+//! use pulse::context::card::Switcher;
+//! use pulse::direction;
+//!
+//! let mut switcher = Switcher::new(&context);
+//! switcher.switch_to_best("alsa_card.pci-0000_00_1f.3", direction::flags::OUTPUT,
+//!     |port| port.name.as_ref().map_or(false, |name| name.contains("headphones")),
+//!     Some(Box::new(|result| {
+//!         match result {
+//!             Ok(selection) => println!("switched to {}", selection.port),
+//!             Err(e) => println!("failed: {}", e),
+//!         }
+//!     })));
+//! ```
+//!
+//! [`Switcher`]: struct.Switcher.html
+//! [`Selection`]: struct.Selection.html
+
+use std::rc::Rc;
+use std::cell::RefCell;
+use crate::callbacks::ListResult;
+use crate::def::PortAvailable;
+use crate::direction;
+use crate::error::{Code, PAErr};
+use super::Context;
+use super::introspect::{CardInfo, CardPortInfo, Introspector};
+
+#[cfg(all(not(feature = "pa_v5"), not(feature = "dox")))]
+use super::introspect::CardProfileInfo as ProfileInfo;
+#[cfg(any(feature = "pa_v5", feature = "dox"))]
+use super::introspect::CardProfileInfo2 as ProfileInfo;
+
+/// A port of a card to switch to, along with the profile to use for it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selection {
+    /// Index of the card.
+    pub card_index: u32,
+    /// Name of the card.
+    pub card: String,
+    /// Name of the profile.
+    pub profile: String,
+    /// Name of the port.
+    pub port: String,
+    /// Direction of the port.
+    pub direction: direction::FlagSet,
+}
+
+/// Callback type for switching results.
+type SwitchCallback = Box<dyn FnMut(Result<Selection, PAErr>) + 'static>;
+
+/// Ranks port availability, higher being better.
+fn availability_rank(available: PortAvailable) -> u8 {
+    match available {
+        PortAvailable::Yes => 2,
+        PortAvailable::Unknown => 1,
+        PortAvailable::No => 0,
+    }
+}
+
+/// Sorts items by the given key (an availability rank and priority), best first. Ties keep their
+/// original order.
+fn rank<T, F>(mut items: Vec<T>, key: F) -> Vec<T>
+    where F: Fn(&T) -> (u8, u32)
+{
+    items.sort_by(|a, b| key(b).cmp(&key(a)));
+    items
+}
+
+/// Checks whether a profile is available.
+///
+/// Profile availability is only reported with PA version 5 or newer, thus this always returns
+/// `true` with older versions.
+#[cfg(any(feature = "pa_v5", feature = "dox"))]
+#[inline]
+pub fn profile_is_available(profile: &ProfileInfo) -> bool {
+    profile.available
+}
+
+/// Checks whether a profile is available.
+///
+/// Profile availability is only reported with PA version 5 or newer, thus this always returns
+/// `true` with older versions.
+#[cfg(all(not(feature = "pa_v5"), not(feature = "dox")))]
+#[inline]
+pub fn profile_is_available(_profile: &ProfileInfo) -> bool {
+    true
+}
+
+/// Ranks profiles (e.g. those of a card, or of a port), best first.
+pub fn rank_profiles<'a, 'b>(profiles: &'b [ProfileInfo<'a>]) -> Vec<&'b ProfileInfo<'a>> {
+    rank(profiles.iter().collect(), |p| (profile_is_available(p) as u8, p.priority))
+}
+
+/// Ranks the ports of a card in the given direction, best first.
+///
+/// Ports of other directions are left out. Unavailable ports are included, last.
+pub fn rank_ports<'a, 'b>(ports: &'b [CardPortInfo<'a>], direction: direction::FlagSet)
+    -> Vec<&'b CardPortInfo<'a>>
+{
+    let ports = ports.iter().filter(|p| p.direction & direction != 0).collect();
+    rank(ports, |p| (availability_rank(p.available), p.priority))
+}
+
+/// Gets the profile to use for the named port of a card.
+///
+/// This is the active profile if it exposes the port, otherwise the best ranked available profile
+/// exposing it. Returns `None` if the card has no such port, or no available profile exposes it.
+pub fn profile_for_port<'a, 'b>(card: &'b CardInfo<'a>, port: &str)
+    -> Option<&'b ProfileInfo<'a>>
+{
+    let port = card.ports.iter().find(|p| p.name.as_ref().map_or(false, |n| n == port))?;
+    let active = card.active_profile.as_ref().and_then(|active| active.name.as_ref());
+    let usable = |p: &&ProfileInfo| p.name.is_some() && profile_is_available(p);
+    port.profiles.iter().filter(usable).find(|p| active.is_some() && p.name.as_ref() == active)
+        .or_else(|| rank_profiles(&port.profiles).into_iter().find(usable))
+}
+
+/// Selects the best port of a card in the given direction that the predicate accepts, along with
+/// the profile to use for it.
+///
+/// Unavailable ports are never selected. Returns `None` if no suitable port exists.
+pub fn select<'a, F>(card: &CardInfo<'a>, direction: direction::FlagSet, predicate: F)
+    -> Option<Selection>
+    where F: Fn(&CardPortInfo<'a>) -> bool
+{
+    let card_name = card.name.as_ref()?;
+    rank_ports(&card.ports, direction).into_iter()
+        .filter(|p| p.available != PortAvailable::No && predicate(p))
+        .filter_map(|port| {
+            let port_name = port.name.as_ref()?;
+            let profile = profile_for_port(card, port_name)?;
+            Some(Selection {
+                card_index: card.index,
+                card: card_name.to_string(),
+                profile: profile.name.as_ref()?.to_string(),
+                port: port_name.to_string(),
+                direction: port.direction,
+            })
+        })
+        .next()
+}
+
+/// Switches cards to selected ports.
+pub struct Switcher {
+    introspector: Rc<RefCell<Introspector>>,
+}
+
+impl Switcher {
+    /// Creates a new switcher.
+    pub fn new(context: &Context) -> Self {
+        Self { introspector: Rc::new(RefCell::new(context.introspect())) }
+    }
+
+    /// Switches the named card to the best port in the given direction that the predicate
+    /// accepts, as chosen by [`select`](fn.select.html).
+    ///
+    /// The callback, if given, is provided with the selection applied, or an error of
+    /// `Code::NoEntity` if the card does not exist, or `Code::NotSupported` if it has no suitable
+    /// port. Otherwise errors are as for [`switch`](#method.switch).
+    pub fn switch_to_best<F>(&mut self, card: &str, direction: direction::FlagSet, predicate: F,
+        mut callback: Option<SwitchCallback>)
+        where F: Fn(&CardPortInfo) -> bool + 'static
+    {
+        let introspector = Rc::clone(&self.introspector);
+        self.introspector.borrow().get_card_info_by_name(card, move |result| {
+            match result {
+                ListResult::Item(info) => {
+                    match select(info, direction, &predicate) {
+                        Some(selection) => switch(&introspector, selection, callback.take()),
+                        None => report(&mut callback, Err(PAErr::from(Code::NotSupported))),
+                    }
+                },
+                ListResult::End => {},
+                ListResult::Error => report(&mut callback, Err(PAErr::from(Code::NoEntity))),
+            }
+        });
+    }
+
+    /// Switches a card to the selected port, activating the selected profile, and then setting the
+    /// port of the sink or source of the card that has it.
+    ///
+    /// The callback, if given, is provided with the selection on success, or an error of
+    /// `Code::Invalid` if the server refused to switch profile or port, or `Code::NoEntity` if no
+    /// sink or source of the card has the port.
+    pub fn switch(&mut self, selection: &Selection, callback: Option<SwitchCallback>) {
+        switch(&self.introspector, selection.clone(), callback);
+    }
+}
+
+/// Provides a result to a callback, if there is one.
+fn report(callback: &mut Option<SwitchCallback>, result: Result<Selection, PAErr>) {
+    if let Some(callback) = callback.as_mut() {
+        callback(result);
+    }
+}
+
+fn switch(introspector: &Rc<RefCell<Introspector>>, selection: Selection,
+    mut callback: Option<SwitchCallback>)
+{
+    let next = Rc::clone(introspector);
+    let (card, profile) = (selection.card.clone(), selection.profile.clone());
+    introspector.borrow_mut().set_card_profile_by_name(&card, &profile,
+        Some(Box::new(move |success| {
+            match success {
+                true => set_port(&next, selection.clone(), callback.take()),
+                false => report(&mut callback, Err(PAErr::from(Code::Invalid))),
+            }
+        })));
+}
+
+/// Sets the selected port on the sink or source of the card that has it.
+fn set_port(introspector: &Rc<RefCell<Introspector>>, selection: Selection,
+    mut callback: Option<SwitchCallback>)
+{
+    let is_output = selection.direction & direction::flags::OUTPUT != 0;
+    let next = Rc::clone(introspector);
+    let card_index = selection.card_index;
+    let port = selection.port.clone();
+    let mut device = None;
+
+    // Given the name of each device of the card that has the port
+    let mut handle = move |result: ListResult<Option<String>>| {
+        match result {
+            ListResult::Item(name) => {
+                if device.is_none() {
+                    device = name;
+                }
+            },
+            ListResult::End => match device.take() {
+                Some(name) => {
+                    let port = selection.port.clone();
+                    let selection = selection.clone();
+                    let mut callback = callback.take();
+                    let on_done = Box::new(move |success| {
+                        let result = match success {
+                            true => Ok(selection.clone()),
+                            false => Err(PAErr::from(Code::Invalid)),
+                        };
+                        report(&mut callback, result);
+                    });
+                    let mut introspector = next.borrow_mut();
+                    match is_output {
+                        true => {
+                            introspector.set_sink_port_by_name(&name, &port,
+                                Some(on_done));
+                        },
+                        false => {
+                            introspector.set_source_port_by_name(&name, &port,
+                                Some(on_done));
+                        },
+                    }
+                },
+                None => report(&mut callback, Err(PAErr::from(Code::NoEntity))),
+            },
+            ListResult::Error => report(&mut callback, Err(PAErr::from(Code::NoEntity))),
+        }
+    };
+
+    let has_port = move |card: Option<u32>, name: Option<&str>, mut ports: Vec<Option<&str>>| {
+        match card == Some(card_index) && ports.drain(..).any(|p| p == Some(port.as_str())) {
+            true => name.map(|n| n.to_string()),
+            false => None,
+        }
+    };
+
+    let introspector = introspector.borrow();
+    match is_output {
+        true => {
+            introspector.get_sink_info_list(move |result| handle(match result {
+                ListResult::Item(info) => ListResult::Item(has_port(info.card,
+                    info.name.as_ref().map(|n| n.as_ref()),
+                    info.ports.iter().map(|p| p.name.as_ref().map(|n| n.as_ref())).collect())),
+                ListResult::End => ListResult::End,
+                ListResult::Error => ListResult::Error,
+            }));
+        },
+        false => {
+            introspector.get_source_info_list(move |result| handle(match result {
+                ListResult::Item(info) => ListResult::Item(has_port(info.card,
+                    info.name.as_ref().map(|n| n.as_ref()),
+                    info.ports.iter().map(|p| p.name.as_ref().map(|n| n.as_ref())).collect())),
+                ListResult::End => ListResult::End,
+                ListResult::Error => ListResult::Error,
+            }));
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranking() {
+        assert!(availability_rank(PortAvailable::Yes) > availability_rank(PortAvailable::Unknown));
+        assert!(availability_rank(PortAvailable::Unknown) > availability_rank(PortAvailable::No));
+
+        let items = vec![("a", 0, 100), ("b", 2, 10), ("c", 1, 1000), ("d", 2, 50), ("e", 2, 10)];
+        let ranked = rank(items, |&(_, available, priority)| (available, priority));
+        let names: Vec<&str> = ranked.iter().map(|&(name, _, _)| name).collect();
+        assert_eq!(names, ["d", "b", "e", "c", "a"]);
+    }
+}
@@ -0,0 +1,502 @@
+// Copyright 2020 Lyndon Brown
+//
+// This file is part of the PulseAudio Rust language binding.
+//
+// Licensed under the MIT license or the Apache license (version 2.0), at your option. You may not
+// copy, modify, or distribute this file except in compliance with said license. You can find copies
+// of these licenses either in the LICENSE-MIT and LICENSE-APACHE files, or alternatively at
+// <http://opensource.org/licenses/MIT> and <http://www.apache.org/licenses/LICENSE-2.0>
+// respectively.
+//
+// Portions of documentation are copied from the LGPL 2.1+ licensed PulseAudio C headers on a
+// fair-use basis, as discussed in the overall project readme (available in the git repository).
+
+//! Client-side ducking policy for communication streams.
+//!
+//! # Overview
+//!
+//! A [`Ducker`] turns other audio down while a communication stream (e.g. a phone or VoIP call) is
+//! playing, without needing any server modules to be loaded.
+//!
+//! It watches sink inputs, identifying communication streams by their `media.role` property (by
+//! default, `phone` or `communication`). Whenever a sink has at least one such stream, all other
+//! sink inputs on that sink are attenuated by a configurable amount, using smooth volume ramps.
+//! Once the last communication stream on the sink goes away (or moves elsewhere), the previous
+//! volumes of the attenuated streams are restored.
+//!
+//! Dropping the [`Ducker`] immediately restores any attenuated streams.
+//!
+//! Note that if the user changes the volume of a stream while it is attenuated, that change will be
+//! lost when the previous volume is restored.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use pulse::context::ducking::{Ducker, Config};
+//! use pulse::context::subscribe::Dispatcher;
+//!
+//! let dispatcher = Dispatcher::new(&mut context);
+//! let ducker = Ducker::new(&mainloop, &context, &dispatcher, Config::default()).unwrap();
+//! ```
+//!
+//! [`Ducker`]: struct.Ducker.html
+
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use crate::callbacks::ListResult;
+use crate::mainloop::api::{Mainloop, MainloopInnerType, MainloopRef};
+use crate::proplist::properties;
+use crate::time::{MicroSeconds, MICROS_PER_MILLI};
+use crate::volume::{ChannelVolumes, Volume, VolumeDB, sw_from_db, sw_from_linear, sw_to_linear};
+use crate::volume::ramp::{Curve, VolumeRamp};
+use crate::error::{Code, PAErr};
+use super::{Context, State};
+use super::introspect::{Introspector, SinkInputInfo};
+use super::subscribe::{subscription_masks, Dispatcher, Facility, Operation, Subscription};
+
+/// Default attenuation applied to other streams.
+pub const DEFAULT_ATTENUATION: VolumeDB = VolumeDB(-20.0);
+
+/// Default duration of volume ramps.
+pub const DEFAULT_RAMP_DURATION: MicroSeconds = MicroSeconds(300 * MICROS_PER_MILLI);
+
+/// Ducking configuration.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Values of the `media.role` property that identify communication streams.
+    pub roles: Vec<String>,
+    /// Attenuation applied to other streams on the same sink. This should be negative.
+    pub attenuation: VolumeDB,
+    /// Duration of the ramps used when attenuating and restoring.
+    pub ramp_duration: MicroSeconds,
+    /// Curve of the ramps used when attenuating and restoring.
+    pub curve: Curve,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            roles: vec!["phone".to_string(), "communication".to_string()],
+            attenuation: DEFAULT_ATTENUATION,
+            ramp_duration: DEFAULT_RAMP_DURATION,
+            curve: Curve::Decibel,
+        }
+    }
+}
+
+/// An active ducking policy.
+///
+/// The policy is in effect for as long as this object is kept alive.
+pub struct Ducker<T>
+    where T: MainloopInnerType + 'static
+{
+    inner: Rc<RefCell<Inner<T>>>,
+    /// Registration with the dispatcher, if subscribed.
+    subscription: Option<Subscription>,
+}
+
+struct Inner<T>
+    where T: MainloopInnerType + 'static
+{
+    config: Config,
+    mainloop: MainloopRef<T>,
+    introspector: Rc<RefCell<Introspector>>,
+    inputs: HashMap<u32, Input>,
+    /// Ramps in progress, by sink input index.
+    ramps: HashMap<u32, VolumeRamp<T>>,
+}
+
+/// What we know about a sink input.
+struct Input {
+    sink: u32,
+    communication: bool,
+    writable: bool,
+    /// Last known volume.
+    volume: ChannelVolumes,
+    /// Volume prior to attenuation, if attenuated.
+    saved: Option<ChannelVolumes>,
+}
+
+impl<T> Ducker<T>
+    where T: MainloopInnerType + 'static
+{
+    /// Creates a new ducking policy, and puts it into effect.
+    ///
+    /// This registers with the dispatcher for [`subscription_masks::SINK_INPUT`] events, until
+    /// dropped. If you need to manage subscriptions yourself, use [`new_unsubscribed`] instead.
+    ///
+    /// Returns an error with code `Code::BadState` if the context is not ready.
+    ///
+    /// [`subscription_masks::SINK_INPUT`]: ../subscribe/subscription_masks/constant.SINK_INPUT.html
+    /// [`new_unsubscribed`]: #method.new_unsubscribed
+    pub fn new<M>(mainloop: &M, context: &Context, dispatcher: &Dispatcher, config: Config)
+        -> Result<Self, PAErr>
+        where M: Mainloop<MI=T> + ?Sized
+    {
+        if context.get_state() != State::Ready {
+            return Err(PAErr::from(Code::BadState));
+        }
+        let mut ducker = Self::new_unsubscribed(mainloop, context, config);
+
+        let weak = Rc::downgrade(&ducker.inner);
+        ducker.subscription = Some(dispatcher.register(subscription_masks::SINK_INPUT,
+            Box::new(move |facility, operation, index| {
+                if let Some(inner) = weak.upgrade() {
+                    Inner::handle_event(&inner, facility, operation, index);
+                }
+            }))?);
+
+        Ok(ducker)
+    }
+
+    /// Creates a new ducking policy, without subscribing to sink input changes.
+    ///
+    /// You must pass sink input events to [`handle_event`] yourself.
+    ///
+    /// Panics if the context is not ready, the sink inputs being requested immediately.
+    ///
+    /// [`handle_event`]: #method.handle_event
+    pub fn new_unsubscribed<M>(mainloop: &M, context: &Context, config: Config) -> Self
+        where M: Mainloop<MI=T> + ?Sized
+    {
+        let inner = Rc::new(RefCell::new(Inner {
+            config: config,
+            mainloop: MainloopRef(mainloop.inner()),
+            introspector: Rc::new(RefCell::new(context.introspect())),
+            inputs: HashMap::new(),
+            ramps: HashMap::new(),
+        }));
+        Inner::refresh_all(&inner);
+        Self { inner: inner, subscription: None }
+    }
+
+    /// Handles a subscription event.
+    ///
+    /// Only sink input events are of interest; others are ignored.
+    pub fn handle_event(&self, facility: Option<Facility>, operation: Option<Operation>,
+        index: u32)
+    {
+        Inner::handle_event(&self.inner, facility, operation, index);
+    }
+
+    /// Checks whether streams on the given sink are currently being attenuated.
+    pub fn is_ducking(&self, sink: u32) -> bool {
+        self.inner.borrow().inputs.values().any(|i| i.sink == sink && i.saved.is_some())
+    }
+}
+
+impl<T> Drop for Ducker<T>
+    where T: MainloopInnerType + 'static
+{
+    fn drop(&mut self) {
+        self.inner.borrow_mut().restore_all();
+    }
+}
+
+impl<T> Inner<T>
+    where T: MainloopInnerType + 'static
+{
+    fn handle_event(this: &Rc<RefCell<Self>>, facility: Option<Facility>,
+        operation: Option<Operation>, index: u32)
+    {
+        if facility != Some(Facility::SinkInput) {
+            return;
+        }
+        match operation {
+            Some(Operation::New) | Some(Operation::Changed) => Self::refresh(this, index),
+            Some(Operation::Removed) => this.borrow_mut().remove(index),
+            None => {},
+        }
+    }
+
+    /// Requests information about all sink inputs.
+    fn refresh_all(this: &Rc<RefCell<Self>>) {
+        let weak = Rc::downgrade(this);
+        let introspector = Rc::clone(&this.borrow().introspector);
+        let _ = introspector.borrow().get_sink_input_info_list(move |result| {
+            if let ListResult::Item(info) = result {
+                if let Some(inner) = weak.upgrade() {
+                    inner.borrow_mut().update(info);
+                }
+            }
+        });
+    }
+
+    /// Requests information about a sink input.
+    fn refresh(this: &Rc<RefCell<Self>>, index: u32) {
+        let weak = Rc::downgrade(this);
+        let introspector = Rc::clone(&this.borrow().introspector);
+        let _ = introspector.borrow().get_sink_input_info(index, move |result| {
+            if let ListResult::Item(info) = result {
+                if let Some(inner) = weak.upgrade() {
+                    inner.borrow_mut().update(info);
+                }
+            }
+        });
+    }
+
+    fn update(&mut self, info: &SinkInputInfo) {
+        let role = info.proplist.get_str(properties::MEDIA_ROLE);
+        let communication = is_communication(&self.config, role.as_ref().map(|r| r.as_str()));
+        let writable = info.has_volume && info.volume_writable;
+
+        let previous_sink = match self.inputs.get_mut(&info.index) {
+            Some(input) => {
+                let previous_sink = input.sink;
+                input.sink = info.sink;
+                input.communication = communication;
+                input.writable = writable;
+                input.volume = info.volume;
+                Some(previous_sink)
+            },
+            None => {
+                self.inputs.insert(info.index, Input {
+                    sink: info.sink,
+                    communication: communication,
+                    writable: writable,
+                    volume: info.volume,
+                    saved: None,
+                });
+                None
+            },
+        };
+
+        if let Some(previous_sink) = previous_sink {
+            if previous_sink != info.sink {
+                self.evaluate(previous_sink);
+            }
+        }
+        self.evaluate(info.sink);
+    }
+
+    fn remove(&mut self, index: u32) {
+        self.ramps.remove(&index);
+        if let Some(input) = self.inputs.remove(&index) {
+            if input.communication {
+                self.evaluate(input.sink);
+            }
+        }
+    }
+
+    /// Attenuates or restores the streams of a sink, as appropriate.
+    fn evaluate(&mut self, sink: u32) {
+        let factor = sw_from_db(self.config.attenuation.0);
+        for (index, to) in plan(&mut self.inputs, sink, factor) {
+            let from = self.ramps.get(&index).and_then(|r| r.get_current())
+                .unwrap_or(self.inputs[&index].volume);
+            match start_ramp(&mut self.mainloop, &self.introspector, &self.config, index, &from,
+                &to)
+            {
+                Some(ramp) => { self.ramps.insert(index, ramp); },
+                None => { self.ramps.remove(&index); },
+            }
+        }
+    }
+
+    /// Immediately restores all attenuated streams.
+    fn restore_all(&mut self) {
+        self.ramps.clear();
+        let mut introspector = self.introspector.borrow_mut();
+        for (index, saved) in take_saved(&mut self.inputs) {
+            let _ = introspector.set_sink_input_volume(index, &saved, None);
+        }
+    }
+}
+
+/// Checks whether a stream with the given media role is a communication stream.
+fn is_communication(config: &Config, role: Option<&str>) -> bool {
+    match role {
+        Some(role) => config.roles.iter().any(|r| r == role),
+        None => false,
+    }
+}
+
+/// Applies an attenuation factor to a volume.
+fn attenuate(volume: &ChannelVolumes, factor: Volume) -> ChannelVolumes {
+    let factor = sw_to_linear(factor);
+    let mut attenuated = *volume;
+    for v in attenuated.get_mut() {
+        *v = sw_from_linear(sw_to_linear(*v) * factor);
+    }
+    attenuated
+}
+
+/// Attenuates or restores the streams of a sink, as appropriate, recording the volumes saved.
+///
+/// Returns the volumes to ramp the affected streams to, by index.
+fn plan(inputs: &mut HashMap<u32, Input>, sink: u32, factor: Volume)
+    -> Vec<(u32, ChannelVolumes)>
+{
+    let active = inputs.values().any(|i| i.sink == sink && i.communication);
+    let mut changes = Vec::new();
+    for (index, input) in inputs.iter_mut() {
+        if input.sink != sink {
+            continue;
+        }
+        // Streams may have become communication streams, or lost their volume, since attenuated
+        let eligible = !input.communication && input.writable;
+        match (active && eligible, input.saved) {
+            (true, None) => {
+                input.saved = Some(input.volume);
+                changes.push((*index, attenuate(&input.volume, factor)));
+            },
+            (false, Some(saved)) => {
+                input.saved = None;
+                if input.writable {
+                    changes.push((*index, saved));
+                }
+            },
+            _ => {},
+        }
+    }
+    changes
+}
+
+/// Forgets the volumes saved for all attenuated streams, returning them by index.
+fn take_saved(inputs: &mut HashMap<u32, Input>) -> Vec<(u32, ChannelVolumes)> {
+    inputs.iter_mut()
+        .filter_map(|(index, input)| input.saved.take().map(|saved| (*index, saved)))
+        .collect()
+}
+
+fn start_ramp<T>(mainloop: &mut MainloopRef<T>, introspector: &Rc<RefCell<Introspector>>,
+    config: &Config, index: u32, from: &ChannelVolumes, to: &ChannelVolumes)
+    -> Option<VolumeRamp<T>>
+    where T: MainloopInnerType + 'static
+{
+    let introspector = Rc::clone(introspector);
+    let setter = Box::new(move |volume: &ChannelVolumes| {
+        introspector.borrow_mut().set_sink_input_volume(index, volume, None)
+    });
+    VolumeRamp::new_with_setter(mainloop, setter, from, to, config.ramp_duration, config.curve)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::volumes;
+    use crate::volume::{VOLUME_NORM, sw_to_db};
+
+    fn input(sink: u32, role: Option<&str>, writable: bool, volume: Volume) -> Input {
+        Input {
+            sink: sink,
+            communication: is_communication(&Config::default(), role),
+            writable: writable,
+            volume: volumes(&[volume, volume]),
+            saved: None,
+        }
+    }
+
+    /// Gives changes in index order, as plain channel volumes (comparing `ChannelVolumes` goes
+    /// through the C API).
+    fn levels(changes: Vec<(u32, ChannelVolumes)>) -> Vec<(u32, Vec<Volume>)> {
+        let mut changes: Vec<(u32, Vec<Volume>)> = changes.into_iter()
+            .map(|(index, volume)| (index, volume.get().to_vec()))
+            .collect();
+        changes.sort_by_key(|change| change.0);
+        changes
+    }
+
+    fn saved(inputs: &HashMap<u32, Input>, index: u32) -> Option<Vec<Volume>> {
+        inputs[&index].saved.map(|saved| saved.get().to_vec())
+    }
+
+    #[test]
+    fn role_matching() {
+        let config = Config::default();
+        assert!(is_communication(&config, Some("phone")));
+        assert!(is_communication(&config, Some("communication")));
+        assert!(!is_communication(&config, Some("music")));
+        assert!(!is_communication(&config, Some("Phone")));
+        assert!(!is_communication(&config, None));
+
+        let config = Config { roles: vec!["voip".to_string()], ..Config::default() };
+        assert!(is_communication(&config, Some("voip")));
+        assert!(!is_communication(&config, Some("phone")));
+    }
+
+    #[test]
+    fn attenuation() {
+        let factor = sw_from_db(DEFAULT_ATTENUATION.0);
+        let quiet = sw_from_db(-10.0);
+        let attenuated = attenuate(&volumes(&[VOLUME_NORM, quiet]), factor);
+        assert_eq!(attenuated.len(), 2);
+        assert_eq!(attenuated.get()[0], factor);
+        assert!((sw_to_db(attenuated.get()[1]) - -30.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn ducking() {
+        let factor = sw_from_db(DEFAULT_ATTENUATION.0);
+        let half = Volume(VOLUME_NORM.0 / 2);
+        let mut inputs = HashMap::new();
+        inputs.insert(1, input(0, Some("music"), true, VOLUME_NORM));
+        inputs.insert(2, input(0, None, true, half));
+        inputs.insert(3, input(0, Some("event"), false, VOLUME_NORM));
+        inputs.insert(4, input(1, Some("music"), true, VOLUME_NORM));
+
+        // No communication stream, nothing to do
+        assert!(plan(&mut inputs, 0, factor).is_empty());
+
+        // Only the other writable streams of the same sink are attenuated
+        inputs.insert(5, input(0, Some("phone"), true, VOLUME_NORM));
+        let attenuated_half = attenuate(&volumes(&[half]), factor).get()[0];
+        assert_eq!(levels(plan(&mut inputs, 0, factor)), vec![
+            (1, vec![factor, factor]),
+            (2, vec![attenuated_half, attenuated_half]),
+        ]);
+        assert_eq!(saved(&inputs, 1), Some(vec![VOLUME_NORM, VOLUME_NORM]));
+        assert_eq!(saved(&inputs, 2), Some(vec![half, half]));
+        assert_eq!(saved(&inputs, 3), None);
+        assert_eq!(saved(&inputs, 4), None);
+        assert_eq!(saved(&inputs, 5), None);
+
+        // Already attenuated streams are left alone
+        assert!(plan(&mut inputs, 0, factor).is_empty());
+
+        // Restored once the communication stream goes
+        inputs.remove(&5);
+        assert_eq!(levels(plan(&mut inputs, 0, factor)), vec![
+            (1, vec![VOLUME_NORM, VOLUME_NORM]),
+            (2, vec![half, half]),
+        ]);
+        assert!(inputs.values().all(|i| i.saved.is_none()));
+    }
+
+    #[test]
+    fn reclassification() {
+        let factor = sw_from_db(DEFAULT_ATTENUATION.0);
+        let mut inputs = HashMap::new();
+        inputs.insert(1, input(0, Some("music"), true, VOLUME_NORM));
+        inputs.insert(2, input(0, Some("music"), true, VOLUME_NORM));
+        inputs.insert(3, input(0, Some("phone"), true, VOLUME_NORM));
+        assert_eq!(plan(&mut inputs, 0, factor).len(), 2);
+
+        // A stream turning into a communication stream is restored, the other staying attenuated
+        inputs.get_mut(&1).unwrap().communication = true;
+        assert_eq!(levels(plan(&mut inputs, 0, factor)), vec![(1, vec![VOLUME_NORM, VOLUME_NORM])]);
+        assert_eq!(saved(&inputs, 1), None);
+        assert_eq!(saved(&inputs, 2), Some(vec![VOLUME_NORM, VOLUME_NORM]));
+
+        // A stream losing its volume is forgotten, there being nothing to restore
+        inputs.get_mut(&2).unwrap().writable = false;
+        assert!(plan(&mut inputs, 0, factor).is_empty());
+        assert_eq!(saved(&inputs, 2), None);
+    }
+
+    #[test]
+    fn restore_on_drop() {
+        let factor = sw_from_db(DEFAULT_ATTENUATION.0);
+        let mut inputs = HashMap::new();
+        inputs.insert(1, input(0, Some("music"), true, VOLUME_NORM));
+        inputs.insert(2, input(0, Some("phone"), true, VOLUME_NORM));
+        inputs.insert(3, input(1, Some("music"), true, VOLUME_NORM));
+        assert_eq!(plan(&mut inputs, 0, factor).len(), 1);
+
+        // Only attenuated streams get restored, to their saved volumes
+        assert_eq!(levels(take_saved(&mut inputs)), vec![(1, vec![VOLUME_NORM, VOLUME_NORM])]);
+        assert!(inputs.values().all(|i| i.saved.is_none()));
+        assert!(take_saved(&mut inputs).is_empty());
+    }
+}
@@ -273,7 +273,7 @@ impl Introspector {
     /// Creates a new `Introspector` from an existing
     /// [`ContextInternal`](../struct.ContextInternal.html) pointer.
     #[inline(always)]
-    fn from_raw(context: *mut ContextInternal) -> Self {
+    pub(crate) fn from_raw(context: *mut ContextInternal) -> Self {
         Self { context: context }
     }
 }
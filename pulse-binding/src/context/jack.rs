@@ -0,0 +1,338 @@
+// Copyright 2020 Lyndon Brown
+//
+// This file is part of the PulseAudio Rust language binding.
+//
+// Licensed under the MIT license or the Apache license (version 2.0), at your option. You may not
+// copy, modify, or distribute this file except in compliance with said license. You can find copies
+// of these licenses either in the LICENSE-MIT and LICENSE-APACHE files, or alternatively at
+// <http://opensource.org/licenses/MIT> and <http://www.apache.org/licenses/LICENSE-2.0>
+// respectively.
+//
+// Portions of documentation are copied from the LGPL 2.1+ licensed PulseAudio C headers on a
+// fair-use basis, as discussed in the overall project readme (available in the git repository).
+
+//! Jack detection events.
+//!
+//! # Overview
+//!
+//! A [`Watcher`] tracks the availability of the ports of all cards, and reports changes as
+//! [`Event`]s: a port becoming available (e.g. headphones or a microphone being plugged in) gives
+//! [`Event::PortPlugged`], and a port becoming unavailable gives [`Event::PortUnplugged`].
+//!
+//! Only ports supporting jack detection change availability. A port whose availability becomes
+//! known gives an event as for any other change, while a port becoming unknown gives none. The
+//! ports of sinks and sources mirror those of their card, thus are not watched separately.
+//!
+//! The availability of ports when the watcher is created, or when a card is added, is taken as the
+//! starting point, and does not produce events.
+//!
+//! Optionally, the watcher can switch to plugged in ports automatically, activating a profile
+//! exposing the port if necessary (see the [`card`] module).
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use pulse::context::jack::{Event, Watcher};
+//! use pulse::context::subscribe::Dispatcher;
+//!
+//! let dispatcher = Dispatcher::new(&mut context);
+//! let mut watcher = Watcher::new(&context, &dispatcher, Box::new(|event| {
+//!     match event {
+//!         Event::PortPlugged(port) => println!("plugged in: {}", port.name),
+//!         Event::PortUnplugged(port) => println!("unplugged: {}", port.name),
+//!     }
+//! })).unwrap();
+//! watcher.set_auto_switch(true);
+//! ```
+//!
+//! [`Watcher`]: struct.Watcher.html
+//! [`Event`]: enum.Event.html
+//! [`Event::PortPlugged`]: enum.Event.html#variant.PortPlugged
+//! [`Event::PortUnplugged`]: enum.Event.html#variant.PortUnplugged
+//! [`card`]: ../card/index.html
+
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use crate::callbacks::ListResult;
+use crate::def::PortAvailable;
+use crate::direction;
+use crate::proplist::properties;
+use crate::error::{Code, PAErr};
+use super::{Context, State};
+use super::card::{self, Selection, Switcher};
+use super::introspect::{CardInfo, CardPortInfo, Introspector};
+use super::subscribe::{subscription_masks, Dispatcher, Facility, Operation, Subscription};
+
+/// A port that changed availability.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Port {
+    /// Index of the card.
+    pub card_index: u32,
+    /// Name of the card.
+    pub card: String,
+    /// Name of the port.
+    pub name: String,
+    /// Description of the port.
+    pub description: Option<String>,
+    /// Direction of the port.
+    pub direction: direction::FlagSet,
+    /// Device class of the card, e.g. `sound` or `modem` (the `device.class` property).
+    pub device_class: Option<String>,
+    /// Form factor of the card, e.g. `internal`, `headset` or `speaker` (the `device.form_factor`
+    /// property).
+    pub form_factor: Option<String>,
+    /// Icon name of the port, or else of the card (the `device.icon_name` property).
+    pub icon_name: Option<String>,
+}
+
+/// A jack detection event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// A port became available.
+    PortPlugged(Port),
+    /// A port became unavailable.
+    PortUnplugged(Port),
+}
+
+impl Event {
+    /// Gets the port the event concerns.
+    pub fn get_port(&self) -> &Port {
+        match self {
+            Event::PortPlugged(port) => port,
+            Event::PortUnplugged(port) => port,
+        }
+    }
+}
+
+/// Callback type for events.
+type EventCallback = Box<dyn FnMut(&Event) + 'static>;
+
+/// Determines the change of a port between availability states, if any: `Some(true)` if plugged,
+/// `Some(false)` if unplugged.
+fn transition(previous: PortAvailable, current: PortAvailable) -> Option<bool> {
+    match (previous, current) {
+        (PortAvailable::Yes, PortAvailable::Yes) | (PortAvailable::No, PortAvailable::No) => None,
+        (_, PortAvailable::Yes) => Some(true),
+        (_, PortAvailable::No) => Some(false),
+        (_, PortAvailable::Unknown) => None,
+    }
+}
+
+/// A jack detection watcher.
+///
+/// Events are reported for as long as this object is kept alive.
+pub struct Watcher {
+    inner: Rc<RefCell<Inner>>,
+    /// Registration with the dispatcher, if subscribed.
+    subscription: Option<Subscription>,
+}
+
+struct Inner {
+    introspector: Rc<RefCell<Introspector>>,
+    switcher: Switcher,
+    auto_switch: bool,
+    /// Last known availability of ports, by card index and port name.
+    cards: HashMap<u32, HashMap<String, PortAvailable>>,
+    callback: Rc<RefCell<EventCallback>>,
+}
+
+impl Watcher {
+    /// Creates a new watcher, which gives events to the callback.
+    ///
+    /// This registers with the dispatcher for [`subscription_masks::MASK_CARD`] events, until
+    /// dropped. If you need to manage subscriptions yourself, use [`new_unsubscribed`] instead.
+    ///
+    /// Returns an error with code `Code::BadState` if the context is not ready.
+    ///
+    /// [`subscription_masks::MASK_CARD`]: ../subscribe/subscription_masks/constant.MASK_CARD.html
+    /// [`new_unsubscribed`]: #method.new_unsubscribed
+    pub fn new(context: &Context, dispatcher: &Dispatcher, callback: EventCallback)
+        -> Result<Self, PAErr>
+    {
+        if context.get_state() != State::Ready {
+            return Err(PAErr::from(Code::BadState));
+        }
+        let mut watcher = Self::new_unsubscribed(context, callback);
+
+        let weak = Rc::downgrade(&watcher.inner);
+        watcher.subscription = Some(dispatcher.register(subscription_masks::MASK_CARD,
+            Box::new(move |facility, operation, index| {
+                if let Some(inner) = weak.upgrade() {
+                    Inner::handle_event(&inner, facility, operation, index);
+                }
+            }))?);
+
+        Ok(watcher)
+    }
+
+    /// Creates a new watcher, which gives events to the callback, without subscribing to card
+    /// changes.
+    ///
+    /// You must pass card events to [`handle_event`] yourself.
+    ///
+    /// Panics if the context is not ready, the cards being requested immediately.
+    ///
+    /// [`handle_event`]: #method.handle_event
+    pub fn new_unsubscribed(context: &Context, callback: EventCallback) -> Self {
+        let inner = Rc::new(RefCell::new(Inner {
+            introspector: Rc::new(RefCell::new(context.introspect())),
+            switcher: Switcher::new(context),
+            auto_switch: false,
+            cards: HashMap::new(),
+            callback: Rc::new(RefCell::new(callback)),
+        }));
+        Inner::refresh_all(&inner);
+        Self { inner: inner, subscription: None }
+    }
+
+    /// Handles a subscription event.
+    ///
+    /// Only card events are of interest; others are ignored.
+    pub fn handle_event(&self, facility: Option<Facility>, operation: Option<Operation>,
+        index: u32)
+    {
+        Inner::handle_event(&self.inner, facility, operation, index);
+    }
+
+    /// Sets whether to switch to ports automatically when plugged in. This is off by default.
+    ///
+    /// The profile of the card is switched if the active profile does not expose the port, and the
+    /// port is then set on the sink or source of the card that has it.
+    pub fn set_auto_switch(&mut self, auto_switch: bool) {
+        self.inner.borrow_mut().auto_switch = auto_switch;
+    }
+
+    /// Checks whether ports are switched to automatically when plugged in.
+    pub fn get_auto_switch(&self) -> bool {
+        self.inner.borrow().auto_switch
+    }
+
+    /// Gets the last known availability of the named port of a card.
+    pub fn get_availability(&self, card: u32, port: &str) -> Option<PortAvailable> {
+        self.inner.borrow().cards.get(&card).and_then(|ports| ports.get(port)).cloned()
+    }
+}
+
+impl Inner {
+    fn handle_event(this: &Rc<RefCell<Self>>, facility: Option<Facility>,
+        operation: Option<Operation>, index: u32)
+    {
+        if facility != Some(Facility::Card) {
+            return;
+        }
+        match operation {
+            Some(Operation::New) | Some(Operation::Changed) => Self::refresh(this, index),
+            Some(Operation::Removed) => { this.borrow_mut().cards.remove(&index); },
+            None => {},
+        }
+    }
+
+    /// Requests information about all cards.
+    fn refresh_all(this: &Rc<RefCell<Self>>) {
+        let weak = Rc::downgrade(this);
+        let introspector = Rc::clone(&this.borrow().introspector);
+        let _ = introspector.borrow().get_card_info_list(move |result| {
+            if let ListResult::Item(info) = result {
+                if let Some(inner) = weak.upgrade() {
+                    Self::update(&inner, info);
+                }
+            }
+        });
+    }
+
+    /// Requests information about a card.
+    fn refresh(this: &Rc<RefCell<Self>>, index: u32) {
+        let weak = Rc::downgrade(this);
+        let introspector = Rc::clone(&this.borrow().introspector);
+        let _ = introspector.borrow().get_card_info_by_index(index, move |result| {
+            if let ListResult::Item(info) = result {
+                if let Some(inner) = weak.upgrade() {
+                    Self::update(&inner, info);
+                }
+            }
+        });
+    }
+
+    /// Records the availability of the ports of a card, reporting changes.
+    fn update(this: &Rc<RefCell<Self>>, info: &CardInfo) {
+        let (events, selections, callback) = {
+            let mut inner = this.borrow_mut();
+            let known = inner.cards.contains_key(&info.index);
+            let ports = inner.cards.entry(info.index).or_insert_with(HashMap::new);
+
+            let mut events = Vec::new();
+            for port in &info.ports {
+                let name = match &port.name {
+                    Some(name) => name.to_string(),
+                    None => continue,
+                };
+                let previous = ports.insert(name.clone(), port.available);
+                let plugged = match (known, previous) {
+                    (true, Some(previous)) => transition(previous, port.available),
+                    // New ports, or those of new cards, give no events
+                    _ => None,
+                };
+                if let Some(plugged) = plugged {
+                    let port = make_port(info, port, name);
+                    events.push(match plugged {
+                        true => Event::PortPlugged(port),
+                        false => Event::PortUnplugged(port),
+                    });
+                }
+            }
+
+            let selections: Vec<Selection> = match inner.auto_switch {
+                true => events.iter().filter_map(|event| match event {
+                    Event::PortPlugged(port) => card::select(info, port.direction,
+                        |p| p.name.as_ref().map_or(false, |n| *n == port.name)),
+                    Event::PortUnplugged(_) => None,
+                }).collect(),
+                false => Vec::new(),
+            };
+            (events, selections, Rc::clone(&inner.callback))
+        };
+
+        // Callbacks are made without borrowing, allowing them to use the watcher
+        for selection in &selections {
+            this.borrow_mut().switcher.switch(selection, None);
+        }
+        for event in &events {
+            (callback.borrow_mut())(event);
+        }
+    }
+}
+
+/// Describes a port of a card.
+fn make_port(card: &CardInfo, port: &CardPortInfo, name: String) -> Port {
+    let card_prop = |key: &str| card.proplist.get_str(key);
+    Port {
+        card_index: card.index,
+        card: card.name.as_ref().map(|n| n.to_string()).unwrap_or_default(),
+        name: name,
+        description: port.description.as_ref().map(|d| d.to_string()),
+        direction: port.direction,
+        device_class: card_prop(properties::DEVICE_CLASS),
+        form_factor: card_prop(properties::DEVICE_FORM_FACTOR),
+        icon_name: port.proplist.get_str(properties::DEVICE_ICON_NAME)
+            .or_else(|| card_prop(properties::DEVICE_ICON_NAME)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transitions() {
+        use PortAvailable::{Yes, No, Unknown};
+        assert_eq!(transition(No, Yes), Some(true));
+        assert_eq!(transition(Unknown, Yes), Some(true));
+        assert_eq!(transition(Yes, No), Some(false));
+        assert_eq!(transition(Unknown, No), Some(false));
+        assert_eq!(transition(Yes, Yes), None);
+        assert_eq!(transition(No, No), None);
+        assert_eq!(transition(Yes, Unknown), None);
+        assert_eq!(transition(Unknown, Unknown), None);
+    }
+}
@@ -0,0 +1,612 @@
+// Copyright 2020 Lyndon Brown
+//
+// This file is part of the PulseAudio Rust language binding.
+//
+// Licensed under the MIT license or the Apache license (version 2.0), at your option. You may not
+// copy, modify, or distribute this file except in compliance with said license. You can find copies
+// of these licenses either in the LICENSE-MIT and LICENSE-APACHE files, or alternatively at
+// <http://opensource.org/licenses/MIT> and <http://www.apache.org/licenses/LICENSE-2.0>
+// respectively.
+//
+// Portions of documentation are copied from the LGPL 2.1+ licensed PulseAudio C headers on a
+// fair-use basis, as discussed in the overall project readme (available in the git repository).
+
+//! Per-application mixer model.
+//!
+//! # Overview
+//!
+//! A [`Mixer`] keeps track of all sink inputs and source outputs, grouped into [`Application`]s
+//! by their owning client, as needed for an “applications” view of a mixer.
+//!
+//! The name, icon name and process ID of an application are taken from the `application.name`,
+//! `application.icon_name` and `application.process.id` properties of the client, falling back to
+//! those of its streams. Streams without an owning client (e.g. those of modules) each form an
+//! application on their own, identified by the stream, with properties taken from the stream.
+//!
+//! The volume of an application, for playback or recording, is the highest volume of its streams
+//! (of those with a volume). Setting it scales the volume of each stream to the given volume,
+//! preserving balance. An application is muted when all of its streams are, and muting or
+//! unmuting it applies to all of its streams.
+//!
+//! Subscription events keep the model up to date, with a callback signalling changes.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use pulse::context::mixer::{Kind, Mixer};
+//! use pulse::context::subscribe::Dispatcher;
+//!
+//! let dispatcher = Dispatcher::new(&mut context);
+//! let mut mixer = Mixer::new(&context, &dispatcher).unwrap();
+//! mixer.set_change_callback(Some(Box::new(|| println!("applications changed"))));
+//!
+//! // Later...
+//! for app in mixer.get_applications() {
+//!     println!("{:?}: {:?}", app.name, app.get_volume(Kind::SinkInput));
+//! }
+//! ```
+//!
+//! [`Mixer`]: struct.Mixer.html
+//! [`Application`]: struct.Application.html
+
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use crate::callbacks::ListResult;
+use crate::proplist::{properties, Proplist};
+use crate::volume::{ChannelVolumes, Volume};
+use crate::error::{Code, PAErr};
+use super::{Context, State};
+use super::introspect::{ClientInfo, Introspector, SinkInputInfo, SourceOutputInfo};
+use super::subscribe::{subscription_masks, Dispatcher, Facility, Operation, Subscription};
+
+/// The kind of a stream.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Kind {
+    /// A sink input (playback stream).
+    SinkInput,
+    /// A source output (recording stream).
+    SourceOutput,
+}
+
+/// Identifies an application.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Key {
+    /// An application with an owning client, by client index.
+    Client(u32),
+    /// A stream without an owning client, by kind and index.
+    Stream(Kind, u32),
+}
+
+/// A stream of an application.
+#[derive(Debug, Clone)]
+pub struct AppStream {
+    /// Kind of stream.
+    pub kind: Kind,
+    /// Index of the stream.
+    pub index: u32,
+    /// Name of the stream.
+    pub name: Option<String>,
+    /// Index of the sink or source the stream is connected to.
+    pub device: u32,
+    /// Volume of the stream.
+    pub volume: ChannelVolumes,
+    /// Mute switch of the stream.
+    pub mute: bool,
+    /// Whether the stream has a volume that can be set.
+    pub volume_writable: bool,
+}
+
+/// An application, with its streams.
+#[derive(Debug, Clone)]
+pub struct Application {
+    /// Identifies the application.
+    pub key: Key,
+    /// Name of the application.
+    pub name: Option<String>,
+    /// Icon name of the application.
+    pub icon_name: Option<String>,
+    /// Process ID of the application.
+    pub process_id: Option<u32>,
+    /// Streams of the application.
+    pub streams: Vec<AppStream>,
+}
+
+impl Application {
+    /// Gets the streams of the given kind.
+    pub fn get_streams(&self, kind: Kind) -> impl Iterator<Item = &AppStream> {
+        self.streams.iter().filter(move |s| s.kind == kind)
+    }
+
+    /// Gets the volume of streams of the given kind: the highest volume of any stream that has a
+    /// volume. Returns `None` if there are no such streams.
+    pub fn get_volume(&self, kind: Kind) -> Option<Volume> {
+        self.get_streams(kind).filter(|s| s.volume_writable).map(|s| s.volume.max()).max()
+    }
+
+    /// Checks whether streams of the given kind are muted, that is all of them. Returns `None` if
+    /// there are no such streams.
+    pub fn is_muted(&self, kind: Kind) -> Option<bool> {
+        let mut streams = self.get_streams(kind).peekable();
+        match streams.peek().is_some() {
+            true => Some(streams.all(|s| s.mute)),
+            false => None,
+        }
+    }
+}
+
+/// Application properties.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Properties {
+    name: Option<String>,
+    icon_name: Option<String>,
+    process_id: Option<u32>,
+}
+
+impl Properties {
+    fn from_proplist(proplist: &Proplist) -> Self {
+        Self {
+            name: proplist.get_str(properties::APPLICATION_NAME),
+            icon_name: proplist.get_str(properties::APPLICATION_ICON_NAME),
+            process_id: proplist.get_str(properties::APPLICATION_PROCESS_ID)
+                .and_then(|pid| pid.parse().ok()),
+        }
+    }
+
+    /// Fills in properties missing from `self` from `other`.
+    fn fill(&mut self, other: &Properties) {
+        if self.name.is_none() {
+            self.name = other.name.clone();
+        }
+        if self.icon_name.is_none() {
+            self.icon_name = other.icon_name.clone();
+        }
+        if self.process_id.is_none() {
+            self.process_id = other.process_id;
+        }
+    }
+}
+
+/// What we know about a stream.
+#[derive(Debug, Clone)]
+struct StreamData {
+    stream: AppStream,
+    client: Option<u32>,
+    properties: Properties,
+}
+
+/// Groups streams into applications.
+fn group(clients: &HashMap<u32, Properties>, streams: &BTreeMap<(Kind, u32), StreamData>)
+    -> Vec<Application>
+{
+    let mut applications: BTreeMap<Key, (Properties, Vec<AppStream>)> = BTreeMap::new();
+    for data in streams.values() {
+        let key = match data.client {
+            Some(client) => Key::Client(client),
+            None => Key::Stream(data.stream.kind, data.stream.index),
+        };
+        let (properties, app_streams) = applications.entry(key).or_insert_with(|| {
+            let properties = match data.client {
+                Some(client) => clients.get(&client).cloned().unwrap_or_default(),
+                None => Properties {
+                    name: data.properties.name.clone().or_else(|| data.stream.name.clone()),
+                    ..Properties::default()
+                },
+            };
+            (properties, Vec::new())
+        });
+        properties.fill(&data.properties);
+        app_streams.push(data.stream.clone());
+    }
+    applications.into_iter().map(|(key, (properties, streams))| {
+        Application {
+            key: key,
+            name: properties.name,
+            icon_name: properties.icon_name,
+            process_id: properties.process_id,
+            streams: streams,
+        }
+    }).collect()
+}
+
+/// Combines the results of several operations into one, giving `true` to the callback once all
+/// succeed, or `false` as soon as one fails.
+fn join(count: usize, callback: Option<Box<dyn FnMut(bool) + 'static>>)
+    -> Vec<Box<dyn FnMut(bool) + 'static>>
+{
+    let state = Rc::new(RefCell::new((count, callback)));
+    (0..count).map(|_| {
+        let state = Rc::clone(&state);
+        Box::new(move |success: bool| {
+            let mut state = state.borrow_mut();
+            state.0 -= 1;
+            if !success || state.0 == 0 {
+                if let Some(mut callback) = state.1.take() {
+                    callback(success);
+                }
+            }
+        }) as Box<dyn FnMut(bool)>
+    }).collect()
+}
+
+/// A per-application mixer model.
+pub struct Mixer {
+    inner: Rc<RefCell<Inner>>,
+    /// Registration with the dispatcher, if subscribed.
+    subscription: Option<Subscription>,
+}
+
+struct Inner {
+    introspector: Rc<RefCell<Introspector>>,
+    clients: HashMap<u32, Properties>,
+    streams: BTreeMap<(Kind, u32), StreamData>,
+    callback: Option<Rc<RefCell<Box<dyn FnMut() + 'static>>>>,
+}
+
+impl Mixer {
+    /// Creates a new mixer model.
+    ///
+    /// This registers with the dispatcher for sink input, source output and client events, until
+    /// dropped. If you need to manage subscriptions yourself, use [`new_unsubscribed`] instead.
+    ///
+    /// Returns an error with code `Code::BadState` if the context is not ready.
+    ///
+    /// [`new_unsubscribed`]: #method.new_unsubscribed
+    pub fn new(context: &Context, dispatcher: &Dispatcher) -> Result<Self, PAErr> {
+        if context.get_state() != State::Ready {
+            return Err(PAErr::from(Code::BadState));
+        }
+        let mut mixer = Self::new_unsubscribed(context);
+
+        let weak = Rc::downgrade(&mixer.inner);
+        let mask = subscription_masks::SINK_INPUT | subscription_masks::SOURCE_OUTPUT |
+            subscription_masks::CLIENT;
+        mixer.subscription = Some(dispatcher.register(mask,
+            Box::new(move |facility, operation, index| {
+                if let Some(inner) = weak.upgrade() {
+                    Inner::handle_event(&inner, facility, operation, index);
+                }
+            }))?);
+
+        Ok(mixer)
+    }
+
+    /// Creates a new mixer model, without subscribing to changes.
+    ///
+    /// You must pass sink input, source output and client events to [`handle_event`] yourself.
+    ///
+    /// [`handle_event`]: #method.handle_event
+    pub fn new_unsubscribed(context: &Context) -> Self {
+        let inner = Rc::new(RefCell::new(Inner {
+            introspector: Rc::new(RefCell::new(context.introspect())),
+            clients: HashMap::new(),
+            streams: BTreeMap::new(),
+            callback: None,
+        }));
+        Inner::refresh_all(&inner);
+        Self { inner: inner, subscription: None }
+    }
+
+    /// Handles a subscription event.
+    ///
+    /// Only sink input, source output and client events are of interest; others are ignored.
+    pub fn handle_event(&self, facility: Option<Facility>, operation: Option<Operation>,
+        index: u32)
+    {
+        Inner::handle_event(&self.inner, facility, operation, index);
+    }
+
+    /// Sets the callback to be called whenever the applications, or their streams, change.
+    pub fn set_change_callback(&mut self, callback: Option<Box<dyn FnMut() + 'static>>) {
+        self.inner.borrow_mut().callback = callback.map(|cb| Rc::new(RefCell::new(cb)));
+    }
+
+    /// Gets all applications, ordered by key.
+    pub fn get_applications(&self) -> Vec<Application> {
+        let inner = self.inner.borrow();
+        group(&inner.clients, &inner.streams)
+    }
+
+    /// Gets an application.
+    pub fn get_application(&self, key: &Key) -> Option<Application> {
+        self.get_applications().into_iter().find(|a| a.key == *key)
+    }
+
+    /// Sets the volume of the streams of the given kind of an application, scaling the volume of
+    /// each stream that has a volume to the given volume.
+    ///
+    /// The callback, if given, is provided with `true` once all streams have been changed, or
+    /// `false` on failure, or if the application has no such streams.
+    pub fn set_volume(&mut self, key: &Key, kind: Kind, volume: Volume,
+        callback: Option<Box<dyn FnMut(bool) + 'static>>)
+    {
+        let streams: Vec<AppStream> = match self.get_application(key) {
+            Some(app) => app.get_streams(kind).filter(|s| s.volume_writable).cloned().collect(),
+            None => Vec::new(),
+        };
+        self.apply(streams, callback, |introspector, stream, mut callback| {
+            let mut cvolume = stream.volume;
+            if cvolume.scale(volume).is_none() {
+                return callback(false);
+            }
+            let _ = match stream.kind {
+                Kind::SinkInput => {
+                    introspector.set_sink_input_volume(stream.index, &cvolume, Some(callback))
+                },
+                Kind::SourceOutput => {
+                    introspector.set_source_output_volume(stream.index, &cvolume, Some(callback))
+                },
+            };
+        });
+    }
+
+    /// Sets the mute switch of all streams of the given kind of an application.
+    ///
+    /// The callback, if given, is provided with `true` once all streams have been changed, or
+    /// `false` on failure, or if the application has no such streams.
+    pub fn set_mute(&mut self, key: &Key, kind: Kind, mute: bool,
+        callback: Option<Box<dyn FnMut(bool) + 'static>>)
+    {
+        let streams: Vec<AppStream> = match self.get_application(key) {
+            Some(app) => app.get_streams(kind).cloned().collect(),
+            None => Vec::new(),
+        };
+        self.apply(streams, callback, |introspector, stream, callback| {
+            let _ = match stream.kind {
+                Kind::SinkInput => {
+                    introspector.set_sink_input_mute(stream.index, mute, Some(callback))
+                },
+                Kind::SourceOutput => {
+                    introspector.set_source_output_mute(stream.index, mute, Some(callback))
+                },
+            };
+        });
+    }
+
+    /// Applies an operation to each of the streams, joining the results for the callback.
+    fn apply<F>(&mut self, streams: Vec<AppStream>, mut callback: Option<Box<dyn FnMut(bool)>>,
+        operation: F)
+        where F: Fn(&mut Introspector, &AppStream, Box<dyn FnMut(bool)>)
+    {
+        if streams.is_empty() {
+            if let Some(callback) = callback.as_mut() {
+                callback(false);
+            }
+            return;
+        }
+        let introspector = Rc::clone(&self.inner.borrow().introspector);
+        let mut introspector = introspector.borrow_mut();
+        for (stream, callback) in streams.iter().zip(join(streams.len(), callback)) {
+            operation(&mut introspector, stream, callback);
+        }
+    }
+}
+
+impl Inner {
+    fn handle_event(this: &Rc<RefCell<Self>>, facility: Option<Facility>,
+        operation: Option<Operation>, index: u32)
+    {
+        let kind = match facility {
+            Some(Facility::SinkInput) => Some(Kind::SinkInput),
+            Some(Facility::SourceOutput) => Some(Kind::SourceOutput),
+            Some(Facility::Client) => None,
+            _ => return,
+        };
+        match operation {
+            Some(Operation::New) | Some(Operation::Changed) => Self::refresh(this, kind, index),
+            Some(Operation::Removed) => {
+                {
+                    let mut inner = this.borrow_mut();
+                    match kind {
+                        Some(kind) => { inner.streams.remove(&(kind, index)); },
+                        None => { inner.clients.remove(&index); },
+                    }
+                }
+                Self::notify(this);
+            },
+            None => {},
+        }
+    }
+
+    /// Requests information about all clients and streams.
+    fn refresh_all(this: &Rc<RefCell<Self>>) {
+        let introspector = Rc::clone(&this.borrow().introspector);
+        let introspector = introspector.borrow();
+
+        let weak = Rc::downgrade(this);
+        let _ = introspector.get_client_info_list(move |result| {
+            if let Some(inner) = weak.upgrade() {
+                Self::handle_client(&inner, result);
+            }
+        });
+        let weak = Rc::downgrade(this);
+        let _ = introspector.get_sink_input_info_list(move |result| {
+            if let Some(inner) = weak.upgrade() {
+                Self::handle_sink_input(&inner, result);
+            }
+        });
+        let weak = Rc::downgrade(this);
+        let _ = introspector.get_source_output_info_list(move |result| {
+            if let Some(inner) = weak.upgrade() {
+                Self::handle_source_output(&inner, result);
+            }
+        });
+    }
+
+    /// Requests information about a stream of the given kind, or a client if `None`.
+    fn refresh(this: &Rc<RefCell<Self>>, kind: Option<Kind>, index: u32) {
+        let introspector = Rc::clone(&this.borrow().introspector);
+        let introspector = introspector.borrow();
+
+        let weak = Rc::downgrade(this);
+        match kind {
+            Some(Kind::SinkInput) => {
+                let _ = introspector.get_sink_input_info(index, move |result| {
+                    if let Some(inner) = weak.upgrade() {
+                        Self::handle_sink_input(&inner, result);
+                    }
+                });
+            },
+            Some(Kind::SourceOutput) => {
+                let _ = introspector.get_source_output_info(index, move |result| {
+                    if let Some(inner) = weak.upgrade() {
+                        Self::handle_source_output(&inner, result);
+                    }
+                });
+            },
+            None => {
+                let _ = introspector.get_client_info(index, move |result| {
+                    if let Some(inner) = weak.upgrade() {
+                        Self::handle_client(&inner, result);
+                    }
+                });
+            },
+        }
+    }
+
+    fn handle_client(this: &Rc<RefCell<Self>>, result: ListResult<&ClientInfo>) {
+        match result {
+            ListResult::Item(info) => {
+                let mut properties = Properties::from_proplist(&info.proplist);
+                if properties.name.is_none() {
+                    properties.name = info.name.as_ref().map(|n| n.to_string());
+                }
+                this.borrow_mut().clients.insert(info.index, properties);
+            },
+            ListResult::End => Self::notify(this),
+            ListResult::Error => {},
+        }
+    }
+
+    fn handle_sink_input(this: &Rc<RefCell<Self>>, result: ListResult<&SinkInputInfo>) {
+        match result {
+            ListResult::Item(info) => {
+                this.borrow_mut().streams.insert((Kind::SinkInput, info.index), StreamData {
+                    stream: AppStream {
+                        kind: Kind::SinkInput,
+                        index: info.index,
+                        name: info.name.as_ref().map(|n| n.to_string()),
+                        device: info.sink,
+                        volume: info.volume,
+                        mute: info.mute,
+                        volume_writable: info.has_volume && info.volume_writable,
+                    },
+                    client: info.client,
+                    properties: Properties::from_proplist(&info.proplist),
+                });
+            },
+            ListResult::End => Self::notify(this),
+            ListResult::Error => {},
+        }
+    }
+
+    fn handle_source_output(this: &Rc<RefCell<Self>>, result: ListResult<&SourceOutputInfo>) {
+        match result {
+            ListResult::Item(info) => {
+                this.borrow_mut().streams.insert((Kind::SourceOutput, info.index), StreamData {
+                    stream: AppStream {
+                        kind: Kind::SourceOutput,
+                        index: info.index,
+                        name: info.name.as_ref().map(|n| n.to_string()),
+                        device: info.source,
+                        volume: info.volume,
+                        mute: info.mute,
+                        volume_writable: info.has_volume && info.volume_writable,
+                    },
+                    client: info.client,
+                    properties: Properties::from_proplist(&info.proplist),
+                });
+            },
+            ListResult::End => Self::notify(this),
+            ListResult::Error => {},
+        }
+    }
+
+    /// Calls the change callback, if any, without borrowing, allowing it to use the mixer.
+    fn notify(this: &Rc<RefCell<Self>>) {
+        let callback = this.borrow().callback.clone();
+        if let Some(callback) = callback {
+            (callback.borrow_mut())();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream(kind: Kind, index: u32, client: Option<u32>, name: Option<&str>) -> StreamData {
+        StreamData {
+            stream: AppStream {
+                kind: kind,
+                index: index,
+                name: Some(format!("stream {}", index)),
+                device: 0,
+                volume: ChannelVolumes::default(),
+                mute: false,
+                volume_writable: true,
+            },
+            client: client,
+            properties: Properties {
+                name: name.map(|n| n.to_string()),
+                icon_name: Some("stream-icon".to_string()),
+                ..Properties::default()
+            },
+        }
+    }
+
+    #[test]
+    fn grouping() {
+        let mut clients = HashMap::new();
+        clients.insert(7, Properties {
+            name: Some("Player".to_string()),
+            process_id: Some(1234),
+            ..Properties::default()
+        });
+        let mut streams = BTreeMap::new();
+        for data in vec![stream(Kind::SinkInput, 1, Some(7), Some("Ignored")),
+            stream(Kind::SourceOutput, 2, Some(7), None),
+            stream(Kind::SinkInput, 3, None, None),
+            stream(Kind::SinkInput, 4, Some(8), Some("Unknown client"))]
+        {
+            streams.insert((data.stream.kind, data.stream.index), data);
+        }
+
+        let apps = group(&clients, &streams);
+        let keys: Vec<Key> = apps.iter().map(|a| a.key).collect();
+        assert_eq!(keys, [Key::Client(7), Key::Client(8), Key::Stream(Kind::SinkInput, 3)]);
+
+        // Client properties take precedence, with gaps filled from streams
+        assert_eq!(apps[0].name.as_ref().map(|s| s.as_str()), Some("Player"));
+        assert_eq!(apps[0].icon_name.as_ref().map(|s| s.as_str()), Some("stream-icon"));
+        assert_eq!(apps[0].process_id, Some(1234));
+        assert_eq!(apps[0].get_streams(Kind::SinkInput).count(), 1);
+        assert_eq!(apps[0].get_streams(Kind::SourceOutput).count(), 1);
+        assert_eq!(apps[0].is_muted(Kind::SinkInput), Some(false));
+
+        // Client not (yet) known
+        assert_eq!(apps[1].name.as_ref().map(|s| s.as_str()), Some("Unknown client"));
+
+        // Without a client, the stream name is used
+        assert_eq!(apps[2].name.as_ref().map(|s| s.as_str()), Some("stream 3"));
+        assert_eq!(apps[2].is_muted(Kind::SourceOutput), None);
+    }
+
+    #[test]
+    fn joined_results() {
+        let results = Rc::new(RefCell::new(Vec::new()));
+        let r = Rc::clone(&results);
+        let mut parts = join(3, Some(Box::new(move |success| r.borrow_mut().push(success))));
+        parts[0](true);
+        parts[1](true);
+        assert!(results.borrow().is_empty());
+        parts[2](true);
+        assert_eq!(*results.borrow(), [true]);
+
+        let r = Rc::clone(&results);
+        let mut parts = join(2, Some(Box::new(move |success| r.borrow_mut().push(success))));
+        parts[0](false);
+        parts[1](true);
+        assert_eq!(*results.borrow(), [true, false]);
+    }
+}
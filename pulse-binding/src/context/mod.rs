@@ -73,12 +73,18 @@
 //! [`operation::Operation`]: ../operation/struct.Operation.html
 //! [`stream`]: ../stream/index.html
 
+pub mod card;
+pub mod ducking;
 pub mod ext_device_manager;
 pub mod ext_device_restore;
 pub mod ext_stream_restore;
 pub mod introspect;
+pub mod jack;
+pub mod mixer;
+pub mod modargs;
 pub mod scache;
 pub mod subscribe;
+pub mod virtual_device;
 
 use std::os::raw::{c_char, c_void};
 use std::ffi::{CStr, CString};
@@ -0,0 +1,429 @@
+// Copyright 2020 Lyndon Brown
+//
+// This file is part of the PulseAudio Rust language binding.
+//
+// Licensed under the MIT license or the Apache license (version 2.0), at your option. You may not
+// copy, modify, or distribute this file except in compliance with said license. You can find copies
+// of these licenses either in the LICENSE-MIT and LICENSE-APACHE files, or alternatively at
+// <http://opensource.org/licenses/MIT> and <http://www.apache.org/licenses/LICENSE-2.0>
+// respectively.
+//
+// Portions of documentation are copied from the LGPL 2.1+ licensed PulseAudio C headers on a
+// fair-use basis, as discussed in the overall project readme (available in the git repository).
+
+//! Module arguments.
+//!
+//! # Overview
+//!
+//! Modules are loaded with [`Introspector::load_module`] given an argument string of whitespace
+//! separated `key=value` pairs, where values containing whitespace or quotes must be quoted (with
+//! double quotes or single quotes) and escaped (with backslashes). Property list arguments, such
+//! as `sink_properties`, are themselves in this form, thus need nested quoting, for instance:
+//!
+//! ```text
+//! sink_name=my_sink sink_properties='device.description="My Sink"'
+//! ```
+//!
+//! [`Arguments`] holds such pairs, producing correctly quoted strings with its `Display`
+//! implementation, and parsing existing strings (as given by [`ModuleInfo::argument`]) with its
+//! `FromStr` implementation.
+//!
+//! The [`modules`] sub-module provides typed builders for frequently used modules, implementing
+//! the [`Module`] trait, which can be loaded with [`Introspector::load_module_with`].
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use pulse::context::modargs::Arguments;
+//! use pulse::context::modargs::modules::NullSink;
+//! use pulse::proplist::properties;
+//!
+//! let mut props = Arguments::new();
+//! props.set(properties::DEVICE_DESCRIPTION, "My Sink");
+//!
+//! let sink = NullSink {
+//!     sink_name: Some("my_sink".to_string()),
+//!     sink_properties: Some(props),
+//!     ..Default::default()
+//! };
+//! context.introspect().load_module_with(&sink, |index| {
+//!     println!("loaded module {}", index);
+//! });
+//! ```
+//!
+//! [`Introspector::load_module`]: ../introspect/struct.Introspector.html#method.load_module
+//! [`Introspector::load_module_with`]:
+//! ../introspect/struct.Introspector.html#method.load_module_with
+//! [`ModuleInfo::argument`]: ../introspect/struct.ModuleInfo.html#structfield.argument
+//! [`Arguments`]: struct.Arguments.html
+//! [`Module`]: trait.Module.html
+//! [`modules`]: modules/index.html
+
+pub mod modules;
+
+use std::fmt;
+use std::str::FromStr;
+use crate::channelmap;
+use crate::error::{Code, PAErr};
+use crate::operation::Operation;
+use crate::sample;
+use super::introspect::{Introspector, ModuleInfo};
+
+/// An ordered set of module arguments (or property list entries in argument form).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Arguments {
+    args: Vec<(String, String)>,
+}
+
+/// A type usable as a module argument value.
+pub trait Value: Sized {
+    /// Gets the argument form of the value (unquoted).
+    fn to_arg(&self) -> String;
+    /// Parses the argument form of a value (unquoted), returning `None` if invalid.
+    fn from_arg(s: &str) -> Option<Self>;
+}
+
+/// A typed module argument builder.
+pub trait Module: Sized {
+    /// Name of the module, e.g. `module-null-sink`.
+    const NAME: &'static str;
+
+    /// Names of the arguments known to this type.
+    const ARGUMENTS: &'static [&'static str];
+
+    /// Gets the module arguments.
+    fn to_arguments(&self) -> Arguments;
+
+    /// Reads module arguments, ignoring any not known to this type.
+    ///
+    /// Returns an error of `Code::Invalid` if a value cannot be parsed.
+    fn from_arguments(args: &Arguments) -> Result<Self, PAErr>;
+}
+
+impl Arguments {
+    /// Creates an empty set of arguments.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gets the (unquoted) value of an argument.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.args.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+
+    /// Gets the value of an argument, parsed as the given type.
+    ///
+    /// Returns `None` if the argument does not exist, or `Some(Err)` with `Code::Invalid` if it
+    /// could not be parsed.
+    pub fn get_value<T>(&self, key: &str) -> Option<Result<T, PAErr>>
+        where T: Value
+    {
+        self.get(key).map(|v| T::from_arg(v).ok_or(PAErr::from(Code::Invalid)))
+    }
+
+    /// Sets an argument, replacing any existing value, and otherwise appending it.
+    ///
+    /// Panics if the key is empty or contains whitespace or an `=` character.
+    pub fn set<V>(&mut self, key: &str, value: V) -> &mut Self
+        where V: Into<String>
+    {
+        assert!(is_valid_key(key), "invalid module argument key: {:?}", key);
+        let value = value.into();
+        match self.args.iter_mut().find(|(k, _)| k == key) {
+            Some(entry) => entry.1 = value,
+            None => self.args.push((key.to_string(), value)),
+        }
+        self
+    }
+
+    /// Sets an argument to the argument form of the given value, as [`set`](#method.set) does.
+    pub fn set_value<T>(&mut self, key: &str, value: &T) -> &mut Self
+        where T: Value
+    {
+        self.set(key, value.to_arg())
+    }
+
+    /// Removes an argument, returning its value.
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        let index = self.args.iter().position(|(k, _)| k == key)?;
+        Some(self.args.remove(index).1)
+    }
+
+    /// Checks if an argument is set.
+    #[inline]
+    pub fn contains(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Gets an iterator over the keys and (unquoted) values, in order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.args.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// Gets the number of arguments.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.args.len()
+    }
+
+    /// Checks if there are no arguments.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.args.is_empty()
+    }
+}
+
+fn is_valid_key(key: &str) -> bool {
+    !key.is_empty() && !key.contains(|c: char| c == '=' || c.is_ascii_whitespace())
+}
+
+/// Writes a value, quoted and escaped if necessary.
+fn write_value(f: &mut fmt::Formatter, value: &str) -> fmt::Result {
+    let special = |c: char| c.is_ascii_whitespace() || c == '"' || c == '\'' || c == '\\';
+    if !value.is_empty() && !value.contains(special) {
+        return f.write_str(value);
+    }
+    // Prefer the quote character not used within the value, to avoid escaping
+    let quote = match value.contains('"') && !value.contains('\'') {
+        true => '\'',
+        false => '"',
+    };
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push(quote);
+    for c in value.chars() {
+        if c == quote || c == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push(quote);
+    f.write_str(&quoted)
+}
+
+impl fmt::Display for Arguments {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, (key, value)) in self.args.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{}=", key)?;
+            write_value(f, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses an argument string, as the server does.
+///
+/// Fails with `Code::Invalid` if the string is malformed (e.g. has an unterminated quote, or a key
+/// without a value), or has duplicate keys.
+impl FromStr for Arguments {
+    type Err = PAErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        #[derive(PartialEq)]
+        enum State { Whitespace, Key, ValueStart, Simple, DoubleQuotes, Ticks }
+
+        let mut args = Arguments::new();
+        let mut add = |key: &mut String, value: &mut String| {
+            if args.contains(key) {
+                return Err(PAErr::from(Code::Invalid));
+            }
+            args.args.push((std::mem::replace(key, String::new()),
+                std::mem::replace(value, String::new())));
+            Ok(())
+        };
+
+        let mut state = State::Whitespace;
+        let mut escaped = false;
+        let mut key = String::new();
+        let mut value = String::new();
+        for c in s.chars() {
+            if escaped {
+                value.push(c);
+                escaped = false;
+                continue;
+            }
+            match state {
+                State::Whitespace if c.is_ascii_whitespace() => {},
+                State::Whitespace | State::Key if c == '\\' => {
+                    return Err(PAErr::from(Code::Invalid));
+                },
+                State::Whitespace => { key.push(c); state = State::Key; },
+                State::Key if c == '=' => state = State::ValueStart,
+                State::Key if c.is_ascii_whitespace() => return Err(PAErr::from(Code::Invalid)),
+                State::Key => key.push(c),
+                State::ValueStart | State::Simple if c.is_ascii_whitespace() => {
+                    add(&mut key, &mut value)?;
+                    state = State::Whitespace;
+                },
+                State::ValueStart if c == '"' => state = State::DoubleQuotes,
+                State::ValueStart if c == '\'' => state = State::Ticks,
+                State::DoubleQuotes if c == '"' => {
+                    add(&mut key, &mut value)?;
+                    state = State::Whitespace;
+                },
+                State::Ticks if c == '\'' => {
+                    add(&mut key, &mut value)?;
+                    state = State::Whitespace;
+                },
+                State::ValueStart | State::Simple | State::DoubleQuotes | State::Ticks
+                    if c == '\\' =>
+                {
+                    escaped = true;
+                    if state == State::ValueStart {
+                        state = State::Simple;
+                    }
+                },
+                State::ValueStart => { value.push(c); state = State::Simple; },
+                State::Simple | State::DoubleQuotes | State::Ticks => value.push(c),
+            }
+        }
+        match state {
+            _ if escaped => return Err(PAErr::from(Code::Invalid)),
+            State::ValueStart | State::Simple => add(&mut key, &mut value)?,
+            State::Whitespace => {},
+            _ => return Err(PAErr::from(Code::Invalid)),
+        }
+        Ok(args)
+    }
+}
+
+impl Value for String {
+    fn to_arg(&self) -> String {
+        self.clone()
+    }
+    fn from_arg(s: &str) -> Option<Self> {
+        Some(s.to_string())
+    }
+}
+
+macro_rules! value_via_string {
+    ( $($t:ty),* ) => {
+        $(
+            impl Value for $t {
+                fn to_arg(&self) -> String {
+                    format!("{}", self)
+                }
+                fn from_arg(s: &str) -> Option<Self> {
+                    s.parse().ok()
+                }
+            }
+        )*
+    };
+}
+
+value_via_string!(u8, u16, u32, i32, f32, sample::Format, channelmap::Map);
+
+/// Booleans are written as `true` or `false`, and read in any of the forms accepted by the server
+/// (`1`/`0`, `y`/`n`, `yes`/`no`, `on`/`off` or `true`/`false`, ignoring case).
+impl Value for bool {
+    fn to_arg(&self) -> String {
+        self.to_string()
+    }
+    fn from_arg(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "1" | "y" | "yes" | "on" | "true" => Some(true),
+            "0" | "n" | "no" | "off" | "false" => Some(false),
+            _ => None,
+        }
+    }
+}
+
+/// Lists are written comma separated.
+impl Value for Vec<String> {
+    fn to_arg(&self) -> String {
+        self.join(",")
+    }
+    fn from_arg(s: &str) -> Option<Self> {
+        match s.is_empty() {
+            true => Some(Vec::new()),
+            false => Some(s.split(',').map(|s| s.to_string()).collect()),
+        }
+    }
+}
+
+/// Nested arguments, e.g. property lists, are written in argument string form.
+impl Value for Arguments {
+    fn to_arg(&self) -> String {
+        self.to_string()
+    }
+    fn from_arg(s: &str) -> Option<Self> {
+        s.parse().ok()
+    }
+}
+
+impl Introspector {
+    /// Loads a module, with arguments from the given typed builder.
+    ///
+    /// Panics on error, i.e. invalid arguments or state. The callback is provided with the index.
+    pub fn load_module_with<M, F>(&mut self, module: &M, callback: F)
+        -> Operation<dyn FnMut(u32)>
+        where M: Module,
+              F: FnMut(u32) + 'static
+    {
+        self.load_module(M::NAME, &module.to_arguments().to_string(), callback)
+    }
+}
+
+impl<'a> ModuleInfo<'a> {
+    /// Parses the argument string of the module.
+    ///
+    /// Returns empty arguments if the module has none, or an error of `Code::Invalid` if the
+    /// string is malformed.
+    pub fn get_arguments(&self) -> Result<Arguments, PAErr> {
+        match self.argument.as_ref() {
+            Some(argument) => argument.parse(),
+            None => Ok(Arguments::new()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format() {
+        let mut props = Arguments::new();
+        props.set("device.description", "My Sink").set("device.icon_name", "audio-card");
+        let mut args = Arguments::new();
+        args.set("sink_name", "my_sink").set_value("sink_properties", &props).set("empty", "");
+        assert_eq!(args.to_string(), "sink_name=my_sink \
+            sink_properties='device.description=\"My Sink\" device.icon_name=audio-card' \
+            empty=\"\"");
+
+        let mut args = Arguments::new();
+        args.set("a", r#"it's "quoted" \ text"#);
+        assert_eq!(args.to_string(), r#"a="it's \"quoted\" \\ text""#);
+    }
+
+    #[test]
+    fn parse() {
+        let args: Arguments = "  a=1 b=\"x y\" c='say \"hi\"' d=esc\\ aped e= f=\"\\\"\\\\\""
+            .parse().unwrap();
+        let pairs: Vec<(&str, &str)> = args.iter().collect();
+        assert_eq!(pairs, vec![("a", "1"), ("b", "x y"), ("c", "say \"hi\""), ("d", "esc aped"),
+            ("e", ""), ("f", "\"\\")]);
+        assert_eq!(args.get_value::<u32>("a"), Some(Ok(1)));
+
+        assert!("a=1 a=2".parse::<Arguments>().is_err());
+        assert!("a=\"open".parse::<Arguments>().is_err());
+        assert!("a b=1".parse::<Arguments>().is_err());
+        assert!("a=1 b".parse::<Arguments>().is_err());
+        assert!("a\\b=1".parse::<Arguments>().is_err());
+        assert!("\\a=1".parse::<Arguments>().is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut props = Arguments::new();
+        props.set("device.description", r#"Bob's "best" sink"#);
+        let mut args = Arguments::new();
+        args.set_value("sink_properties", &props).set("x", "\\");
+        let parsed: Arguments = args.to_string().parse().unwrap();
+        assert_eq!(parsed, args);
+        assert_eq!(parsed.get_value::<Arguments>("sink_properties"), Some(Ok(props)));
+    }
+}
@@ -0,0 +1,387 @@
+// Copyright 2020 Lyndon Brown
+//
+// This file is part of the PulseAudio Rust language binding.
+//
+// Licensed under the MIT license or the Apache license (version 2.0), at your option. You may not
+// copy, modify, or distribute this file except in compliance with said license. You can find copies
+// of these licenses either in the LICENSE-MIT and LICENSE-APACHE files, or alternatively at
+// <http://opensource.org/licenses/MIT> and <http://www.apache.org/licenses/LICENSE-2.0>
+// respectively.
+//
+// Portions of documentation are copied from the LGPL 2.1+ licensed PulseAudio C headers on a
+// fair-use basis, as discussed in the overall project readme (available in the git repository).
+
+//! Typed argument builders for frequently used modules.
+//!
+//! # Overview
+//!
+//! Each type here describes the arguments of one module, with a field per argument, named as the
+//! argument is. Arguments left as `None` are omitted, leaving the module to use its default.
+//!
+//! All types implement the [`Module`] trait, for conversion to and from [`Arguments`], and for use
+//! with [`Introspector::load_module_with`].
+//!
+//! Only commonly used arguments are covered; others can be added to the [`Arguments`] object
+//! obtained from [`Module::to_arguments`], and given to [`Introspector::load_module`] directly.
+//!
+//! [`Module`]: ../trait.Module.html
+//! [`Module::to_arguments`]: ../trait.Module.html#tymethod.to_arguments
+//! [`Arguments`]: ../struct.Arguments.html
+//! [`Introspector::load_module`]: ../../introspect/struct.Introspector.html#method.load_module
+//! [`Introspector::load_module_with`]:
+//! ../../introspect/struct.Introspector.html#method.load_module_with
+
+use crate::channelmap;
+use crate::error::PAErr;
+use crate::sample;
+use super::{Arguments, Module};
+
+/// Defines a module argument builder type.
+macro_rules! module {
+    (
+        $(#[$meta:meta])*
+        pub struct $name:ident = $module:expr;
+        {
+            $( $(#[$fmeta:meta])* $field:ident: $t:ty, )*
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Default, PartialEq)]
+        pub struct $name {
+            $( $(#[$fmeta])* pub $field: Option<$t>, )*
+        }
+
+        impl Module for $name {
+            const NAME: &'static str = $module;
+            const ARGUMENTS: &'static [&'static str] = &[ $( stringify!($field), )* ];
+
+            fn to_arguments(&self) -> Arguments {
+                let mut args = Arguments::new();
+                $(
+                    if let Some(value) = &self.$field {
+                        args.set_value(stringify!($field), value);
+                    }
+                )*
+                args
+            }
+
+            fn from_arguments(args: &Arguments) -> Result<Self, PAErr> {
+                Ok(Self {
+                    $( $field: args.get_value(stringify!($field)).transpose()?, )*
+                })
+            }
+        }
+    };
+}
+
+module! {
+    /// Arguments of `module-null-sink`, which creates a sink discarding all data (with a monitor
+    /// source that can be recorded from).
+    pub struct NullSink = "module-null-sink";
+    {
+        /// Name of the sink.
+        sink_name: String,
+        /// Properties of the sink.
+        sink_properties: Arguments,
+        /// Sample format.
+        format: sample::Format,
+        /// Sample rate.
+        rate: u32,
+        /// Number of channels.
+        channels: u8,
+        /// Channel map.
+        channel_map: channelmap::Map,
+    }
+}
+
+module! {
+    /// Arguments of `module-loopback`, which plays back data recorded from a source on a sink.
+    pub struct Loopback = "module-loopback";
+    {
+        /// Name of the source to record from.
+        source: String,
+        /// Name of the sink to play back on.
+        sink: String,
+        /// Target latency, in milliseconds.
+        latency_msec: u32,
+        /// Interval at which to adjust the rate to keep the latency constant, in seconds.
+        adjust_time: u32,
+        /// Sample format.
+        format: sample::Format,
+        /// Sample rate.
+        rate: u32,
+        /// Number of channels.
+        channels: u8,
+        /// Channel map.
+        channel_map: channelmap::Map,
+        /// Properties of the sink input.
+        sink_input_properties: Arguments,
+        /// Properties of the source output.
+        source_output_properties: Arguments,
+        /// Whether to prevent the sink input from being moved.
+        sink_dont_move: bool,
+        /// Whether to prevent the source output from being moved.
+        source_dont_move: bool,
+        /// Whether to remix channels.
+        remix: bool,
+    }
+}
+
+module! {
+    /// Arguments of `module-remap-sink`, which creates a virtual sink remapping channels onto
+    /// another (master) sink.
+    pub struct RemapSink = "module-remap-sink";
+    {
+        /// Name of the sink.
+        sink_name: String,
+        /// Properties of the sink.
+        sink_properties: Arguments,
+        /// Name of the master sink.
+        master: String,
+        /// Channel map of the master sink, corresponding position by position to `channel_map`.
+        master_channel_map: channelmap::Map,
+        /// Sample format.
+        format: sample::Format,
+        /// Sample rate.
+        rate: u32,
+        /// Number of channels.
+        channels: u8,
+        /// Channel map.
+        channel_map: channelmap::Map,
+        /// Whether to remix channels.
+        remix: bool,
+    }
+}
+
+module! {
+    /// Arguments of `module-remap-source`, which creates a virtual source remapping channels from
+    /// another (master) source.
+    pub struct RemapSource = "module-remap-source";
+    {
+        /// Name of the source.
+        source_name: String,
+        /// Properties of the source.
+        source_properties: Arguments,
+        /// Name of the master source.
+        master: String,
+        /// Channel map of the master source, corresponding position by position to `channel_map`.
+        master_channel_map: channelmap::Map,
+        /// Sample format.
+        format: sample::Format,
+        /// Sample rate.
+        rate: u32,
+        /// Number of channels.
+        channels: u8,
+        /// Channel map.
+        channel_map: channelmap::Map,
+        /// Whether to remix channels.
+        remix: bool,
+    }
+}
+
+module! {
+    /// Arguments of `module-combine-sink`, which creates a sink playing back on several others.
+    pub struct CombineSink = "module-combine-sink";
+    {
+        /// Name of the sink.
+        sink_name: String,
+        /// Properties of the sink.
+        sink_properties: Arguments,
+        /// Names of the sinks to play back on (all sinks if not given).
+        slaves: Vec<String>,
+        /// Interval at which to adjust rates to keep the sinks in sync, in seconds.
+        adjust_time: u32,
+        /// Resampling method.
+        resample_method: String,
+        /// Sample format.
+        format: sample::Format,
+        /// Sample rate.
+        rate: u32,
+        /// Number of channels.
+        channels: u8,
+        /// Channel map.
+        channel_map: channelmap::Map,
+    }
+}
+
+module! {
+    /// Arguments of `module-echo-cancel`, which creates a virtual source and sink pair, removing
+    /// the echo of played back audio from recorded audio.
+    pub struct EchoCancel = "module-echo-cancel";
+    {
+        /// Name of the source.
+        source_name: String,
+        /// Properties of the source.
+        source_properties: Arguments,
+        /// Name of the master source.
+        source_master: String,
+        /// Name of the sink.
+        sink_name: String,
+        /// Properties of the sink.
+        sink_properties: Arguments,
+        /// Name of the master sink.
+        sink_master: String,
+        /// Interval at which to adjust the rate to keep the source and sink in sync, in seconds.
+        adjust_time: u32,
+        /// Echo cancellation method, e.g. `webrtc` or `speex`.
+        aec_method: String,
+        /// Parameters of the echo canceller.
+        aec_args: Arguments,
+        /// Sample format.
+        format: sample::Format,
+        /// Sample rate.
+        rate: u32,
+        /// Number of channels.
+        channels: u8,
+        /// Channel map.
+        channel_map: channelmap::Map,
+        /// Whether to use the format of the master source.
+        use_master_format: bool,
+        /// Whether to share volume with the master devices.
+        use_volume_sharing: bool,
+        /// Whether to save data for debugging.
+        save_aec: bool,
+        /// Whether the module is being loaded automatically (by `module-filter-apply`).
+        autoloaded: bool,
+    }
+}
+
+module! {
+    /// Arguments of `module-ladspa-sink`, which creates a virtual sink applying a LADSPA plugin to
+    /// data played back on another (master) sink.
+    pub struct LadspaSink = "module-ladspa-sink";
+    {
+        /// Name of the sink.
+        sink_name: String,
+        /// Properties of the sink.
+        sink_properties: Arguments,
+        /// Properties of the sink input.
+        sink_input_properties: Arguments,
+        /// Name of the master sink.
+        master: String,
+        /// Sample format.
+        format: sample::Format,
+        /// Sample rate.
+        rate: u32,
+        /// Number of channels.
+        channels: u8,
+        /// Channel map.
+        channel_map: channelmap::Map,
+        /// Name of the plugin library.
+        plugin: String,
+        /// Label of the plugin within the library.
+        label: String,
+        /// Comma separated values of the plugin’s input control ports, any left empty taking their
+        /// default.
+        control: String,
+        /// Names of the plugin’s input ports, in order of channels.
+        input_ladspaport_map: Vec<String>,
+        /// Names of the plugin’s output ports, in order of channels.
+        output_ladspaport_map: Vec<String>,
+    }
+}
+
+module! {
+    /// Arguments of `module-tunnel-sink`, which creates a sink playing back on a sink of a remote
+    /// server.
+    pub struct TunnelSink = "module-tunnel-sink";
+    {
+        /// Address of the remote server.
+        server: String,
+        /// Name of the remote sink.
+        sink: String,
+        /// Name of the local sink.
+        sink_name: String,
+        /// Properties of the local sink.
+        sink_properties: Arguments,
+        /// Path of the authentication cookie file.
+        cookie: String,
+        /// Sample format.
+        format: sample::Format,
+        /// Sample rate.
+        rate: u32,
+        /// Number of channels.
+        channels: u8,
+        /// Channel map.
+        channel_map: channelmap::Map,
+    }
+}
+
+module! {
+    /// Arguments of `module-tunnel-source`, which creates a source recording from a source of a
+    /// remote server.
+    pub struct TunnelSource = "module-tunnel-source";
+    {
+        /// Address of the remote server.
+        server: String,
+        /// Name of the remote source.
+        source: String,
+        /// Name of the local source.
+        source_name: String,
+        /// Properties of the local source.
+        source_properties: Arguments,
+        /// Path of the authentication cookie file.
+        cookie: String,
+        /// Sample format.
+        format: sample::Format,
+        /// Sample rate.
+        rate: u32,
+        /// Number of channels.
+        channels: u8,
+        /// Channel map.
+        channel_map: channelmap::Map,
+    }
+}
+
+module! {
+    /// Arguments of `module-native-protocol-tcp`, which accepts client connections over TCP.
+    pub struct NativeProtocolTcp = "module-native-protocol-tcp";
+    {
+        /// Port to listen on.
+        port: u16,
+        /// Address to listen on.
+        listen: String,
+        /// Whether to allow clients to connect without authentication.
+        auth_anonymous: bool,
+        /// Semicolon separated IP addresses and networks (with prefix length) of clients allowed to
+        /// connect without authentication.
+        auth_ip_acl: String,
+        /// Whether to allow clients with the authentication cookie to connect.
+        auth_cookie_enabled: bool,
+        /// Path of the authentication cookie file.
+        auth_cookie: String,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn typed_arguments() {
+        let mut props = Arguments::new();
+        props.set("device.description", "My Sink");
+        let sink = CombineSink {
+            sink_name: Some("combined".to_string()),
+            sink_properties: Some(props),
+            slaves: Some(vec!["a".to_string(), "b".to_string()]),
+            rate: Some(48000),
+            ..Default::default()
+        };
+        let args = sink.to_arguments();
+        assert_eq!(args.to_string(), "sink_name=combined \
+            sink_properties='device.description=\"My Sink\"' slaves=a,b rate=48000");
+        assert_eq!(CombineSink::from_arguments(&args), Ok(sink));
+
+        let args: Arguments = "port=4713 auth_anonymous=yes auth_ip_acl=127.0.0.1;192.168.0.0/16"
+            .parse().unwrap();
+        let tcp = NativeProtocolTcp::from_arguments(&args).unwrap();
+        assert_eq!(tcp.port, Some(4713));
+        assert_eq!(tcp.auth_anonymous, Some(true));
+        assert_eq!(tcp.auth_ip_acl.as_ref().map(|s| s.as_str()), Some("127.0.0.1;192.168.0.0/16"));
+        assert_eq!(tcp.listen, None);
+
+        let args: Arguments = "rate=fast".parse().unwrap();
+        assert!(NullSink::from_arguments(&args).is_err());
+    }
+}
@@ -31,6 +31,12 @@
 //! callback, detailing *facility* and *operation*, where for instance `Facility::Source` with
 //! `Operation::New` indicates that a new source was added.
 //!
+//! A context has only the one callback and mask, thus the above does not combine well with
+//! multiple parts of an application each wanting events. For that, subscribers can instead be
+//! registered with a [`Dispatcher`]; the context is then subscribed to the combination of their
+//! masks, with each subscriber given only the events it asked for. This takes over the callback and
+//! mask, so should not be mixed with direct use of the above.
+//!
 //! # Example
 //!
 //! Subscribe (declare interest):
@@ -53,9 +59,14 @@
 //! [`context::Context::subscribe`]: ../struct.Context.html#method.subscribe
 //! [`context::Context::set_subscribe_callback`]: ../struct.Context.html#method.set_subscribe_callback
 //! [`subscription_masks`]: subscription_masks/index.html
+//! [`Dispatcher`]: struct.Dispatcher.html
 
 use std::os::raw::c_void;
-use super::{ContextInternal, Context};
+use std::ptr::null_mut;
+use std::rc::Rc;
+use std::cell::RefCell;
+use super::{ContextInternal, Context, State};
+use crate::error::{Code, PAErr};
 use crate::operation;
 use crate::callbacks::{box_closure_get_capi_ptr, MultiUseCallback};
 
@@ -163,6 +174,138 @@ fn get_operation(value: EventType) -> Option<Operation> {
     Operation::from_int((value & OPERATION_MASK) as u32)
 }
 
+/// Callback type for subscribers.
+pub type SubscriberCallback = Box<dyn FnMut(Option<Facility>, Option<Operation>, u32) + 'static>;
+
+/// Shares the event subscription of a context between subscribers.
+///
+/// Creating a dispatcher takes over the subscription of the context: the callback set with
+/// [`Context::set_subscribe_callback`] is replaced, and the mask given to [`Context::subscribe`] is
+/// overridden. Using those afterwards breaks delivery of events to subscribers.
+///
+/// The context is subscribed to the combination of the masks of all subscribers, which is updated
+/// as subscribers come and go. Clones share the same set of subscribers.
+///
+/// [`Context::set_subscribe_callback`]: ../struct.Context.html#method.set_subscribe_callback
+/// [`Context::subscribe`]: ../struct.Context.html#method.subscribe
+#[derive(Clone)]
+pub struct Dispatcher {
+    shared: Rc<RefCell<Shared>>,
+}
+
+struct Shared {
+    /// The context, on which we hold a reference.
+    context: *mut ContextInternal,
+    subscribers: Vec<(u64, InterestMaskSet, Rc<RefCell<SubscriberCallback>>)>,
+    next_id: u64,
+    /// Last mask successfully given to the server.
+    mask: Option<InterestMaskSet>,
+}
+
+/// A subscriber registered with a [`Dispatcher`](struct.Dispatcher.html).
+///
+/// The subscriber is unregistered when this object is dropped.
+pub struct Subscription {
+    shared: Rc<RefCell<Shared>>,
+    id: u64,
+}
+
+impl Dispatcher {
+    /// Creates a new dispatcher for the given context.
+    pub fn new(context: &mut Context) -> Self {
+        let shared = Rc::new(RefCell::new(Shared {
+            context: unsafe { capi::pa_context_ref(context.ptr) },
+            subscribers: Vec::new(),
+            next_id: 0,
+            mask: None,
+        }));
+        let weak = Rc::downgrade(&shared);
+        context.set_subscribe_callback(Some(Box::new(move |facility, operation, index| {
+            if let Some(shared) = weak.upgrade() {
+                Shared::dispatch(&shared, facility, operation, index);
+            }
+        })));
+        Self { shared: shared }
+    }
+
+    /// Registers a subscriber.
+    ///
+    /// The callback is called with events of the facilities in `mask`, for as long as the returned
+    /// object is kept alive.
+    ///
+    /// Returns an error with code `Code::BadState` if the context is not ready, the server not
+    /// accepting subscriptions before then.
+    pub fn register(&self, mask: InterestMaskSet, callback: SubscriberCallback)
+        -> Result<Subscription, PAErr>
+    {
+        let state: State = unsafe {
+            capi::pa_context_get_state(self.shared.borrow().context).into()
+        };
+        match state {
+            State::Ready => Ok(self.add(mask, callback)),
+            _ => Err(PAErr::from(Code::BadState)),
+        }
+    }
+
+    fn add(&self, mask: InterestMaskSet, callback: SubscriberCallback) -> Subscription {
+        let mut shared = self.shared.borrow_mut();
+        let id = shared.next_id;
+        shared.next_id += 1;
+        shared.subscribers.push((id, mask, Rc::new(RefCell::new(callback))));
+        shared.update_mask();
+        Subscription { shared: Rc::clone(&self.shared), id: id }
+    }
+}
+
+impl Shared {
+    /// Subscribes to the combination of the masks of the subscribers, if changed.
+    fn update_mask(&mut self) {
+        let mask = self.subscribers.iter()
+            .fold(subscription_masks::NULL, |mask, subscriber| mask | subscriber.1);
+        if self.mask == Some(mask) {
+            return;
+        }
+        // Fails if the context has since failed or been terminated, when no events can arrive
+        let ptr = unsafe { capi::pa_context_subscribe(self.context, mask, None, null_mut()) };
+        if !ptr.is_null() {
+            unsafe { capi::pa_operation_unref(ptr) };
+            self.mask = Some(mask);
+        }
+    }
+
+    /// Passes an event to the subscribers interested in it.
+    fn dispatch(this: &Rc<RefCell<Self>>, facility: Option<Facility>,
+        operation: Option<Operation>, index: u32)
+    {
+        let mask = match facility {
+            Some(facility) => facility.to_interest_mask(),
+            None => return,
+        };
+        let callbacks: Vec<Rc<RefCell<SubscriberCallback>>> = this.borrow().subscribers.iter()
+            .filter(|subscriber| subscriber.1 & mask != 0)
+            .map(|subscriber| Rc::clone(&subscriber.2))
+            .collect();
+        // Callbacks are made without borrowing, allowing them to (un)register subscribers
+        for callback in callbacks {
+            (callback.borrow_mut())(facility, operation, index);
+        }
+    }
+}
+
+impl Drop for Shared {
+    fn drop(&mut self) {
+        unsafe { capi::pa_context_unref(self.context) };
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        let mut shared = self.shared.borrow_mut();
+        shared.subscribers.retain(|subscriber| subscriber.0 != self.id);
+        shared.update_mask();
+    }
+}
+
 pub(super) type Callback = MultiUseCallback<dyn FnMut(Option<Facility>, Option<Operation>, u32),
     extern "C" fn(*mut ContextInternal, EventType, u32, *mut c_void)>;
 
@@ -222,3 +365,52 @@ fn cb_proxy(_: *mut ContextInternal, et: EventType, index: u32, userdata: *mut c
         (callback)(facility, operation, index);
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use super::*;
+    use crate::mainloop::standard::Mainloop;
+
+    #[test]
+    fn not_ready() {
+        let mainloop = Mainloop::new().unwrap();
+        let mut context = Context::new(&mainloop, "test").unwrap();
+        let dispatcher = Dispatcher::new(&mut context);
+
+        let result = dispatcher.register(subscription_masks::ALL, Box::new(|_, _, _| {}));
+        assert_eq!(result.err(), Some(PAErr::from(Code::BadState)));
+        assert!(dispatcher.shared.borrow().subscribers.is_empty());
+    }
+
+    #[test]
+    fn dispatch() {
+        let mainloop = Mainloop::new().unwrap();
+        let mut context = Context::new(&mainloop, "test").unwrap();
+        let dispatcher = Dispatcher::new(&mut context);
+
+        let counter = |count: &Rc<Cell<u32>>| -> SubscriberCallback {
+            let count = Rc::clone(count);
+            Box::new(move |_, _, _| count.set(count.get() + 1))
+        };
+        let sinks = Rc::new(Cell::new(0));
+        let devices = Rc::new(Cell::new(0));
+        let _sink_subscription = dispatcher.add(subscription_masks::SINK, counter(&sinks));
+        let device_subscription = dispatcher.add(
+            subscription_masks::SINK | subscription_masks::SOURCE, counter(&devices));
+
+        let event = |facility| {
+            Shared::dispatch(&dispatcher.shared, facility, Some(Operation::Changed), 0);
+        };
+        event(Some(Facility::Sink));
+        event(Some(Facility::Source));
+        event(Some(Facility::Client));
+        event(None);
+        assert_eq!((sinks.get(), devices.get()), (1, 2));
+
+        drop(device_subscription);
+        event(Some(Facility::Sink));
+        assert_eq!((sinks.get(), devices.get()), (2, 2));
+        assert_eq!(dispatcher.shared.borrow().subscribers.len(), 1);
+    }
+}
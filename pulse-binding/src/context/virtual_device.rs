@@ -0,0 +1,329 @@
+// Copyright 2020 Lyndon Brown
+//
+// This file is part of the PulseAudio Rust language binding.
+//
+// Licensed under the MIT license or the Apache license (version 2.0), at your option. You may not
+// copy, modify, or distribute this file except in compliance with said license. You can find copies
+// of these licenses either in the LICENSE-MIT and LICENSE-APACHE files, or alternatively at
+// <http://opensource.org/licenses/MIT> and <http://www.apache.org/licenses/LICENSE-2.0>
+// respectively.
+//
+// Portions of documentation are copied from the LGPL 2.1+ licensed PulseAudio C headers on a
+// fair-use basis, as discussed in the overall project readme (available in the git repository).
+
+//! Management of application owned virtual devices.
+//!
+//! # Overview
+//!
+//! A [`Manager`] loads modules creating virtual devices, such as null sinks and loopbacks, on
+//! behalf of an application, and unloads them again when dropped.
+//!
+//! Each module loaded is tagged with an owner, identifying the application, along with the process
+//! ID and host name of the application instance, by adding the [`OWNER_PROPERTY`],
+//! [`OWNER_PROCESS_ID_PROPERTY`] and [`OWNER_PROCESS_HOST_PROPERTY`] properties to its property
+//! list arguments (e.g. `sink_properties`). Thus the devices created carry these properties, and
+//! the module argument string (see [`ModuleInfo::argument`]) records them.
+//!
+//! If an application instance crashes, its modules are left behind. Calling
+//! [`Manager::clean_up_stale`] on startup finds modules of the same owner belonging to instances
+//! that no longer exist, and unloads them. Modules belonging to other running instances, or to
+//! instances on other hosts (where it cannot be determined whether they are still running), are
+//! left alone.
+//!
+//! Modules without property list arguments cannot be tagged, thus are not found when cleaning up.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use pulse::context::modargs::modules::{Loopback, NullSink};
+//! use pulse::context::virtual_device::Manager;
+//!
+//! let mut manager = Manager::new(&context, "org.example.FooApp");
+//! manager.clean_up_stale(None);
+//!
+//! let sink = NullSink { sink_name: Some("foo_capture".to_string()), ..Default::default() };
+//! manager.load(&sink, Some(Box::new(|result| {
+//!     println!("loaded: {:?}", result);
+//! })));
+//! ```
+//!
+//! [`Manager`]: struct.Manager.html
+//! [`Manager::clean_up_stale`]: struct.Manager.html#method.clean_up_stale
+//! [`OWNER_PROPERTY`]: constant.OWNER_PROPERTY.html
+//! [`OWNER_PROCESS_ID_PROPERTY`]: constant.OWNER_PROCESS_ID_PROPERTY.html
+//! [`OWNER_PROCESS_HOST_PROPERTY`]: constant.OWNER_PROCESS_HOST_PROPERTY.html
+//! [`ModuleInfo::argument`]: ../introspect/struct.ModuleInfo.html#structfield.argument
+
+use std::rc::Rc;
+use std::cell::RefCell;
+use crate::callbacks::ListResult;
+use crate::def::INVALID_INDEX;
+use crate::error::{Code, PAErr};
+use crate::util;
+use super::Context;
+use super::introspect::Introspector;
+use super::modargs::{Arguments, Module};
+
+/// Property identifying the owner (application) of a virtual device.
+pub const OWNER_PROPERTY: &str = "virtual_device.owner";
+
+/// Property holding the process ID of the application instance owning a virtual device.
+pub const OWNER_PROCESS_ID_PROPERTY: &str = "virtual_device.owner.process.id";
+
+/// Property holding the host name of the application instance owning a virtual device.
+pub const OWNER_PROCESS_HOST_PROPERTY: &str = "virtual_device.owner.process.host";
+
+/// Buffer size used for querying the host name.
+const HOST_NAME_MAX: usize = 256;
+
+/// Identifies the application instance owning a module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Tag {
+    owner: String,
+    process_id: u32,
+    host: Option<String>,
+}
+
+impl Tag {
+    /// Adds the tag to each of the given property list arguments (creating any missing).
+    ///
+    /// Arguments that cannot be parsed are left untouched.
+    fn apply(&self, args: &mut Arguments, property_arguments: &[&str]) {
+        for key in property_arguments {
+            let mut props = match args.get_value::<Arguments>(key) {
+                Some(Ok(props)) => props,
+                Some(Err(_)) => continue,
+                None => Arguments::new(),
+            };
+            props.set(OWNER_PROPERTY, self.owner.as_str())
+                .set(OWNER_PROCESS_ID_PROPERTY, self.process_id.to_string());
+            if let Some(host) = &self.host {
+                props.set(OWNER_PROCESS_HOST_PROPERTY, host.as_str());
+            }
+            args.set_value(key, &props);
+        }
+    }
+
+    /// Reads the tag from the property list arguments of a module, if tagged.
+    fn from_arguments(args: &Arguments) -> Option<Self> {
+        args.iter()
+            .filter(|(key, _)| key.ends_with("_properties"))
+            .filter_map(|(_, value)| value.parse::<Arguments>().ok())
+            .find_map(|props| {
+                Some(Self {
+                    owner: props.get(OWNER_PROPERTY)?.to_string(),
+                    process_id: props.get(OWNER_PROCESS_ID_PROPERTY)?.parse().ok()?,
+                    host: props.get(OWNER_PROCESS_HOST_PROPERTY).map(|h| h.to_string()),
+                })
+            })
+    }
+
+    /// Checks if this tag belongs to an instance, other than `current`, of the same owner on the
+    /// same host, which no longer exists.
+    fn is_stale(&self, current: &Tag, exists: &dyn Fn(u32) -> bool) -> bool {
+        self.owner == current.owner && self.host == current.host
+            && self.process_id != current.process_id && !exists(self.process_id)
+    }
+}
+
+/// Checks if a process exists.
+#[cfg(unix)]
+fn process_exists(pid: u32) -> bool {
+    match unsafe { libc::kill(pid as libc::pid_t, 0) } {
+        0 => true,
+        _ => std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM),
+    }
+}
+
+/// Checks if a process exists.
+///
+/// Not determinable here, so processes are assumed to exist.
+#[cfg(not(unix))]
+fn process_exists(_pid: u32) -> bool {
+    true
+}
+
+/// Loads and tracks modules owned by an application instance.
+///
+/// All modules loaded through this object (and not explicitly unloaded) are unloaded when it is
+/// dropped, which requires the context to still be connected.
+pub struct Manager {
+    introspector: Rc<RefCell<Introspector>>,
+    tag: Tag,
+    modules: Rc<RefCell<Vec<u32>>>,
+}
+
+impl Manager {
+    /// Creates a new manager, for the given owner (e.g. an application ID).
+    pub fn new(context: &Context, owner: &str) -> Self {
+        Self {
+            introspector: Rc::new(RefCell::new(context.introspect())),
+            tag: Tag {
+                owner: owner.to_string(),
+                process_id: std::process::id(),
+                host: util::get_host_name(HOST_NAME_MAX),
+            },
+            modules: Rc::new(RefCell::new(Vec::new())),
+        }
+    }
+
+    /// Gets the owner.
+    #[inline]
+    pub fn get_owner(&self) -> &str {
+        &self.tag.owner
+    }
+
+    /// Gets the indexes of the modules currently loaded through this object.
+    pub fn get_modules(&self) -> Vec<u32> {
+        self.modules.borrow().clone()
+    }
+
+    /// Loads a module, with arguments from the given typed builder, tagging its property list
+    /// arguments.
+    ///
+    /// The callback, if given, is provided with the index of the module, or an error of
+    /// `Code::ModInitFailed` if it failed to load.
+    pub fn load<M>(&mut self, module: &M,
+        callback: Option<Box<dyn FnMut(Result<u32, PAErr>) + 'static>>)
+        where M: Module
+    {
+        let property_arguments: Vec<&str> = M::ARGUMENTS.iter().cloned()
+            .filter(|key| key.ends_with("_properties"))
+            .collect();
+        self.load_arguments(M::NAME, module.to_arguments(), &property_arguments, callback)
+    }
+
+    /// Loads a module with the given arguments, tagging those named in `property_arguments`,
+    /// which must be property list arguments of the module.
+    ///
+    /// The callback, if given, is provided with the index of the module, or an error of
+    /// `Code::ModInitFailed` if it failed to load.
+    pub fn load_arguments(&mut self, name: &str, mut args: Arguments, property_arguments: &[&str],
+        mut callback: Option<Box<dyn FnMut(Result<u32, PAErr>) + 'static>>)
+    {
+        self.tag.apply(&mut args, property_arguments);
+        let modules = Rc::downgrade(&self.modules);
+        let introspector = Rc::clone(&self.introspector);
+        self.introspector.borrow_mut().load_module(name, &args.to_string(), move |index| {
+            let result = match index {
+                INVALID_INDEX => Err(PAErr::from(Code::ModInitFailed)),
+                index => {
+                    // If the manager has since been dropped, unload it straight away
+                    match modules.upgrade() {
+                        Some(modules) => modules.borrow_mut().push(index),
+                        None => { introspector.borrow_mut().unload_module(index, |_| {}); },
+                    }
+                    Ok(index)
+                },
+            };
+            if let Some(callback) = callback.as_mut() {
+                callback(result);
+            }
+        });
+    }
+
+    /// Unloads a module loaded through this object.
+    ///
+    /// The callback, if given, is provided with `true` on success. Modules not loaded through this
+    /// object are not unloaded, giving `false`.
+    pub fn unload(&mut self, index: u32, mut callback: Option<Box<dyn FnMut(bool) + 'static>>) {
+        let position = self.modules.borrow().iter().position(|i| *i == index);
+        match position {
+            Some(position) => {
+                self.modules.borrow_mut().remove(position);
+                self.introspector.borrow_mut().unload_module(index, move |success| {
+                    if let Some(callback) = callback.as_mut() {
+                        callback(success);
+                    }
+                });
+            },
+            None => {
+                if let Some(callback) = callback.as_mut() {
+                    callback(false);
+                }
+            },
+        }
+    }
+
+    /// Finds modules of the same owner left behind by application instances that no longer exist,
+    /// and unloads them.
+    ///
+    /// The callback, if given, is provided with the indexes of the modules unloaded.
+    pub fn clean_up_stale(&mut self, mut callback: Option<Box<dyn FnMut(Vec<u32>) + 'static>>) {
+        let introspector = Rc::clone(&self.introspector);
+        let tag = self.tag.clone();
+        let mut stale = Vec::new();
+        self.introspector.borrow().get_module_info_list(move |result| {
+            match result {
+                ListResult::Item(info) => {
+                    let is_stale = info.get_arguments().ok()
+                        .and_then(|args| Tag::from_arguments(&args))
+                        .map_or(false, |other| other.is_stale(&tag, &process_exists));
+                    if is_stale {
+                        stale.push(info.index);
+                    }
+                },
+                ListResult::End => {
+                    for index in &stale {
+                        introspector.borrow_mut().unload_module(*index, |_| {});
+                    }
+                    if let Some(callback) = callback.as_mut() {
+                        callback(std::mem::replace(&mut stale, Vec::new()));
+                    }
+                },
+                ListResult::Error => {
+                    if let Some(callback) = callback.as_mut() {
+                        callback(Vec::new());
+                    }
+                },
+            }
+        });
+    }
+}
+
+impl Drop for Manager {
+    fn drop(&mut self) {
+        let mut introspector = self.introspector.borrow_mut();
+        for index in self.modules.borrow_mut().drain(..) {
+            introspector.unload_module(index, |_| {});
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tagging() {
+        let tag = Tag { owner: "foo".to_string(), process_id: 100, host: Some("h".to_string()) };
+        let mut args: Arguments = "sink_name=x sink_properties='device.description=\"X Y\"'"
+            .parse().unwrap();
+        tag.apply(&mut args, &["sink_properties", "sink_input_properties"]);
+        assert_eq!(args.to_string(), "sink_name=x sink_properties='device.description=\"X Y\" \
+            virtual_device.owner=foo virtual_device.owner.process.id=100 \
+            virtual_device.owner.process.host=h' sink_input_properties=\"virtual_device.owner=foo \
+            virtual_device.owner.process.id=100 virtual_device.owner.process.host=h\"");
+
+        // As the server would report it
+        let args: Arguments = args.to_string().parse().unwrap();
+        assert_eq!(Tag::from_arguments(&args), Some(tag));
+        assert_eq!(Tag::from_arguments(&"sink_name=x".parse().unwrap()), None);
+    }
+
+    #[test]
+    fn staleness() {
+        let current = Tag { owner: "foo".to_string(), process_id: 1, host: None };
+        let other = |owner: &str, process_id: u32, host: Option<&str>| Tag {
+            owner: owner.to_string(),
+            process_id: process_id,
+            host: host.map(|h| h.to_string()),
+        };
+        let exists = |pid: u32| pid == 2;
+        assert!(other("foo", 3, None).is_stale(&current, &exists));
+        assert!(!other("foo", 2, None).is_stale(&current, &exists));
+        assert!(!other("foo", 1, None).is_stale(&current, &exists));
+        assert!(!other("bar", 3, None).is_stale(&current, &exists));
+        assert!(!other("foo", 3, Some("elsewhere")).is_stale(&current, &exists));
+    }
+}
@@ -32,6 +32,7 @@ pub struct Retval(pub RetvalActual);
 /// Playback and record buffer metrics.
 #[repr(C)]
 #[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BufferAttr {
     /* NOTE: This struct must be directly usable by the C API, thus same attributes/layout/etc */
 
@@ -21,19 +21,23 @@
 //! [`Info`]: struct.Info.html
 //! [`set_encoding`]: struct.Info.html#method.set_encoding
 
+pub mod model;
+pub mod negotiate;
+
 use std::os::raw::{c_char, c_void};
 use std::ffi::{CStr, CString};
 use std::ptr::{null, null_mut};
 use std::borrow::Cow;
+use std::str::FromStr;
 use crate::{sample, channelmap};
-use crate::error::PAErr;
+use crate::error::{Code, PAErr};
 use crate::proplist::{Proplist, ProplistInternal};
 
 pub use capi::pa_prop_type_t as PropType;
 
 /// Represents the type of encoding used in a stream or accepted by a sink.
 #[repr(C)]
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 #[allow(non_camel_case_types)]
 pub enum Encoding {
     /* NOTE: This enum’s variants and variant values **must** remain identical to the `sys` crate
@@ -538,7 +542,7 @@ impl Info {
 
         // Capture array of pointers to the above CString values
         let mut c_value_ptrs: Vec<*const c_char> = Vec::with_capacity(c_values.len());
-        for v in c_values {
+        for v in &c_values {
             c_value_ptrs.push(v.as_ptr());
         }
         unsafe {
@@ -609,3 +613,68 @@ impl Clone for Info {
         Self::from_raw(ptr as *mut InfoInternal)
     }
 }
+
+impl std::fmt::Display for Encoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match Encoding::to_string(*self) {
+            Some(s) => f.write_str(&s),
+            None => f.write_str("(invalid)"),
+        }
+    }
+}
+
+/// Parses an encoding name, of the form returned by [`Encoding::to_string`].
+///
+/// Fails with `Code::Invalid` if the name is not recognised.
+///
+/// [`Encoding::to_string`]: enum.Encoding.html#method.to_string
+impl FromStr for Encoding {
+    type Err = PAErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Done here rather than with `from_string()`, which needs PA version 12
+        let known = [
+            Encoding::Any,
+            Encoding::PCM,
+            Encoding::AC3_IEC61937,
+            Encoding::EAC3_IEC61937,
+            Encoding::MPEG_IEC61937,
+            Encoding::DTS_IEC61937,
+            Encoding::MPEG2_AAC_IEC61937,
+            #[cfg(any(feature = "pa_v13", feature = "dox"))]
+            Encoding::TRUEHD_IEC61937,
+            #[cfg(any(feature = "pa_v13", feature = "dox"))]
+            Encoding::DTSHD_IEC61937,
+        ];
+        known.iter().cloned()
+            .find(|e| Encoding::to_string(*e).map_or(false, |name| name == s))
+            .ok_or(PAErr::from(Code::Invalid))
+    }
+}
+
+#[cfg(feature = "serde")]
+serde_via_string!(Encoding, "encoding");
+
+impl std::fmt::Display for Info {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&self.print())
+    }
+}
+
+/// Parses a format of the form produced by [`Info::print`], as accepted by
+/// [`Info::new_from_string`].
+///
+/// Fails with `Code::Invalid` if the string cannot be parsed.
+///
+/// [`Info::print`]: struct.Info.html#method.print
+/// [`Info::new_from_string`]: struct.Info.html#method.new_from_string
+impl FromStr for Info {
+    type Err = PAErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Info::new_from_string(s).ok_or(PAErr::from(Code::Invalid))
+    }
+}
+
+#[cfg(feature = "serde")]
+serde_via_string!(Info, "format");
@@ -0,0 +1,431 @@
+// Copyright 2020 Lyndon Brown
+//
+// This file is part of the PulseAudio Rust language binding.
+//
+// Licensed under the MIT license or the Apache license (version 2.0), at your option. You may not
+// copy, modify, or distribute this file except in compliance with said license. You can find copies
+// of these licenses either in the LICENSE-MIT and LICENSE-APACHE files, or alternatively at
+// <http://opensource.org/licenses/MIT> and <http://www.apache.org/licenses/LICENSE-2.0>
+// respectively.
+//
+// Portions of documentation are copied from the LGPL 2.1+ licensed PulseAudio C headers on a
+// fair-use basis, as discussed in the overall project readme (available in the git repository).
+
+//! Typed view of formats.
+//!
+//! # Overview
+//!
+//! The properties of an [`Info`] object are stored as JSON-encoded strings, accessed by raw key
+//! with methods such as [`Info::get_prop_int_range`]. A [`Description`] decodes these into typed
+//! fields: the sample format, rate and channel count, each of which may be a fixed value, a list of
+//! values or a range (see [`Constraint`]), and the channel map. Any other properties, such as
+//! those of IEC 61937 encapsulated formats, are kept in a map of [`Property`] values.
+//!
+//! Properties that cannot be decoded into a typed field, for instance a sample format name not
+//! known to this binding, are kept as other properties instead, such that converting back with
+//! [`Description::to_info`] gives an equivalent `Info` object.
+//!
+//! `Info` implements `PartialEq` and `Hash` by comparison of descriptions.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use pulse::format::model::{Constraint, Description};
+//!
+//! for info in &sink_info.formats {
+//!     let description = Description::from(info);
+//!     if let Some(Constraint::Range(min, max)) = description.rate {
+//!         println!("{}: {} to {} Hz", description.encoding, min, max);
+//!     }
+//! }
+//! ```
+//!
+//! [`Info`]: ../struct.Info.html
+//! [`Info::get_prop_int_range`]: ../struct.Info.html#method.get_prop_int_range
+//! [`Description`]: struct.Description.html
+//! [`Description::to_info`]: struct.Description.html#method.to_info
+//! [`Constraint`]: enum.Constraint.html
+//! [`Property`]: enum.Property.html
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::hash::{Hash, Hasher};
+use crate::channelmap;
+use crate::error::{Code, PAErr};
+use crate::proplist::properties;
+use crate::proplist::map::Value;
+use crate::sample;
+use super::{Encoding, Info, PropType};
+
+/// The values permitted for a property.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Constraint<T> {
+    /// A single value.
+    Fixed(T),
+    /// Any of a list of values.
+    List(Vec<T>),
+    /// Any value within an inclusive range (minimum and maximum). Not applicable to sample formats.
+    Range(T, T),
+}
+
+impl<T> Constraint<T>
+    where T: PartialOrd
+{
+    /// Checks if the given value is permitted.
+    pub fn contains(&self, value: &T) -> bool {
+        match self {
+            Constraint::Fixed(v) => v == value,
+            Constraint::List(values) => values.contains(value),
+            Constraint::Range(min, max) => value >= min && value <= max,
+        }
+    }
+}
+
+/// A format property, other than those with dedicated fields in a [`Description`].
+///
+/// [`Description`]: struct.Description.html
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Property {
+    /// An integer.
+    Int(i32),
+    /// An inclusive integer range (minimum and maximum).
+    IntRange(i32, i32),
+    /// A list of integers.
+    IntArray(Vec<i32>),
+    /// A string.
+    String(String),
+    /// A list of strings.
+    StringArray(Vec<String>),
+    /// A value not in the JSON form used for format properties, preserved as is.
+    Raw(Value),
+}
+
+/// Typed description of a format.
+///
+/// Descriptions are compared and hashed with channel maps taken by their positions, unlike the
+/// `PartialEq` implementation of `channelmap::Map`.
+#[derive(Debug, Clone)]
+pub struct Description {
+    /// The encoding.
+    pub encoding: Encoding,
+    /// Sample format(s), for PCM formats.
+    pub sample_format: Option<Constraint<sample::Format>>,
+    /// Sample rate(s).
+    pub rate: Option<Constraint<u32>>,
+    /// Channel count(s).
+    pub channels: Option<Constraint<u8>>,
+    /// Channel map, for PCM formats.
+    pub channel_map: Option<channelmap::Map>,
+    /// Other properties, by key.
+    pub extra: BTreeMap<String, Property>,
+}
+
+impl Property {
+    /// Reads a property from the given format, returning `None` if it does not exist.
+    fn from_info(info: &Info, key: &str) -> Option<Self> {
+        let decoded = match info.get_prop_type(key) {
+            PropType::Int => info.get_prop_int(key).ok().map(Property::Int),
+            PropType::IntRange => info.get_prop_int_range(key).ok()
+                .map(|(min, max)| Property::IntRange(min, max)),
+            PropType::IntArray => info.get_prop_int_array(key).map(Property::IntArray),
+            PropType::String => info.get_prop_string(key).map(Property::String),
+            PropType::StringArray => info.get_prop_string_array(key).map(Property::StringArray),
+            _ => None,
+        };
+        decoded.or_else(|| info.get_properties().get_value(key).map(Property::Raw))
+    }
+
+    /// Writes the property to the given format.
+    fn set(&self, info: &mut Info, key: &str) -> Result<(), PAErr> {
+        match self {
+            Property::Int(v) => info.set_prop_int(key, *v),
+            Property::IntRange(min, max) => info.set_prop_int_range(key, *min, *max),
+            Property::IntArray(values) => info.set_prop_int_array(key, values),
+            Property::String(s) => info.set_prop_string(key, s),
+            Property::StringArray(values) => {
+                let values: Vec<&str> = values.iter().map(|s| s.as_str()).collect();
+                info.set_prop_string_array(key, &values);
+            },
+            Property::Raw(value) => {
+                info.get_properties_mut().set_value(key, value)
+                    .or(Err(PAErr::from(Code::Invalid)))?;
+            },
+        }
+        Ok(())
+    }
+}
+
+impl Description {
+    /// Creates a description of the given encoding, without properties.
+    pub fn new(encoding: Encoding) -> Self {
+        Self {
+            encoding: encoding,
+            sample_format: None,
+            rate: None,
+            channels: None,
+            channel_map: None,
+            extra: BTreeMap::new(),
+        }
+    }
+
+    /// Gets the positions of the channel map, if any.
+    fn channel_positions(&self) -> Option<&[channelmap::Position]> {
+        self.channel_map.as_ref().map(|map| map.get())
+    }
+
+    /// Creates an equivalent `Info` object.
+    ///
+    /// Returns an error of `Code::Invalid` if a sample format is invalid, or given as a range.
+    pub fn to_info(&self) -> Result<Info, PAErr> {
+        let mut info = Info::new().ok_or(PAErr::from(Code::Internal))?;
+        info.set_encoding(self.encoding);
+
+        if let Some(constraint) = &self.sample_format {
+            let name = |f: &sample::Format| f.to_string().ok_or(PAErr::from(Code::Invalid));
+            let key = properties::FORMAT_SAMPLE_FORMAT;
+            match constraint {
+                Constraint::Fixed(f) => info.set_prop_string(key, &name(f)?),
+                Constraint::List(formats) => {
+                    let names = formats.iter().map(name).collect::<Result<Vec<_>, _>>()?;
+                    let names: Vec<&str> = names.iter().map(|s| s.as_ref()).collect();
+                    info.set_prop_string_array(key, &names);
+                },
+                Constraint::Range(_, _) => return Err(PAErr::from(Code::Invalid)),
+            }
+        }
+        if let Some(constraint) = &self.rate {
+            set_ints(&mut info, properties::FORMAT_RATE, constraint, |r| r as i32);
+        }
+        if let Some(constraint) = &self.channels {
+            set_ints(&mut info, properties::FORMAT_CHANNELS, constraint, i32::from);
+        }
+        if let Some(map) = &self.channel_map {
+            info.set_channel_map(map);
+        }
+        for (key, property) in &self.extra {
+            property.set(&mut info, key)?;
+        }
+        Ok(info)
+    }
+}
+
+impl PartialEq for Description {
+    fn eq(&self, other: &Self) -> bool {
+        self.encoding == other.encoding &&
+        self.sample_format == other.sample_format &&
+        self.rate == other.rate &&
+        self.channels == other.channels &&
+        self.channel_positions() == other.channel_positions() &&
+        self.extra == other.extra
+    }
+}
+
+impl Eq for Description {}
+
+impl Hash for Description {
+    fn hash<H>(&self, state: &mut H)
+        where H: Hasher
+    {
+        self.encoding.hash(state);
+        self.sample_format.hash(state);
+        self.rate.hash(state);
+        self.channels.hash(state);
+        self.channel_positions().hash(state);
+        self.extra.hash(state);
+    }
+}
+
+impl From<&Info> for Description {
+    fn from(info: &Info) -> Self {
+        let mut description = Description::new(info.get_encoding());
+        for key in info.get_properties().iter() {
+            let decoded = match key.as_str() {
+                k if k == properties::FORMAT_SAMPLE_FORMAT => {
+                    description.sample_format = get_sample_formats(info, k);
+                    description.sample_format.is_some()
+                },
+                k if k == properties::FORMAT_RATE => {
+                    description.rate = get_ints(info, k);
+                    description.rate.is_some()
+                },
+                k if k == properties::FORMAT_CHANNELS => {
+                    description.channels = get_ints(info, k);
+                    description.channels.is_some()
+                },
+                k if k == properties::FORMAT_CHANNEL_MAP => {
+                    description.channel_map = info.get_prop_string(k)
+                        .and_then(|s| s.parse().ok());
+                    description.channel_map.is_some()
+                },
+                _ => false,
+            };
+            if !decoded {
+                if let Some(property) = Property::from_info(info, &key) {
+                    description.extra.insert(key, property);
+                }
+            }
+        }
+        description
+    }
+}
+
+/// Reads an integer property, returning `None` if it is not an integer, range or list thereof, or
+/// a value is out of range for `T`.
+fn get_ints<T>(info: &Info, key: &str) -> Option<Constraint<T>>
+    where T: TryFrom<i32>
+{
+    let conv = |v: i32| T::try_from(v).ok();
+    match info.get_prop_type(key) {
+        PropType::Int => conv(info.get_prop_int(key).ok()?).map(Constraint::Fixed),
+        PropType::IntRange => {
+            let (min, max) = info.get_prop_int_range(key).ok()?;
+            Some(Constraint::Range(conv(min)?, conv(max)?))
+        },
+        PropType::IntArray => info.get_prop_int_array(key)?.into_iter().map(conv)
+            .collect::<Option<Vec<T>>>().map(Constraint::List),
+        _ => None,
+    }
+}
+
+fn set_ints<T, F>(info: &mut Info, key: &str, constraint: &Constraint<T>, conv: F)
+    where T: Copy,
+          F: Fn(T) -> i32
+{
+    match constraint {
+        Constraint::Fixed(v) => info.set_prop_int(key, conv(*v)),
+        Constraint::List(values) => {
+            let values: Vec<i32> = values.iter().map(|v| conv(*v)).collect();
+            info.set_prop_int_array(key, &values);
+        },
+        Constraint::Range(min, max) => info.set_prop_int_range(key, conv(*min), conv(*max)),
+    }
+}
+
+/// Reads the sample format property, returning `None` if it is not a string or list of strings,
+/// or a name is not recognised.
+fn get_sample_formats(info: &Info, key: &str) -> Option<Constraint<sample::Format>> {
+    match info.get_prop_type(key) {
+        PropType::String => info.get_prop_string(key)?.parse().ok().map(Constraint::Fixed),
+        PropType::StringArray => info.get_prop_string_array(key)?.iter()
+            .map(|name| name.parse().ok())
+            .collect::<Option<Vec<sample::Format>>>().map(Constraint::List),
+        _ => None,
+    }
+}
+
+impl Info {
+    /// Gets a typed description of the format.
+    ///
+    /// See the [`model`](model/index.html) module documentation for details.
+    #[inline]
+    pub fn to_description(&self) -> Description {
+        Description::from(self)
+    }
+}
+
+impl PartialEq for Info {
+    /// Compares formats by their [`Description`](model/struct.Description.html)s.
+    fn eq(&self, other: &Self) -> bool {
+        self.to_description() == other.to_description()
+    }
+}
+
+impl Eq for Info {}
+
+impl Hash for Info {
+    fn hash<H>(&self, state: &mut H)
+        where H: Hasher
+    {
+        self.to_description().hash(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::hash_map::DefaultHasher;
+    use super::*;
+    use crate::test_util::front_map;
+
+    fn hash(info: &Info) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        info.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn pcm_info() -> Info {
+        let mut info = Info::new().unwrap();
+        info.set_encoding(Encoding::PCM);
+        info.set_prop_string_array(properties::FORMAT_SAMPLE_FORMAT, &["s16le", "float32le"]);
+        info.set_prop_int_range(properties::FORMAT_RATE, 8000, 48000);
+        info.set_prop_int(properties::FORMAT_CHANNELS, 2);
+        info.set_prop_string("format.custom", "value");
+        info
+    }
+
+    #[test]
+    fn constraints() {
+        assert!(Constraint::Fixed(44100).contains(&44100));
+        assert!(!Constraint::List(vec![32000, 48000]).contains(&44100));
+        assert!(Constraint::Range(8000, 192000).contains(&44100));
+        assert!(!Constraint::Range(8000, 192000).contains(&384000));
+    }
+
+    #[test]
+    fn round_trip() {
+        let info = pcm_info();
+        let description = info.to_description();
+        assert_eq!(description.encoding, Encoding::PCM);
+        assert_eq!(description.sample_format,
+            Some(Constraint::List(vec![sample::Format::S16le, sample::Format::F32le])));
+        assert_eq!(description.rate, Some(Constraint::Range(8000, 48000)));
+        assert_eq!(description.channels, Some(Constraint::Fixed(2)));
+        assert_eq!(description.channel_map, None);
+        assert_eq!(description.extra.get("format.custom"),
+            Some(&Property::String("value".to_string())));
+
+        let converted = description.to_info().unwrap();
+        assert_eq!(converted.to_description(), description);
+        assert_eq!(converted.get_properties().len(), info.get_properties().len());
+    }
+
+    #[test]
+    fn undecodable_kept() {
+        let mut info = Info::new().unwrap();
+        info.set_encoding(Encoding::PCM);
+        info.set_prop_string(properties::FORMAT_SAMPLE_FORMAT, "s99le");
+        info.set_prop_int(properties::FORMAT_RATE, -1);
+
+        let description = info.to_description();
+        assert_eq!(description.sample_format, None);
+        assert_eq!(description.rate, None);
+        assert_eq!(description.extra.get(properties::FORMAT_SAMPLE_FORMAT),
+            Some(&Property::String("s99le".to_string())));
+        assert_eq!(description.extra.get(properties::FORMAT_RATE), Some(&Property::Int(-1)));
+        assert_eq!(description.to_info().unwrap(), info);
+    }
+
+    #[test]
+    fn equality() {
+        let a = pcm_info();
+        let mut b = pcm_info();
+        assert_eq!(a, b);
+        assert_eq!(hash(&a), hash(&b));
+
+        b.set_prop_int(properties::FORMAT_CHANNELS, 1);
+        assert_ne!(a, b);
+
+        let mut c = a.clone();
+        c.set_encoding(Encoding::AC3_IEC61937);
+        assert_ne!(a, c);
+
+        // Channel maps are compared by position, thus even an invalid map equals itself
+        let mut description = Description::new(Encoding::PCM);
+        description.channel_map = Some(channelmap::Map::default());
+        assert_eq!(description, description.clone());
+        description.channel_map = Some(front_map(2));
+        assert_eq!(description, description.clone());
+        let mut other = description.clone();
+        other.channel_map = Some(front_map(3));
+        assert_ne!(description, other);
+    }
+}
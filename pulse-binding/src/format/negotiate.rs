@@ -0,0 +1,392 @@
+// Copyright 2020 Lyndon Brown
+//
+// This file is part of the PulseAudio Rust language binding.
+//
+// Licensed under the MIT license or the Apache license (version 2.0), at your option. You may not
+// copy, modify, or distribute this file except in compliance with said license. You can find copies
+// of these licenses either in the LICENSE-MIT and LICENSE-APACHE files, or alternatively at
+// <http://opensource.org/licenses/MIT> and <http://www.apache.org/licenses/LICENSE-2.0>
+// respectively.
+//
+// Portions of documentation are copied from the LGPL 2.1+ licensed PulseAudio C headers on a
+// fair-use basis, as discussed in the overall project readme (available in the git repository).
+
+//! Format negotiation.
+//!
+//! # Overview
+//!
+//! Sinks advertise the formats they accept (see [`SinkInfo::formats`]), with properties such as
+//! the sample rate possibly given as a range or set of values rather than a single value. A
+//! [`Negotiator`] matches a ranked list of formats supported by the application, described by
+//! [`Preference`] objects, against those of a sink, choosing the most preferred compatible format,
+//! with concrete values filled in for such properties, ready to be given to
+//! [`Stream::new_extended`].
+//!
+//! Where both the application and the sink accept any value for a property, it is left unspecified,
+//! for the server to choose. Where the application accepts any value but the sink does not, the
+//! permitted value closest to a common default (48 kHz, two channels) is chosen.
+//!
+//! Once the stream is connected, [`Negotiator::get_result`] reports the format actually chosen by
+//! the server.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use pulse::format::Encoding;
+//! use pulse::format::negotiate::{Negotiator, Preference};
+//!
+//! let negotiator = Negotiator::new(vec![
+//!     Preference::encoded(Encoding::EAC3_IEC61937, &[48000]),
+//!     Preference::encoded(Encoding::AC3_IEC61937, &[48000]),
+//!     Preference::pcm(&spec, None),
+//! ]);
+//! negotiator.negotiate(&context, "@DEFAULT_SINK@", Box::new(move |result| {
+//!     if let Ok(choice) = result {
+//!         let stream = Stream::new_extended(&mut context, "Playback", &[&choice.format], &mut
+//!             proplist);
+//!         // ...
+//!     }
+//! }));
+//! ```
+//!
+//! [`SinkInfo::formats`]: ../../context/introspect/struct.SinkInfo.html#structfield.formats
+//! [`Negotiator`]: struct.Negotiator.html
+//! [`Preference`]: struct.Preference.html
+//! [`Stream::new_extended`]: ../../stream/struct.Stream.html#method.new_extended
+//! [`Negotiator::get_result`]: struct.Negotiator.html#method.get_result
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use crate::callbacks::ListResult;
+use crate::channelmap;
+use crate::context::Context;
+use crate::error::{Code, PAErr};
+use crate::proplist::properties;
+use crate::sample;
+use crate::stream::Stream;
+use super::{Encoding, Info, PropType};
+
+/// Rate chosen when any is acceptable to the application, but not to the sink, or the closest to
+/// it.
+const DEFAULT_RATE: i32 = 48000;
+
+/// Channel count chosen when any is acceptable to the application, but not to the sink, or the
+/// closest to it.
+const DEFAULT_CHANNELS: i32 = 2;
+
+/// A format supported by the application.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Preference {
+    /// The encoding.
+    pub encoding: Encoding,
+    /// Acceptable sample formats (PCM only), in order of preference. Empty if any is acceptable.
+    pub sample_formats: Vec<sample::Format>,
+    /// Acceptable sample rates, in order of preference. Empty if any is acceptable.
+    pub rates: Vec<u32>,
+    /// Acceptable channel counts, in order of preference. Empty if any is acceptable.
+    pub channels: Vec<u8>,
+    /// Channel map to use (PCM only), if the chosen channel count matches.
+    pub channel_map: Option<channelmap::Map>,
+}
+
+impl Preference {
+    /// Creates a preference for PCM data of the given specification, and optionally channel map.
+    pub fn pcm(spec: &sample::Spec, map: Option<&channelmap::Map>) -> Self {
+        Self {
+            encoding: Encoding::PCM,
+            sample_formats: vec![spec.format],
+            rates: vec![spec.rate],
+            channels: vec![spec.channels],
+            channel_map: map.cloned(),
+        }
+    }
+
+    /// Creates a preference for a compressed encoding, at one of the given sample rates (in order
+    /// of preference, or empty if any is acceptable).
+    pub fn encoded(encoding: Encoding, rates: &[u32]) -> Self {
+        Self {
+            encoding: encoding,
+            sample_formats: Vec::new(),
+            rates: rates.to_vec(),
+            channels: Vec::new(),
+            channel_map: None,
+        }
+    }
+}
+
+/// The outcome of negotiation.
+#[derive(Debug, Clone)]
+pub struct Choice {
+    /// Index of the preference chosen.
+    pub preference: usize,
+    /// The format, with concrete values filled in.
+    pub format: Info,
+}
+
+/// Values permitted for an integer format property.
+#[derive(Debug, Clone, PartialEq)]
+enum Allowed {
+    Any,
+    Values(Vec<i32>),
+    Range(i32, i32),
+}
+
+/// A format offered by a sink, as relevant to negotiation.
+#[derive(Debug, Clone, PartialEq)]
+struct Offer {
+    encoding: Encoding,
+    /// Permitted sample formats, or `None` if any is.
+    sample_formats: Option<Vec<sample::Format>>,
+    rates: Allowed,
+    channels: Allowed,
+}
+
+/// Concrete values chosen for a preference, `None` being left for the server to choose.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Selection {
+    sample_format: Option<sample::Format>,
+    rate: Option<u32>,
+    channels: Option<u8>,
+}
+
+impl Allowed {
+    fn from_info(info: &Info, key: &str) -> Self {
+        match info.get_prop_type(key) {
+            PropType::Int => info.get_prop_int(key)
+                .map_or(Allowed::Any, |v| Allowed::Values(vec![v])),
+            PropType::IntRange => info.get_prop_int_range(key)
+                .map_or(Allowed::Any, |(min, max)| Allowed::Range(min, max)),
+            PropType::IntArray => info.get_prop_int_array(key)
+                .map_or(Allowed::Any, Allowed::Values),
+            _ => Allowed::Any,
+        }
+    }
+
+    fn contains(&self, value: i32) -> bool {
+        match self {
+            Allowed::Any => true,
+            Allowed::Values(values) => values.contains(&value),
+            Allowed::Range(min, max) => value >= *min && value <= *max,
+        }
+    }
+
+    /// Chooses a value, the first of `wanted` that is permitted, or if `wanted` is empty, the
+    /// permitted value closest to `default` (or `None` if any is permitted).
+    ///
+    /// Returns `Err` if none of `wanted` is permitted.
+    fn choose(&self, wanted: &[i32], default: i32) -> Result<Option<i32>, ()> {
+        if !wanted.is_empty() {
+            return wanted.iter().cloned().find(|v| self.contains(*v)).map(Some).ok_or(());
+        }
+        match self {
+            Allowed::Any => Ok(None),
+            Allowed::Values(values) => values.iter().cloned()
+                .min_by_key(|v| (i64::from(*v) - i64::from(default)).abs())
+                .map(Some).ok_or(()),
+            Allowed::Range(min, max) if min <= max => Ok(Some(default.max(*min).min(*max))),
+            Allowed::Range(_, _) => Err(()),
+        }
+    }
+}
+
+impl Offer {
+    fn from_info(info: &Info) -> Self {
+        let key = properties::FORMAT_SAMPLE_FORMAT;
+        let names = match info.get_prop_type(key) {
+            PropType::String => info.get_prop_string(key).map(|s| vec![s]),
+            PropType::StringArray => info.get_prop_string_array(key),
+            _ => None,
+        };
+        Self {
+            encoding: info.get_encoding(),
+            sample_formats: names.map(|names| {
+                names.iter().filter_map(|name| name.parse().ok()).collect()
+            }),
+            rates: Allowed::from_info(info, properties::FORMAT_RATE),
+            channels: Allowed::from_info(info, properties::FORMAT_CHANNELS),
+        }
+    }
+
+    /// Chooses concrete values for the given preference, if compatible.
+    fn select(&self, pref: &Preference) -> Option<Selection> {
+        if self.encoding != pref.encoding && self.encoding != Encoding::Any {
+            return None;
+        }
+        let sample_format = match (pref.encoding, &self.sample_formats) {
+            (Encoding::PCM, Some(offered)) => match pref.sample_formats.is_empty() {
+                true => Some(*offered.first()?),
+                false => Some(*pref.sample_formats.iter().find(|f| offered.contains(f))?),
+            },
+            (Encoding::PCM, None) => pref.sample_formats.first().cloned(),
+            _ => None,
+        };
+        let rates: Vec<i32> = pref.rates.iter().map(|r| *r as i32).collect();
+        let rate = self.rates.choose(&rates, DEFAULT_RATE).ok()?;
+        let channels: Vec<i32> = pref.channels.iter().map(|c| i32::from(*c)).collect();
+        let channels = self.channels.choose(&channels, DEFAULT_CHANNELS).ok()?;
+        Some(Selection {
+            sample_format: sample_format,
+            rate: rate.map(|r| r as u32),
+            channels: channels.map(|c| c as u8),
+        })
+    }
+}
+
+/// Chooses the most preferred format compatible with any of those offered, returning the index of
+/// the preference and the values chosen.
+fn select(preferences: &[Preference], offers: &[Offer]) -> Option<(usize, Selection)> {
+    preferences.iter().enumerate()
+        .filter_map(|(i, pref)| offers.iter().find_map(|o| o.select(pref)).map(|s| (i, s)))
+        .next()
+}
+
+/// Matches application supported formats against those of sinks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Negotiator {
+    preferences: Vec<Preference>,
+}
+
+impl Negotiator {
+    /// Creates a new negotiator for the given formats, in order of preference.
+    pub fn new(preferences: Vec<Preference>) -> Self {
+        Self { preferences: preferences }
+    }
+
+    /// Gets the formats, in order of preference.
+    #[inline]
+    pub fn get_preferences(&self) -> &[Preference] {
+        &self.preferences
+    }
+
+    /// Chooses the most preferred format compatible with any of the given formats (as offered by a
+    /// sink).
+    ///
+    /// Returns `None` if none is compatible.
+    pub fn choose(&self, offered: &[Info]) -> Option<Choice> {
+        let offers: Vec<Offer> = offered.iter().map(Offer::from_info).collect();
+        let (index, selection) = select(&self.preferences, &offers)?;
+        let pref = &self.preferences[index];
+
+        let mut format = Info::new()?;
+        format.set_encoding(pref.encoding);
+        if let Some(sample_format) = selection.sample_format {
+            format.set_sample_format(sample_format);
+        }
+        if let Some(rate) = selection.rate {
+            format.set_rate(rate as i32);
+        }
+        if let Some(channels) = selection.channels {
+            format.set_channels(u32::from(channels));
+            if let Some(map) = pref.channel_map.as_ref().filter(|m| m.len() == channels) {
+                format.set_channel_map(map);
+            }
+        }
+        Some(Choice { preference: index, format: format })
+    }
+
+    /// Queries the formats of the given sink (which may be `@DEFAULT_SINK@`), and chooses the most
+    /// preferred compatible format, as [`choose`](#method.choose) does.
+    ///
+    /// The callback is given the choice, or an error of `Code::NoEntity` if the sink does not
+    /// exist, or `Code::NotSupported` if no format is compatible.
+    pub fn negotiate(&self, context: &Context, sink: &str,
+        callback: Box<dyn FnMut(Result<Choice, PAErr>) + 'static>)
+    {
+        let negotiator = self.clone();
+        let callback = Rc::new(RefCell::new(callback));
+        context.introspect().get_sink_info_by_name(sink, move |result| {
+            let info = match result {
+                ListResult::Item(info) => info,
+                ListResult::End => return,
+                ListResult::Error => {
+                    return (callback.borrow_mut())(Err(PAErr::from(Code::NoEntity)));
+                },
+            };
+            let choice = negotiator.choose(&info.formats).ok_or(PAErr::from(Code::NotSupported));
+            (callback.borrow_mut())(choice);
+        });
+    }
+
+    /// Gets the format actually chosen by the server for a connected stream, along with the
+    /// preference it corresponds to.
+    ///
+    /// Returns `None` if the stream is not connected, or its format corresponds to none of the
+    /// preferences.
+    pub fn get_result(&self, stream: &Stream) -> Option<Choice> {
+        let format = stream.get_format_info()?.clone();
+        let (index, _) = select(&self.preferences, &[Offer::from_info(&format)])?;
+        Some(Choice { preference: index, format: format })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pcm_offer(formats: Option<Vec<sample::Format>>, rates: Allowed, channels: Allowed)
+        -> Offer
+    {
+        Offer {
+            encoding: Encoding::PCM,
+            sample_formats: formats,
+            rates: rates,
+            channels: channels,
+        }
+    }
+
+    #[test]
+    fn allowed() {
+        let range = Allowed::Range(32000, 44100);
+        assert_eq!(range.choose(&[], DEFAULT_RATE), Ok(Some(44100)));
+        assert_eq!(range.choose(&[48000, 32000], DEFAULT_RATE), Ok(Some(32000)));
+        assert_eq!(range.choose(&[48000], DEFAULT_RATE), Err(()));
+        let values = Allowed::Values(vec![1, 6, 8]);
+        assert_eq!(values.choose(&[], DEFAULT_CHANNELS), Ok(Some(1)));
+        assert_eq!(values.choose(&[2, 6], DEFAULT_CHANNELS), Ok(Some(6)));
+        assert_eq!(Allowed::Any.choose(&[], DEFAULT_RATE), Ok(None));
+        assert_eq!(Allowed::Any.choose(&[44100], DEFAULT_RATE), Ok(Some(44100)));
+    }
+
+    #[test]
+    fn ranking() {
+        let spec = sample::Spec { format: sample::Format::S16le, rate: 44100, channels: 2 };
+        let prefs = vec![
+            Preference::encoded(Encoding::AC3_IEC61937, &[48000]),
+            Preference::pcm(&spec, None),
+        ];
+
+        // PCM only sink, with rate fixed by the application
+        let offers = vec![pcm_offer(None, Allowed::Any, Allowed::Any)];
+        let (index, sel) = select(&prefs, &offers).unwrap();
+        assert_eq!(index, 1);
+        assert_eq!(sel, Selection {
+            sample_format: Some(sample::Format::S16le), rate: Some(44100), channels: Some(2) });
+
+        // Passthrough preferred when available
+        let offers = vec![
+            pcm_offer(None, Allowed::Any, Allowed::Any),
+            Offer {
+                encoding: Encoding::AC3_IEC61937,
+                sample_formats: None,
+                rates: Allowed::Values(vec![32000, 44100, 48000]),
+                channels: Allowed::Any,
+            },
+        ];
+        let (index, sel) = select(&prefs, &offers).unwrap();
+        assert_eq!(index, 0);
+        assert_eq!(sel, Selection { sample_format: None, rate: Some(48000), channels: None });
+
+        // Incompatible sample format
+        let offers = vec![pcm_offer(Some(vec![sample::Format::F32le]), Allowed::Any, Allowed::Any)];
+        assert_eq!(select(&prefs, &offers), None);
+    }
+
+    #[test]
+    fn fill_from_ranges() {
+        let pref = Preference::encoded(Encoding::PCM, &[]);
+        let offer = pcm_offer(Some(vec![sample::Format::F32le, sample::Format::S16le]),
+            Allowed::Range(8000, 192000), Allowed::Values(vec![1, 2, 6]));
+        assert_eq!(offer.select(&pref), Some(Selection {
+            sample_format: Some(sample::Format::F32le), rate: Some(48000), channels: Some(2) }));
+    }
+}
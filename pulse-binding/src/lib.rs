@@ -118,6 +118,31 @@
 extern crate libc;
 extern crate libpulse_sys as capi;
 
+/// Implements `serde` serialization for a type as a string, using its `Display` and `FromStr`
+/// implementations.
+#[cfg(feature = "serde")]
+macro_rules! serde_via_string {
+    ( $t:ty, $what:expr ) => {
+        impl serde::Serialize for $t {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+                where S: serde::Serializer
+            {
+                serializer.collect_str(self)
+            }
+        }
+
+        impl<'de> serde::Deserialize<'de> for $t {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+                where D: serde::Deserializer<'de>
+            {
+                let s = String::deserialize(deserializer)?;
+                s.parse().map_err(|_| serde::de::Error::custom(format!("invalid {}: {:?}", $what,
+                    s)))
+            }
+        }
+    };
+}
+
 pub mod callbacks;
 pub mod channelmap;
 pub mod context;
@@ -135,3 +160,6 @@ pub mod utf8;
 pub mod util;
 pub mod version;
 pub mod volume;
+
+#[cfg(test)]
+mod test_util;
@@ -296,6 +296,20 @@ pub trait Mainloop {
     }
 }
 
+/// A mainloop handle built from a shared mainloop inner object.
+///
+/// This allows helpers to create event sources from within callbacks, where the original mainloop
+/// object is not available.
+pub(crate) struct MainloopRef<T: MainloopInnerType>(pub(crate) Rc<T>);
+
+impl<T: MainloopInnerType> Mainloop for MainloopRef<T> {
+    type MI = T;
+
+    fn inner(&self) -> Rc<T> {
+        Rc::clone(&self.0)
+    }
+}
+
 /// An IO event callback prototype.
 pub type IoEventCb = extern "C" fn(a: *const MainloopApi, e: *mut IoEventInternal, fd: i32,
     events: IoEventFlagSet, userdata: *mut c_void);
@@ -0,0 +1,313 @@
+// Copyright 2020 Lyndon Brown
+//
+// This file is part of the PulseAudio Rust language binding.
+//
+// Licensed under the MIT license or the Apache license (version 2.0), at your option. You may not
+// copy, modify, or distribute this file except in compliance with said license. You can find copies
+// of these licenses either in the LICENSE-MIT and LICENSE-APACHE files, or alternatively at
+// <http://opensource.org/licenses/MIT> and <http://www.apache.org/licenses/LICENSE-2.0>
+// respectively.
+//
+// Portions of documentation are copied from the LGPL 2.1+ licensed PulseAudio C headers on a
+// fair-use basis, as discussed in the overall project readme (available in the git repository).
+
+//! Automatic population of application properties.
+//!
+//! # Overview
+//!
+//! Clients are expected to describe themselves with the `application.*` properties of
+//! [`properties`]. An [`Info`] object holds the details only the application itself can know, such
+//! as its name and version, and [`Proplist::new_for_application`] creates a property list from it,
+//! filling in the rest from the process environment:
+//!
+//! * `application.name` falls back to the binary name, as given by [`util::get_binary_name`].
+//! * `application.id` and `application.icon_name` fall back to the XDG desktop file ID, if given,
+//!   less any `.desktop` suffix.
+//! * `application.language` falls back to the locale, from the `LC_ALL`, `LC_MESSAGES` and `LANG`
+//!   environment variables, in that order of precedence.
+//! * `application.process.*` are taken from the process ID, [`util::get_binary_name`],
+//!   [`util::get_user_name`], [`util::get_host_name`], the system machine ID and the
+//!   `XDG_SESSION_ID` environment variable.
+//!
+//! Values set explicitly in the [`Info`] object take precedence over detected ones. When filling an
+//! existing property list with [`Proplist::fill_application`], explicitly set values replace any
+//! entries it already holds, whereas detected values do not.
+//!
+//! The [`application_info!`] macro creates an [`Info`] object with the name and version of the
+//! Cargo package from which it is used.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use pulse::proplist::Proplist;
+//!
+//! let mut info = pulse::application_info!();
+//! info.desktop_id = Some("org.example.FooApp.desktop".to_string());
+//!
+//! let proplist = Proplist::new_for_application(&info).unwrap();
+//! let context = Context::new_with_proplist(&mainloop, "FooApp", &proplist).unwrap();
+//! ```
+//!
+//! [`properties`]: ../properties/index.html
+//! [`Info`]: struct.Info.html
+//! [`Proplist::new_for_application`]: ../struct.Proplist.html#method.new_for_application
+//! [`Proplist::fill_application`]: ../struct.Proplist.html#method.fill_application
+//! [`util::get_binary_name`]: ../../util/fn.get_binary_name.html
+//! [`util::get_user_name`]: ../../util/fn.get_user_name.html
+//! [`util::get_host_name`]: ../../util/fn.get_host_name.html
+//! [`application_info!`]: ../../macro.application_info.html
+
+use crate::error::{Code, PAErr};
+use crate::util;
+use super::{properties, Proplist};
+
+/// Buffer size used for querying names from the system.
+const NAME_MAX: usize = 256;
+
+/// Files that may hold the machine ID, in order of preference.
+const MACHINE_ID_FILES: &[&str] = &["/etc/machine-id", "/var/lib/dbus/machine-id"];
+
+/// Creates an application [`Info`](proplist/application/struct.Info.html) object, with the name
+/// and version of the Cargo package being built.
+///
+/// Other fields can be set on the returned object as desired.
+#[macro_export]
+macro_rules! application_info {
+    () => {
+        $crate::proplist::application::Info::new(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
+    };
+}
+
+/// Application details, used for filling in application properties.
+///
+/// Fields left as `None` are filled from the process environment, where possible.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Info {
+    /// Application name (`application.name`).
+    pub name: Option<String>,
+    /// Application ID, in reverse domain name form (`application.id`).
+    pub id: Option<String>,
+    /// Application version (`application.version`).
+    pub version: Option<String>,
+    /// Icon name, per the XDG icon naming specification (`application.icon_name`).
+    pub icon_name: Option<String>,
+    /// Locale, e.g. `en_GB` (`application.language`).
+    pub language: Option<String>,
+    /// XDG desktop file ID, e.g. `org.example.FooApp.desktop`, used as a fallback for the
+    /// application ID and icon name.
+    pub desktop_id: Option<String>,
+}
+
+/// Details detected from the process environment.
+#[derive(Debug, Clone, Default)]
+struct Environment {
+    process_id: u32,
+    binary: Option<String>,
+    user: Option<String>,
+    host: Option<String>,
+    machine_id: Option<String>,
+    session_id: Option<String>,
+    language: Option<String>,
+}
+
+impl Info {
+    /// Creates a new object with the given name and version.
+    ///
+    /// See also the [`application_info!`](../../macro.application_info.html) macro.
+    pub fn new(name: &str, version: &str) -> Self {
+        Self {
+            name: Some(name.to_string()),
+            version: Some(version.to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// Gets the property entries for these details, falling back to the given environment.
+    ///
+    /// Each entry is paired with whether it was given explicitly, rather than detected.
+    fn entries(&self, env: &Environment) -> Vec<(&'static str, String, bool)> {
+        let desktop_id = self.desktop_id.as_ref()
+            .map(|id| match id.ends_with(".desktop") {
+                true => id[..id.len() - ".desktop".len()].to_string(),
+                false => id.to_string(),
+            })
+            .filter(|id| !id.is_empty());
+
+        let given = vec![
+            (properties::APPLICATION_NAME, self.name.clone()),
+            (properties::APPLICATION_ID, self.id.clone().or_else(|| desktop_id.clone())),
+            (properties::APPLICATION_VERSION, self.version.clone()),
+            (properties::APPLICATION_ICON_NAME, self.icon_name.clone().or(desktop_id)),
+            (properties::APPLICATION_LANGUAGE, self.language.clone()),
+        ];
+        let detected = vec![
+            (properties::APPLICATION_NAME, env.binary.clone()),
+            (properties::APPLICATION_LANGUAGE, env.language.clone()),
+            (properties::APPLICATION_PROCESS_ID, Some(env.process_id.to_string())),
+            (properties::APPLICATION_PROCESS_BINARY, env.binary.clone()),
+            (properties::APPLICATION_PROCESS_USER, env.user.clone()),
+            (properties::APPLICATION_PROCESS_HOST, env.host.clone()),
+            (properties::APPLICATION_PROCESS_MACHINE_ID, env.machine_id.clone()),
+            (properties::APPLICATION_PROCESS_SESSION_ID, env.session_id.clone()),
+        ];
+
+        let mut entries: Vec<(&'static str, String, bool)> = given.into_iter()
+            .filter_map(|(key, value)| Some((key, value?, true)))
+            .collect();
+        for (key, value) in detected {
+            if let Some(value) = value {
+                if !entries.iter().any(|entry| entry.0 == key) {
+                    entries.push((key, value, false));
+                }
+            }
+        }
+        entries
+    }
+}
+
+impl Environment {
+    /// Detects details of the current process.
+    fn detect() -> Self {
+        let var = |name: &str| std::env::var(name).ok();
+        Self {
+            process_id: std::process::id(),
+            binary: util::get_binary_name(NAME_MAX),
+            user: util::get_user_name(NAME_MAX),
+            host: util::get_host_name(NAME_MAX),
+            machine_id: MACHINE_ID_FILES.iter()
+                .filter_map(|path| std::fs::read_to_string(path).ok())
+                .map(|id| id.trim().to_string())
+                .find(|id| !id.is_empty()),
+            session_id: var("XDG_SESSION_ID").filter(|id| !id.is_empty()),
+            language: language_from_env(var),
+        }
+    }
+}
+
+/// Gets the locale for messages from the given environment variable lookup function.
+fn language_from_env<F>(var: F) -> Option<String>
+    where F: Fn(&str) -> Option<String>
+{
+    ["LC_ALL", "LC_MESSAGES", "LANG"].iter()
+        .filter_map(|name| var(name))
+        .find(|value| !value.is_empty())
+}
+
+impl Proplist {
+    /// Creates a property list describing the application, from the given details and the process
+    /// environment.
+    ///
+    /// See the [`application`](application/index.html) module documentation for details.
+    pub fn new_for_application(info: &Info) -> Result<Self, PAErr> {
+        let mut proplist = Proplist::new().ok_or(PAErr::from(Code::Internal))?;
+        proplist.fill_application(info)?;
+        Ok(proplist)
+    }
+
+    /// Fills in application properties from the given details and the process environment.
+    ///
+    /// Values set explicitly in `info` replace entries already present, whereas entries already
+    /// present are left untouched by values detected from the environment.
+    pub fn fill_application(&mut self, info: &Info) -> Result<(), PAErr> {
+        self.fill_application_from(info, &Environment::detect())
+    }
+
+    fn fill_application_from(&mut self, info: &Info, env: &Environment) -> Result<(), PAErr> {
+        for (key, value, explicit) in info.entries(env) {
+            if !explicit && self.contains(key) == Some(true) {
+                continue;
+            }
+            self.set_str(key, &value).or(Err(PAErr::from(Code::Invalid)))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn precedence() {
+        let env = Environment {
+            process_id: 42,
+            binary: Some("foo-bin".to_string()),
+            language: Some("en_GB.UTF-8".to_string()),
+            ..Default::default()
+        };
+        let find = |entries: &[(&str, String, bool)], key: &str| {
+            entries.iter().find(|(k, _, _)| *k == key).map(|(_, v, _)| v.clone())
+        };
+
+        let entries = Info::default().entries(&env);
+        assert_eq!(find(&entries, properties::APPLICATION_NAME), Some("foo-bin".to_string()));
+        assert_eq!(find(&entries, properties::APPLICATION_ID), None);
+        assert_eq!(find(&entries, properties::APPLICATION_PROCESS_ID), Some("42".to_string()));
+        assert_eq!(find(&entries, properties::APPLICATION_PROCESS_USER), None);
+
+        let info = Info {
+            icon_name: Some("foo-icon".to_string()),
+            language: Some("de_DE".to_string()),
+            desktop_id: Some("org.example.Foo.desktop".to_string()),
+            ..Info::new("Foo", "1.0")
+        };
+        let entries = info.entries(&env);
+        assert_eq!(find(&entries, properties::APPLICATION_NAME), Some("Foo".to_string()));
+        assert_eq!(find(&entries, properties::APPLICATION_VERSION), Some("1.0".to_string()));
+        assert_eq!(find(&entries, properties::APPLICATION_ID), Some("org.example.Foo".to_string()));
+        assert_eq!(find(&entries, properties::APPLICATION_ICON_NAME), Some("foo-icon".to_string()));
+        assert_eq!(find(&entries, properties::APPLICATION_LANGUAGE), Some("de_DE".to_string()));
+
+        // Only the suffix itself is removed
+        let info = Info { desktop_id: Some("foo.desktop.desktop".to_string()), ..Info::default() };
+        let entries = info.entries(&env);
+        assert_eq!(find(&entries, properties::APPLICATION_ID), Some("foo.desktop".to_string()));
+        let info = Info { desktop_id: Some("foo".to_string()), ..Info::default() };
+        assert_eq!(find(&info.entries(&env), properties::APPLICATION_ID), Some("foo".to_string()));
+        let info = Info { desktop_id: Some(".desktop".to_string()), ..Info::default() };
+        assert_eq!(find(&info.entries(&env), properties::APPLICATION_ID), None);
+    }
+
+    #[test]
+    fn fill() {
+        let env = Environment {
+            process_id: 42,
+            binary: Some("foo-bin".to_string()),
+            language: Some("en_GB".to_string()),
+            ..Default::default()
+        };
+        let mut proplist = Proplist::new().unwrap();
+        proplist.set_str(properties::APPLICATION_NAME, "Old").unwrap();
+        proplist.set_str(properties::APPLICATION_LANGUAGE, "fr_FR").unwrap();
+        proplist.set_str(properties::APPLICATION_PROCESS_ID, "7").unwrap();
+
+        // Explicit values replace existing entries, detected ones do not
+        let info = Info { language: Some("de_DE".to_string()), ..Info::new("Foo", "1.0") };
+        proplist.fill_application_from(&info, &env).unwrap();
+        let get = |key| proplist.get_str(key);
+        assert_eq!(get(properties::APPLICATION_NAME), Some("Foo".to_string()));
+        assert_eq!(get(properties::APPLICATION_VERSION), Some("1.0".to_string()));
+        assert_eq!(get(properties::APPLICATION_LANGUAGE), Some("de_DE".to_string()));
+        assert_eq!(get(properties::APPLICATION_PROCESS_ID), Some("7".to_string()));
+        assert_eq!(get(properties::APPLICATION_PROCESS_BINARY), Some("foo-bin".to_string()));
+
+        let mut proplist = Proplist::new().unwrap();
+        proplist.set_str(properties::APPLICATION_NAME, "Old").unwrap();
+        proplist.fill_application_from(&Info::default(), &env).unwrap();
+        assert_eq!(proplist.get_str(properties::APPLICATION_NAME), Some("Old".to_string()));
+        assert_eq!(proplist.get_str(properties::APPLICATION_LANGUAGE), Some("en_GB".to_string()));
+    }
+
+    #[test]
+    fn language() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| vars.iter().find(|(k, _)| *k == name).map(|(_, v)| v.to_string())
+        };
+        assert_eq!(language_from_env(env(&[])), None);
+        assert_eq!(language_from_env(env(&[("LANG", "fr_FR")])), Some("fr_FR".to_string()));
+        assert_eq!(language_from_env(env(&[("LANG", "fr_FR"), ("LC_MESSAGES", "de_DE")])),
+            Some("de_DE".to_string()));
+        assert_eq!(language_from_env(env(&[("LC_ALL", ""), ("LANG", "fr_FR")])),
+            Some("fr_FR".to_string()));
+    }
+}
@@ -0,0 +1,457 @@
+// Copyright 2020 Lyndon Brown
+//
+// This file is part of the PulseAudio Rust language binding.
+//
+// Licensed under the MIT license or the Apache license (version 2.0), at your option. You may not
+// copy, modify, or distribute this file except in compliance with said license. You can find copies
+// of these licenses either in the LICENSE-MIT and LICENSE-APACHE files, or alternatively at
+// <http://opensource.org/licenses/MIT> and <http://www.apache.org/licenses/LICENSE-2.0>
+// respectively.
+//
+// Portions of documentation are copied from the LGPL 2.1+ licensed PulseAudio C headers on a
+// fair-use basis, as discussed in the overall project readme (available in the git repository).
+
+//! Conversion of property lists to and from maps.
+//!
+//! # Overview
+//!
+//! Entries of a property list hold either a string, or arbitrary binary data (as set with
+//! [`Proplist::set`]). A [`Value`] represents either kind, and the entries of a [`Proplist`] can be
+//! copied into a `HashMap` or `BTreeMap` of such values, or a property list built from any
+//! collection of key/value pairs, via `FromIterator`, `Extend` or `TryFrom`.
+//!
+//! With the `serde` feature enabled, a [`Proplist`] can also be serialized, as a map of keys to
+//! values, with string values as strings and binary values as bytes, such that the two kinds
+//! remain distinct when deserialized again.
+//!
+//! Note that the server considers any value that is valid UTF-8, with a single terminating NUL
+//! byte, to be a string, thus binary data set in such a form is read back as a string.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use std::collections::BTreeMap;
+//! use pulse::proplist::{properties, Proplist};
+//! use pulse::proplist::map::Value;
+//!
+//! let proplist: Proplist = vec![
+//!     (properties::APPLICATION_NAME, Value::from("FooApp")),
+//!     ("foo.data", Value::from(vec![1u8, 2, 3])),
+//! ].into_iter().collect();
+//!
+//! let map: BTreeMap<String, Value> = proplist.to_btree_map();
+//! assert_eq!(map["foo.data"], Value::Binary(vec![1, 2, 3]));
+//! ```
+//!
+//! [`Proplist`]: ../struct.Proplist.html
+//! [`Proplist::set`]: ../struct.Proplist.html#method.set
+//! [`Value`]: enum.Value.html
+
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::hash::BuildHasher;
+use std::iter::FromIterator;
+use crate::error::{Code, PAErr};
+use super::Proplist;
+
+/// The value of a property list entry.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Value {
+    /// A string value.
+    String(String),
+    /// An arbitrary binary data value.
+    Binary(Vec<u8>),
+}
+
+impl Value {
+    /// Gets the value as a string, if it is a string value.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            Value::Binary(_) => None,
+        }
+    }
+
+    /// Gets the value as bytes. For string values this excludes the terminating NUL byte.
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Value::String(s) => s.as_bytes(),
+            Value::Binary(d) => d,
+        }
+    }
+
+    /// Checks if this is a string value.
+    #[inline]
+    pub fn is_string(&self) -> bool {
+        match self { Value::String(_) => true, Value::Binary(_) => false }
+    }
+}
+
+impl From<String> for Value {
+    #[inline]
+    fn from(s: String) -> Self {
+        Value::String(s)
+    }
+}
+
+impl From<&str> for Value {
+    #[inline]
+    fn from(s: &str) -> Self {
+        Value::String(s.to_string())
+    }
+}
+
+impl From<Vec<u8>> for Value {
+    #[inline]
+    fn from(d: Vec<u8>) -> Self {
+        Value::Binary(d)
+    }
+}
+
+impl From<&[u8]> for Value {
+    #[inline]
+    fn from(d: &[u8]) -> Self {
+        Value::Binary(d.to_vec())
+    }
+}
+
+impl Proplist {
+    /// Gets the value for the specified key, as a string if it is one, otherwise as binary data.
+    ///
+    /// Returns `None` if the key does not exist.
+    pub fn get_value(&self, key: &str) -> Option<Value> {
+        match self.get_str(key) {
+            Some(s) => Some(Value::String(s)),
+            None => self.get(key).map(|d| Value::Binary(d.to_vec())),
+        }
+    }
+
+    /// Sets a string or binary data entry, possibly overwriting an already existing entry with the
+    /// same key.
+    ///
+    /// Returns an error if the key is invalid, or if a string value contains a NUL byte.
+    pub fn set_value(&mut self, key: &str, value: &Value) -> Result<(), ()> {
+        if key.contains('\0') {
+            return Err(());
+        }
+        match value {
+            Value::String(s) if s.contains('\0') => Err(()),
+            Value::String(s) => self.set_str(key, s),
+            Value::Binary(d) => self.set(key, d),
+        }
+    }
+
+    /// Copies all entries into a `HashMap`.
+    pub fn to_hash_map(&self) -> HashMap<String, Value> {
+        self.entries().collect()
+    }
+
+    /// Copies all entries into a `BTreeMap`, which, unlike the property list itself, is ordered by
+    /// key.
+    pub fn to_btree_map(&self) -> BTreeMap<String, Value> {
+        self.entries().collect()
+    }
+
+    /// Creates a property list from key/value pairs.
+    ///
+    /// Returns an error if any key is invalid, or any value could not be set (see
+    /// [`set_value`](#method.set_value)).
+    pub fn try_from_iter<I, K, V>(iter: I) -> Result<Self, PAErr>
+        where I: IntoIterator<Item = (K, V)>,
+              K: AsRef<str>,
+              V: Into<Value>
+    {
+        let mut proplist = Proplist::new().ok_or(PAErr::from(Code::Internal))?;
+        proplist.try_extend(iter)?;
+        Ok(proplist)
+    }
+
+    /// Sets entries from key/value pairs, possibly overwriting already existing entries with the
+    /// same keys.
+    ///
+    /// Returns an error if any key is invalid, or any value could not be set (see
+    /// [`set_value`](#method.set_value)), in which case entries preceding it will have been set.
+    pub fn try_extend<I, K, V>(&mut self, iter: I) -> Result<(), PAErr>
+        where I: IntoIterator<Item = (K, V)>,
+              K: AsRef<str>,
+              V: Into<Value>
+    {
+        for (key, value) in iter {
+            self.set_value(key.as_ref(), &value.into()).or(Err(PAErr::from(Code::Invalid)))?;
+        }
+        Ok(())
+    }
+
+    fn entries(&self) -> impl std::iter::Iterator<Item = (String, Value)> + '_ {
+        self.iter().filter_map(move |key| {
+            let value = self.get_value(&key)?;
+            Some((key, value))
+        })
+    }
+}
+
+impl<K, V> FromIterator<(K, V)> for Proplist
+    where K: AsRef<str>,
+          V: Into<Value>
+{
+    /// Creates a property list from key/value pairs.
+    ///
+    /// Panics if any key is invalid, or any value could not be set. Use
+    /// [`try_from_iter`](struct.Proplist.html#method.try_from_iter) to handle this instead.
+    fn from_iter<I>(iter: I) -> Self
+        where I: IntoIterator<Item = (K, V)>
+    {
+        Self::try_from_iter(iter).expect("invalid proplist entry")
+    }
+}
+
+impl<K, V> Extend<(K, V)> for Proplist
+    where K: AsRef<str>,
+          V: Into<Value>
+{
+    /// Sets entries from key/value pairs.
+    ///
+    /// Panics if any key is invalid, or any value could not be set. Use
+    /// [`try_extend`](struct.Proplist.html#method.try_extend) to handle this instead.
+    fn extend<I>(&mut self, iter: I)
+        where I: IntoIterator<Item = (K, V)>
+    {
+        self.try_extend(iter).expect("invalid proplist entry")
+    }
+}
+
+impl<S> From<&Proplist> for HashMap<String, Value, S>
+    where S: BuildHasher + Default
+{
+    fn from(proplist: &Proplist) -> Self {
+        proplist.entries().collect()
+    }
+}
+
+impl From<&Proplist> for BTreeMap<String, Value> {
+    #[inline]
+    fn from(proplist: &Proplist) -> Self {
+        proplist.to_btree_map()
+    }
+}
+
+impl<K, V, S> TryFrom<HashMap<K, V, S>> for Proplist
+    where K: AsRef<str>,
+          V: Into<Value>
+{
+    type Error = PAErr;
+
+    #[inline]
+    fn try_from(map: HashMap<K, V, S>) -> Result<Self, Self::Error> {
+        Self::try_from_iter(map)
+    }
+}
+
+impl<K, V> TryFrom<BTreeMap<K, V>> for Proplist
+    where K: AsRef<str>,
+          V: Into<Value>
+{
+    type Error = PAErr;
+
+    #[inline]
+    fn try_from(map: BTreeMap<K, V>) -> Result<Self, Self::Error> {
+        Self::try_from_iter(map)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Value {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: serde::Serializer
+    {
+        match self {
+            Value::String(s) => serializer.serialize_str(s),
+            Value::Binary(d) => serializer.serialize_bytes(d),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Value {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where D: serde::Deserializer<'de>
+    {
+        struct ValueVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for ValueVisitor {
+            type Value = Value;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a string or bytes")
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Value, E> {
+                Ok(Value::String(v.to_string()))
+            }
+
+            fn visit_string<E>(self, v: String) -> Result<Value, E> {
+                Ok(Value::String(v))
+            }
+
+            fn visit_bytes<E>(self, v: &[u8]) -> Result<Value, E> {
+                Ok(Value::Binary(v.to_vec()))
+            }
+
+            fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Value, E> {
+                Ok(Value::Binary(v))
+            }
+
+            // Formats without a native byte type (e.g. JSON) represent bytes as a sequence
+            fn visit_seq<A>(self, mut seq: A) -> Result<Value, A::Error>
+                where A: serde::de::SeqAccess<'de>
+            {
+                let mut data = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+                while let Some(byte) = seq.next_element()? {
+                    data.push(byte);
+                }
+                Ok(Value::Binary(data))
+            }
+        }
+
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Proplist {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: serde::Serializer
+    {
+        // Sorted, for stable output
+        serializer.collect_map(self.to_btree_map())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Proplist {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where D: serde::Deserializer<'de>
+    {
+        struct ProplistVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for ProplistVisitor {
+            type Value = Proplist;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a map of property keys to values")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Proplist, A::Error>
+                where A: serde::de::MapAccess<'de>
+            {
+                use serde::de::Error;
+                let mut proplist = Proplist::new()
+                    .ok_or_else(|| A::Error::custom("failed to allocate proplist"))?;
+                while let Some((key, value)) = map.next_entry::<String, Value>()? {
+                    proplist.set_value(&key, &value)
+                        .map_err(|_| A::Error::custom(format!("invalid property: {:?}", key)))?;
+                }
+                Ok(proplist)
+            }
+        }
+
+        deserializer.deserialize_map(ProplistVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values() {
+        let s = Value::from("foo");
+        assert_eq!(s, Value::String("foo".to_string()));
+        assert_eq!(s.as_str(), Some("foo"));
+        assert_eq!(s.as_bytes(), b"foo");
+        assert!(s.is_string());
+
+        let d = Value::from(&b"foo"[..]);
+        assert_eq!(d, Value::Binary(b"foo".to_vec()));
+        assert_eq!(d.as_str(), None);
+        assert_eq!(d.as_bytes(), b"foo");
+        assert!(!d.is_string());
+    }
+
+    #[test]
+    fn map_conversion() {
+        let proplist: Proplist = vec![
+            ("foo.name", Value::from("Foo")),
+            ("foo.data", Value::from(vec![0u8, 1, 2])),
+        ].into_iter().collect();
+        assert_eq!(proplist.get_value("foo.name"), Some(Value::from("Foo")));
+        assert_eq!(proplist.get_value("foo.data"), Some(Value::Binary(vec![0, 1, 2])));
+        assert_eq!(proplist.get_value("foo.none"), None);
+
+        let map = proplist.to_btree_map();
+        assert_eq!(map.len(), 2);
+        assert_eq!(map["foo.name"], Value::from("Foo"));
+        assert_eq!(map["foo.data"], Value::Binary(vec![0, 1, 2]));
+        assert_eq!(HashMap::<String, Value>::from(&proplist), proplist.to_hash_map());
+        assert_eq!(BTreeMap::from(&proplist), map);
+
+        let copy = Proplist::try_from(map.clone()).unwrap();
+        assert_eq!(copy.to_btree_map(), map);
+
+        let mut proplist = proplist;
+        proplist.extend(vec![("foo.name", "Bar"), ("foo.other", "x")]);
+        assert_eq!(proplist.get_str("foo.name"), Some("Bar".to_string()));
+        assert_eq!(proplist.len(), 3);
+    }
+
+    #[test]
+    fn invalid_entries() {
+        assert!(Proplist::try_from_iter(vec![("foo\0bar", "x")]).is_err());
+        assert!(Proplist::try_from_iter(vec![("foo.name", "x\0y")]).is_err());
+
+        // Entries preceding an invalid one are set
+        let mut proplist = Proplist::new().unwrap();
+        assert!(proplist.try_extend(vec![("foo.a", "a"), ("foo\0b", "b")]).is_err());
+        assert_eq!(proplist.get_str("foo.a"), Some("a".to_string()));
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid proplist entry")]
+    fn invalid_from_iter() {
+        let _: Proplist = vec![("foo.name", "x\0y")].into_iter().collect();
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid proplist entry")]
+    fn invalid_extend() {
+        let mut proplist = Proplist::new().unwrap();
+        proplist.extend(vec![("foo\0bar", "x")]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn deserialization() {
+        use serde::Deserialize;
+        use serde::de::IntoDeserializer;
+        use serde::de::value::{BytesDeserializer, Error, MapDeserializer, SeqDeserializer,
+            StrDeserializer};
+
+        let de: StrDeserializer<Error> = "abc".into_deserializer();
+        assert_eq!(Value::deserialize(de), Ok(Value::from("abc")));
+        let de = BytesDeserializer::<Error>::new(b"abc");
+        assert_eq!(Value::deserialize(de), Ok(Value::from(&b"abc"[..])));
+        // Binary data that happens to be valid UTF-8 remains binary, also as a sequence
+        let de = SeqDeserializer::<_, Error>::new(b"abc".iter().cloned());
+        assert_eq!(Value::deserialize(de), Ok(Value::from(&b"abc"[..])));
+
+        let de = MapDeserializer::<_, Error>::new(vec![("foo.name", "abc"), ("foo.other", "x")]
+            .into_iter());
+        let mut expected = BTreeMap::new();
+        expected.insert("foo.name".to_string(), Value::from("abc"));
+        expected.insert("foo.other".to_string(), Value::from("x"));
+        assert_eq!(Proplist::deserialize(de).unwrap().to_btree_map(), expected);
+
+        let de = MapDeserializer::<_, Error>::new(vec![("foo\0bar", "x")].into_iter());
+        assert!(Proplist::deserialize(de).is_err());
+    }
+}
@@ -13,6 +13,10 @@
 
 //! Property list constants and functions.
 
+pub mod application;
+pub mod map;
+pub mod values;
+
 use std::os::raw::{c_char, c_void};
 use std::ffi::{CStr, CString};
 use std::ptr::{null, null_mut};
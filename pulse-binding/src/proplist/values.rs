@@ -0,0 +1,621 @@
+// Copyright 2020 Lyndon Brown
+//
+// This file is part of the PulseAudio Rust language binding.
+//
+// Licensed under the MIT license or the Apache license (version 2.0), at your option. You may not
+// copy, modify, or distribute this file except in compliance with said license. You can find copies
+// of these licenses either in the LICENSE-MIT and LICENSE-APACHE files, or alternatively at
+// <http://opensource.org/licenses/MIT> and <http://www.apache.org/licenses/LICENSE-2.0>
+// respectively.
+//
+// Portions of documentation are copied from the LGPL 2.1+ licensed PulseAudio C headers on a
+// fair-use basis, as discussed in the overall project readme (available in the git repository).
+
+//! Typed values of well-known properties.
+//!
+//! # Overview
+//!
+//! Property values are stored as strings. For the well-known properties of [`properties`] that
+//! hold one of a fixed set of strings, this module provides enums, such as [`MediaRole`], and
+//! [`Proplist`] has typed getters and setters, such as [`Proplist::get_media_role`]. Likewise,
+//! numeric properties have getters and setters using numeric types.
+//!
+//! Since other software may use values not known to this binding, each enum has an `Other` variant
+//! holding any unknown value as is, so nothing is lost when reading a value and writing it back.
+//!
+//! For properties without a dedicated getter, [`Proplist::get_parsed`] and
+//! [`Proplist::set_display`] can be used with any type implementing `FromStr` resp. `Display`.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use pulse::proplist::Proplist;
+//! use pulse::proplist::values::MediaRole;
+//!
+//! let mut proplist = Proplist::new().unwrap();
+//! proplist.set_media_role(&MediaRole::Music).unwrap();
+//! proplist.set_application_process_id(std::process::id()).unwrap();
+//!
+//! assert_eq!(proplist.get_media_role(), Some(MediaRole::Music));
+//! ```
+//!
+//! [`properties`]: ../properties/index.html
+//! [`MediaRole`]: enum.MediaRole.html
+//! [`Proplist`]: ../struct.Proplist.html
+//! [`Proplist::get_media_role`]: ../struct.Proplist.html#method.get_media_role
+//! [`Proplist::get_parsed`]: ../struct.Proplist.html#method.get_parsed
+//! [`Proplist::set_display`]: ../struct.Proplist.html#method.set_display
+
+use std::fmt::Display;
+use std::str::FromStr;
+use super::{properties, Proplist};
+
+/// Defines an enum of known string values, with a variant preserving unknown ones.
+macro_rules! string_values {
+    (
+        $(#[$meta:meta])*
+        pub enum $name:ident {
+            $( $(#[$vmeta:meta])* $variant:ident => $value:expr, )*
+        }
+    ) => {
+        $(#[$meta])*
+        ///
+        /// Values compare (and hash) by their string, thus an `Other` variant holding a known value
+        /// is equal to the corresponding variant.
+        #[derive(Debug, Clone)]
+        pub enum $name {
+            $( $(#[$vmeta])* $variant, )*
+            /// Any other value, preserved as is.
+            Other(String),
+        }
+
+        impl PartialEq for $name {
+            fn eq(&self, other: &Self) -> bool {
+                self.as_str() == other.as_str()
+            }
+        }
+
+        impl Eq for $name {}
+
+        impl std::hash::Hash for $name {
+            fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+                self.as_str().hash(state);
+            }
+        }
+
+        impl $name {
+            /// Gets the property value string.
+            pub fn as_str(&self) -> &str {
+                match self {
+                    $( $name::$variant => $value, )*
+                    $name::Other(s) => s,
+                }
+            }
+        }
+
+        impl From<&str> for $name {
+            fn from(s: &str) -> Self {
+                match s {
+                    $( $value => $name::$variant, )*
+                    _ => $name::Other(s.to_string()),
+                }
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = std::convert::Infallible;
+
+            #[inline]
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                Ok(Self::from(s))
+            }
+        }
+
+        impl std::fmt::Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str(self.as_str())
+            }
+        }
+    };
+}
+
+string_values! {
+    /// Logical role of a stream ([`properties::MEDIA_ROLE`]).
+    ///
+    /// [`properties::MEDIA_ROLE`]: ../properties/constant.MEDIA_ROLE.html
+    pub enum MediaRole {
+        /// Soundtrack of a video.
+        Video => "video",
+        /// Music.
+        Music => "music",
+        /// Game audio.
+        Game => "game",
+        /// Event sounds, e.g. notifications.
+        Event => "event",
+        /// Telephony and voice communication.
+        Phone => "phone",
+        /// Sounds accompanying animations.
+        Animation => "animation",
+        /// Audio production, e.g. in a digital audio workstation.
+        Production => "production",
+        /// Accessibility, e.g. screen readers.
+        A11y => "a11y",
+        /// Test signals.
+        Test => "test",
+    }
+}
+
+string_values! {
+    /// Form factor of a device ([`properties::DEVICE_FORM_FACTOR`]).
+    ///
+    /// [`properties::DEVICE_FORM_FACTOR`]: ../properties/constant.DEVICE_FORM_FACTOR.html
+    pub enum FormFactor {
+        /// Built into the computer.
+        Internal => "internal",
+        /// Speakers.
+        Speaker => "speaker",
+        /// Telephone handset.
+        Handset => "handset",
+        /// Television.
+        Tv => "tv",
+        /// Web camera.
+        Webcam => "webcam",
+        /// Microphone.
+        Microphone => "microphone",
+        /// Headset (headphones with microphone).
+        Headset => "headset",
+        /// Headphones.
+        Headphone => "headphone",
+        /// Hands-free kit.
+        HandsFree => "hands-free",
+        /// Car audio system.
+        Car => "car",
+        /// Hi-fi system.
+        Hifi => "hifi",
+        /// Computer.
+        Computer => "computer",
+        /// Portable device.
+        Portable => "portable",
+    }
+}
+
+string_values! {
+    /// Bus of a device ([`properties::DEVICE_BUS`]).
+    ///
+    /// [`properties::DEVICE_BUS`]: ../properties/constant.DEVICE_BUS.html
+    pub enum Bus {
+        /// ISA.
+        Isa => "isa",
+        /// PCI.
+        Pci => "pci",
+        /// USB.
+        Usb => "usb",
+        /// FireWire.
+        Firewire => "firewire",
+        /// Bluetooth.
+        Bluetooth => "bluetooth",
+    }
+}
+
+string_values! {
+    /// Class of a device ([`properties::DEVICE_CLASS`]).
+    ///
+    /// [`properties::DEVICE_CLASS`]: ../properties/constant.DEVICE_CLASS.html
+    pub enum DeviceClass {
+        /// Sound card.
+        Sound => "sound",
+        /// Modem.
+        Modem => "modem",
+        /// Monitor source of a sink.
+        Monitor => "monitor",
+        /// Filter, e.g. an equalizer or echo canceller.
+        Filter => "filter",
+    }
+}
+
+string_values! {
+    /// Access mode of a device ([`properties::DEVICE_ACCESS_MODE`]).
+    ///
+    /// [`properties::DEVICE_ACCESS_MODE`]: ../properties/constant.DEVICE_ACCESS_MODE.html
+    pub enum AccessMode {
+        /// Memory mapped.
+        Mmap => "mmap",
+        /// Memory mapped, with rewinding.
+        MmapRewrite => "mmap_rewrite",
+        /// Serial (read/write calls).
+        Serial => "serial",
+    }
+}
+
+impl Proplist {
+    /// Gets the value of a property, parsed from its string form.
+    ///
+    /// Returns `None` if the key does not exist, or the value could not be parsed.
+    pub fn get_parsed<T>(&self, key: &str) -> Option<T>
+        where T: FromStr
+    {
+        self.get_str(key).and_then(|s| s.trim().parse().ok())
+    }
+
+    /// Sets the value of a property, in its string form, possibly overwriting an already existing
+    /// entry with the same key.
+    pub fn set_display<T>(&mut self, key: &str, value: &T) -> Result<(), ()>
+        where T: Display + ?Sized
+    {
+        self.set_str(key, &value.to_string())
+    }
+
+    /// Gets the logical role of a stream ([`properties::MEDIA_ROLE`]).
+    ///
+    /// [`properties::MEDIA_ROLE`]: properties/constant.MEDIA_ROLE.html
+    pub fn get_media_role(&self) -> Option<MediaRole> {
+        self.get_str(properties::MEDIA_ROLE).map(|s| MediaRole::from(s.as_str()))
+    }
+
+    /// Sets the logical role of a stream ([`properties::MEDIA_ROLE`]).
+    ///
+    /// [`properties::MEDIA_ROLE`]: properties/constant.MEDIA_ROLE.html
+    pub fn set_media_role(&mut self, role: &MediaRole) -> Result<(), ()> {
+        self.set_str(properties::MEDIA_ROLE, role.as_str())
+    }
+
+    /// Gets the roles a device is particularly well suited for
+    /// ([`properties::DEVICE_INTENDED_ROLES`]).
+    ///
+    /// [`properties::DEVICE_INTENDED_ROLES`]: properties/constant.DEVICE_INTENDED_ROLES.html
+    pub fn get_device_intended_roles(&self) -> Option<Vec<MediaRole>> {
+        self.get_str(properties::DEVICE_INTENDED_ROLES)
+            .map(|s| s.split_whitespace().map(MediaRole::from).collect())
+    }
+
+    /// Sets the roles a device is particularly well suited for
+    /// ([`properties::DEVICE_INTENDED_ROLES`]).
+    ///
+    /// [`properties::DEVICE_INTENDED_ROLES`]: properties/constant.DEVICE_INTENDED_ROLES.html
+    pub fn set_device_intended_roles(&mut self, roles: &[MediaRole]) -> Result<(), ()> {
+        let roles: Vec<&str> = roles.iter().map(|r| r.as_str()).collect();
+        self.set_str(properties::DEVICE_INTENDED_ROLES, &roles.join(" "))
+    }
+
+    /// Gets the form factor of a device ([`properties::DEVICE_FORM_FACTOR`]).
+    ///
+    /// [`properties::DEVICE_FORM_FACTOR`]: properties/constant.DEVICE_FORM_FACTOR.html
+    pub fn get_device_form_factor(&self) -> Option<FormFactor> {
+        self.get_str(properties::DEVICE_FORM_FACTOR).map(|s| FormFactor::from(s.as_str()))
+    }
+
+    /// Sets the form factor of a device ([`properties::DEVICE_FORM_FACTOR`]).
+    ///
+    /// [`properties::DEVICE_FORM_FACTOR`]: properties/constant.DEVICE_FORM_FACTOR.html
+    pub fn set_device_form_factor(&mut self, form_factor: &FormFactor) -> Result<(), ()> {
+        self.set_str(properties::DEVICE_FORM_FACTOR, form_factor.as_str())
+    }
+
+    /// Gets the bus of a device ([`properties::DEVICE_BUS`]).
+    ///
+    /// [`properties::DEVICE_BUS`]: properties/constant.DEVICE_BUS.html
+    pub fn get_device_bus(&self) -> Option<Bus> {
+        self.get_str(properties::DEVICE_BUS).map(|s| Bus::from(s.as_str()))
+    }
+
+    /// Sets the bus of a device ([`properties::DEVICE_BUS`]).
+    ///
+    /// [`properties::DEVICE_BUS`]: properties/constant.DEVICE_BUS.html
+    pub fn set_device_bus(&mut self, bus: &Bus) -> Result<(), ()> {
+        self.set_str(properties::DEVICE_BUS, bus.as_str())
+    }
+
+    /// Gets the class of a device ([`properties::DEVICE_CLASS`]).
+    ///
+    /// [`properties::DEVICE_CLASS`]: properties/constant.DEVICE_CLASS.html
+    pub fn get_device_class(&self) -> Option<DeviceClass> {
+        self.get_str(properties::DEVICE_CLASS).map(|s| DeviceClass::from(s.as_str()))
+    }
+
+    /// Sets the class of a device ([`properties::DEVICE_CLASS`]).
+    ///
+    /// [`properties::DEVICE_CLASS`]: properties/constant.DEVICE_CLASS.html
+    pub fn set_device_class(&mut self, class: &DeviceClass) -> Result<(), ()> {
+        self.set_str(properties::DEVICE_CLASS, class.as_str())
+    }
+
+    /// Gets the access mode of a device ([`properties::DEVICE_ACCESS_MODE`]).
+    ///
+    /// [`properties::DEVICE_ACCESS_MODE`]: properties/constant.DEVICE_ACCESS_MODE.html
+    pub fn get_device_access_mode(&self) -> Option<AccessMode> {
+        self.get_str(properties::DEVICE_ACCESS_MODE).map(|s| AccessMode::from(s.as_str()))
+    }
+
+    /// Sets the access mode of a device ([`properties::DEVICE_ACCESS_MODE`]).
+    ///
+    /// [`properties::DEVICE_ACCESS_MODE`]: properties/constant.DEVICE_ACCESS_MODE.html
+    pub fn set_device_access_mode(&mut self, mode: &AccessMode) -> Result<(), ()> {
+        self.set_str(properties::DEVICE_ACCESS_MODE, mode.as_str())
+    }
+
+    /// Gets the buffer size of a device in bytes ([`properties::DEVICE_BUFFERING_BUFFER_SIZE`]).
+    ///
+    /// [`properties::DEVICE_BUFFERING_BUFFER_SIZE`]:
+    /// properties/constant.DEVICE_BUFFERING_BUFFER_SIZE.html
+    pub fn get_device_buffering_buffer_size(&self) -> Option<u32> {
+        self.get_parsed(properties::DEVICE_BUFFERING_BUFFER_SIZE)
+    }
+
+    /// Sets the buffer size of a device in bytes ([`properties::DEVICE_BUFFERING_BUFFER_SIZE`]).
+    ///
+    /// [`properties::DEVICE_BUFFERING_BUFFER_SIZE`]:
+    /// properties/constant.DEVICE_BUFFERING_BUFFER_SIZE.html
+    pub fn set_device_buffering_buffer_size(&mut self, size: u32) -> Result<(), ()> {
+        self.set_display(properties::DEVICE_BUFFERING_BUFFER_SIZE, &size)
+    }
+
+    /// Gets the fragment size of a device in bytes
+    /// ([`properties::DEVICE_BUFFERING_FRAGMENT_SIZE`]).
+    ///
+    /// [`properties::DEVICE_BUFFERING_FRAGMENT_SIZE`]:
+    /// properties/constant.DEVICE_BUFFERING_FRAGMENT_SIZE.html
+    pub fn get_device_buffering_fragment_size(&self) -> Option<u32> {
+        self.get_parsed(properties::DEVICE_BUFFERING_FRAGMENT_SIZE)
+    }
+
+    /// Sets the fragment size of a device in bytes
+    /// ([`properties::DEVICE_BUFFERING_FRAGMENT_SIZE`]).
+    ///
+    /// [`properties::DEVICE_BUFFERING_FRAGMENT_SIZE`]:
+    /// properties/constant.DEVICE_BUFFERING_FRAGMENT_SIZE.html
+    pub fn set_device_buffering_fragment_size(&mut self, size: u32) -> Result<(), ()> {
+        self.set_display(properties::DEVICE_BUFFERING_FRAGMENT_SIZE, &size)
+    }
+
+    /// Gets the process ID of an application ([`properties::APPLICATION_PROCESS_ID`]).
+    ///
+    /// [`properties::APPLICATION_PROCESS_ID`]: properties/constant.APPLICATION_PROCESS_ID.html
+    pub fn get_application_process_id(&self) -> Option<u32> {
+        self.get_parsed(properties::APPLICATION_PROCESS_ID)
+    }
+
+    /// Sets the process ID of an application ([`properties::APPLICATION_PROCESS_ID`]).
+    ///
+    /// [`properties::APPLICATION_PROCESS_ID`]: properties/constant.APPLICATION_PROCESS_ID.html
+    pub fn set_application_process_id(&mut self, pid: u32) -> Result<(), ()> {
+        self.set_display(properties::APPLICATION_PROCESS_ID, &pid)
+    }
+
+    /// Gets the X11 screen a window is on ([`properties::WINDOW_X11_SCREEN`]).
+    ///
+    /// [`properties::WINDOW_X11_SCREEN`]: properties/constant.WINDOW_X11_SCREEN.html
+    pub fn get_window_x11_screen(&self) -> Option<u32> {
+        self.get_parsed(properties::WINDOW_X11_SCREEN)
+    }
+
+    /// Sets the X11 screen a window is on ([`properties::WINDOW_X11_SCREEN`]).
+    ///
+    /// [`properties::WINDOW_X11_SCREEN`]: properties/constant.WINDOW_X11_SCREEN.html
+    pub fn set_window_x11_screen(&mut self, screen: u32) -> Result<(), ()> {
+        self.set_display(properties::WINDOW_X11_SCREEN, &screen)
+    }
+
+    /// Gets the X11 monitor a window is on ([`properties::WINDOW_X11_MONITOR`]).
+    ///
+    /// [`properties::WINDOW_X11_MONITOR`]: properties/constant.WINDOW_X11_MONITOR.html
+    pub fn get_window_x11_monitor(&self) -> Option<u32> {
+        self.get_parsed(properties::WINDOW_X11_MONITOR)
+    }
+
+    /// Sets the X11 monitor a window is on ([`properties::WINDOW_X11_MONITOR`]).
+    ///
+    /// [`properties::WINDOW_X11_MONITOR`]: properties/constant.WINDOW_X11_MONITOR.html
+    pub fn set_window_x11_monitor(&mut self, monitor: u32) -> Result<(), ()> {
+        self.set_display(properties::WINDOW_X11_MONITOR, &monitor)
+    }
+
+    /// Gets the XID of an X11 window ([`properties::WINDOW_X11_XID`]).
+    ///
+    /// [`properties::WINDOW_X11_XID`]: properties/constant.WINDOW_X11_XID.html
+    pub fn get_window_x11_xid(&self) -> Option<u32> {
+        self.get_parsed(properties::WINDOW_X11_XID)
+    }
+
+    /// Sets the XID of an X11 window ([`properties::WINDOW_X11_XID`]).
+    ///
+    /// [`properties::WINDOW_X11_XID`]: properties/constant.WINDOW_X11_XID.html
+    pub fn set_window_x11_xid(&mut self, xid: u32) -> Result<(), ()> {
+        self.set_display(properties::WINDOW_X11_XID, &xid)
+    }
+
+    /// Gets the position of a window on the screen ([`properties::WINDOW_X`] and
+    /// [`properties::WINDOW_Y`]).
+    ///
+    /// Returns `None` unless both are set.
+    ///
+    /// [`properties::WINDOW_X`]: properties/constant.WINDOW_X.html
+    /// [`properties::WINDOW_Y`]: properties/constant.WINDOW_Y.html
+    pub fn get_window_position(&self) -> Option<(i32, i32)> {
+        Some((self.get_parsed(properties::WINDOW_X)?, self.get_parsed(properties::WINDOW_Y)?))
+    }
+
+    /// Sets the position of a window on the screen ([`properties::WINDOW_X`] and
+    /// [`properties::WINDOW_Y`]).
+    ///
+    /// [`properties::WINDOW_X`]: properties/constant.WINDOW_X.html
+    /// [`properties::WINDOW_Y`]: properties/constant.WINDOW_Y.html
+    pub fn set_window_position(&mut self, x: i32, y: i32) -> Result<(), ()> {
+        self.set_display(properties::WINDOW_X, &x)?;
+        self.set_display(properties::WINDOW_Y, &y)
+    }
+
+    /// Gets the size of a window on the screen ([`properties::WINDOW_WIDTH`] and
+    /// [`properties::WINDOW_HEIGHT`]).
+    ///
+    /// Returns `None` unless both are set.
+    ///
+    /// [`properties::WINDOW_WIDTH`]: properties/constant.WINDOW_WIDTH.html
+    /// [`properties::WINDOW_HEIGHT`]: properties/constant.WINDOW_HEIGHT.html
+    pub fn get_window_size(&self) -> Option<(u32, u32)> {
+        Some((self.get_parsed(properties::WINDOW_WIDTH)?,
+            self.get_parsed(properties::WINDOW_HEIGHT)?))
+    }
+
+    /// Sets the size of a window on the screen ([`properties::WINDOW_WIDTH`] and
+    /// [`properties::WINDOW_HEIGHT`]).
+    ///
+    /// [`properties::WINDOW_WIDTH`]: properties/constant.WINDOW_WIDTH.html
+    /// [`properties::WINDOW_HEIGHT`]: properties/constant.WINDOW_HEIGHT.html
+    pub fn set_window_size(&mut self, width: u32, height: u32) -> Result<(), ()> {
+        self.set_display(properties::WINDOW_WIDTH, &width)?;
+        self.set_display(properties::WINDOW_HEIGHT, &height)
+    }
+
+    /// Gets the relative position of the centre of a window on the screen, from `0.0` (left resp.
+    /// top) to `1.0` (right resp. bottom) ([`properties::WINDOW_HPOS`] and
+    /// [`properties::WINDOW_VPOS`]).
+    ///
+    /// Returns `None` unless both are set.
+    ///
+    /// [`properties::WINDOW_HPOS`]: properties/constant.WINDOW_HPOS.html
+    /// [`properties::WINDOW_VPOS`]: properties/constant.WINDOW_VPOS.html
+    pub fn get_window_relative_position(&self) -> Option<(f64, f64)> {
+        Some((self.get_parsed(properties::WINDOW_HPOS)?,
+            self.get_parsed(properties::WINDOW_VPOS)?))
+    }
+
+    /// Sets the relative position of the centre of a window on the screen, from `0.0` (left resp.
+    /// top) to `1.0` (right resp. bottom) ([`properties::WINDOW_HPOS`] and
+    /// [`properties::WINDOW_VPOS`]).
+    ///
+    /// [`properties::WINDOW_HPOS`]: properties/constant.WINDOW_HPOS.html
+    /// [`properties::WINDOW_VPOS`]: properties/constant.WINDOW_VPOS.html
+    pub fn set_window_relative_position(&mut self, hpos: f64, vpos: f64) -> Result<(), ()> {
+        self.set_display(properties::WINDOW_HPOS, &hpos)?;
+        self.set_display(properties::WINDOW_VPOS, &vpos)
+    }
+
+    /// Gets the indexes of the desktops a window is visible on ([`properties::WINDOW_DESKTOP`]).
+    ///
+    /// An empty list means the window is visible on all desktops. Returns `None` if not set, or
+    /// not a valid list.
+    ///
+    /// [`properties::WINDOW_DESKTOP`]: properties/constant.WINDOW_DESKTOP.html
+    pub fn get_window_desktop(&self) -> Option<Vec<u32>> {
+        let value = self.get_str(properties::WINDOW_DESKTOP)?;
+        match value.trim().is_empty() {
+            true => Some(Vec::new()),
+            false => value.split(',').map(|d| d.trim().parse().ok()).collect(),
+        }
+    }
+
+    /// Sets the indexes of the desktops a window is visible on ([`properties::WINDOW_DESKTOP`]).
+    ///
+    /// An empty list means the window is visible on all desktops.
+    ///
+    /// [`properties::WINDOW_DESKTOP`]: properties/constant.WINDOW_DESKTOP.html
+    pub fn set_window_desktop(&mut self, desktops: &[u32]) -> Result<(), ()> {
+        let desktops: Vec<String> = desktops.iter().map(|d| d.to_string()).collect();
+        self.set_str(properties::WINDOW_DESKTOP, &desktops.join(","))
+    }
+
+    /// Gets the absolute position of the mouse on the screen, for event sounds triggered by a
+    /// mouse click ([`properties::EVENT_MOUSE_X`] and [`properties::EVENT_MOUSE_Y`]).
+    ///
+    /// Returns `None` unless both are set.
+    ///
+    /// [`properties::EVENT_MOUSE_X`]: properties/constant.EVENT_MOUSE_X.html
+    /// [`properties::EVENT_MOUSE_Y`]: properties/constant.EVENT_MOUSE_Y.html
+    pub fn get_event_mouse_position(&self) -> Option<(i32, i32)> {
+        Some((self.get_parsed(properties::EVENT_MOUSE_X)?,
+            self.get_parsed(properties::EVENT_MOUSE_Y)?))
+    }
+
+    /// Sets the absolute position of the mouse on the screen, for event sounds triggered by a
+    /// mouse click ([`properties::EVENT_MOUSE_X`] and [`properties::EVENT_MOUSE_Y`]).
+    ///
+    /// [`properties::EVENT_MOUSE_X`]: properties/constant.EVENT_MOUSE_X.html
+    /// [`properties::EVENT_MOUSE_Y`]: properties/constant.EVENT_MOUSE_Y.html
+    pub fn set_event_mouse_position(&mut self, x: i32, y: i32) -> Result<(), ()> {
+        self.set_display(properties::EVENT_MOUSE_X, &x)?;
+        self.set_display(properties::EVENT_MOUSE_Y, &y)
+    }
+
+    /// Gets the relative position of the mouse on the screen, from `0.0` (left resp. top) to `1.0`
+    /// (right resp. bottom), for event sounds triggered by a mouse click
+    /// ([`properties::EVENT_MOUSE_HPOS`] and [`properties::EVENT_MOUSE_VPOS`]).
+    ///
+    /// Returns `None` unless both are set.
+    ///
+    /// [`properties::EVENT_MOUSE_HPOS`]: properties/constant.EVENT_MOUSE_HPOS.html
+    /// [`properties::EVENT_MOUSE_VPOS`]: properties/constant.EVENT_MOUSE_VPOS.html
+    pub fn get_event_mouse_relative_position(&self) -> Option<(f64, f64)> {
+        Some((self.get_parsed(properties::EVENT_MOUSE_HPOS)?,
+            self.get_parsed(properties::EVENT_MOUSE_VPOS)?))
+    }
+
+    /// Sets the relative position of the mouse on the screen, from `0.0` (left resp. top) to `1.0`
+    /// (right resp. bottom), for event sounds triggered by a mouse click
+    /// ([`properties::EVENT_MOUSE_HPOS`] and [`properties::EVENT_MOUSE_VPOS`]).
+    ///
+    /// [`properties::EVENT_MOUSE_HPOS`]: properties/constant.EVENT_MOUSE_HPOS.html
+    /// [`properties::EVENT_MOUSE_VPOS`]: properties/constant.EVENT_MOUSE_VPOS.html
+    pub fn set_event_mouse_relative_position(&mut self, hpos: f64, vpos: f64) -> Result<(), ()> {
+        self.set_display(properties::EVENT_MOUSE_HPOS, &hpos)?;
+        self.set_display(properties::EVENT_MOUSE_VPOS, &vpos)
+    }
+
+    /// Gets the mouse button that triggered an event sound, with 0 being left, 1 middle and 2 right
+    /// ([`properties::EVENT_MOUSE_BUTTON`]).
+    ///
+    /// [`properties::EVENT_MOUSE_BUTTON`]: properties/constant.EVENT_MOUSE_BUTTON.html
+    pub fn get_event_mouse_button(&self) -> Option<u32> {
+        self.get_parsed(properties::EVENT_MOUSE_BUTTON)
+    }
+
+    /// Sets the mouse button that triggered an event sound, with 0 being left, 1 middle and 2 right
+    /// ([`properties::EVENT_MOUSE_BUTTON`]).
+    ///
+    /// [`properties::EVENT_MOUSE_BUTTON`]: properties/constant.EVENT_MOUSE_BUTTON.html
+    pub fn set_event_mouse_button(&mut self, button: u32) -> Result<(), ()> {
+        self.set_display(properties::EVENT_MOUSE_BUTTON, &button)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_values() {
+        assert_eq!(MediaRole::from("a11y"), MediaRole::A11y);
+        assert_eq!(MediaRole::Phone.as_str(), "phone");
+        assert_eq!(FormFactor::from("hands-free"), FormFactor::HandsFree);
+        assert_eq!(Bus::Bluetooth.to_string(), "bluetooth");
+        assert_eq!("mmap_rewrite".parse::<AccessMode>(), Ok(AccessMode::MmapRewrite));
+        assert_eq!(DeviceClass::from("monitor"), DeviceClass::Monitor);
+    }
+
+    #[test]
+    fn unknown_values() {
+        let role = MediaRole::from("alarm");
+        assert_eq!(role, MediaRole::Other("alarm".to_string()));
+        assert_eq!(role.as_str(), "alarm");
+        // Matching is exact, so values round-trip unchanged
+        assert_eq!(FormFactor::from("Speaker").to_string(), "Speaker");
+    }
+
+    #[test]
+    fn equality() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+
+        fn hash<T: Hash>(value: &T) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            value.hash(&mut hasher);
+            hasher.finish()
+        }
+
+        let other = MediaRole::Other("music".to_string());
+        assert_eq!(other, MediaRole::Music);
+        assert_eq!(hash(&other), hash(&MediaRole::Music));
+        assert_ne!(other, MediaRole::Video);
+        assert_ne!(MediaRole::Other("Music".to_string()), MediaRole::Music);
+        assert_eq!(Bus::Other("usb".to_string()), Bus::Usb);
+    }
+}
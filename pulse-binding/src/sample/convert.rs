@@ -0,0 +1,780 @@
+// Copyright 2020 Lyndon Brown
+//
+// This file is part of the PulseAudio Rust language binding.
+//
+// Licensed under the MIT license or the Apache license (version 2.0), at your option. You may not
+// copy, modify, or distribute this file except in compliance with said license. You can find copies
+// of these licenses either in the LICENSE-MIT and LICENSE-APACHE files, or alternatively at
+// <http://opensource.org/licenses/MIT> and <http://www.apache.org/licenses/LICENSE-2.0>
+// respectively.
+//
+// Portions of documentation are copied from the LGPL 2.1+ licensed PulseAudio C headers on a
+// fair-use basis, as discussed in the overall project readme (available in the git repository).
+
+//! Sample format conversion.
+//!
+//! # Overview
+//!
+//! This module converts interleaved sample data between any two of the sample [`Format`]s, entirely
+//! within the client, without involving the server.
+//!
+//! Conversions follow the same conventions as the server’s own sample conversion routines:
+//!
+//!  * Between integer formats (including `U8`, `ALaw` and `ULaw`), samples are converted by bit
+//!    shifting, i.e. by truncation when narrowing.
+//!  * To and from the floating point formats, integer samples are scaled by a power of two (e.g.
+//!    `1 / 32768` for `S16`), with `U8` using `1 / 128` on input and `127` on output.
+//!  * Conversion to an integer format from floating point rounds to the nearest value (ties to
+//!    even), and clips out-of-range values. Floating point output is not clipped.
+//!  * A-law and µ-law are encoded and decoded with the standard G.711 algorithm, at 13-bit and
+//!    14-bit resolution respectively.
+//!
+//! Optionally, triangular (TPDF) dither can be applied when reducing resolution; see
+//! [`Converter::set_dither`].
+//!
+//! Data is processed in fixed size blocks through a small intermediate buffer, keeping the inner
+//! loops simple enough for the compiler to vectorize.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use pulse::sample::Format;
+//! use pulse::sample::convert;
+//!
+//! let s16 = convert::convert(&alaw_data, Format::ALaw, Format::S16le).unwrap();
+//! ```
+//!
+//! [`Format`]: ../enum.Format.html
+//! [`Converter::set_dither`]: struct.Converter.html#method.set_dither
+
+use crate::error::{Code, PAErr};
+use super::Format;
+
+/// Number of samples processed at a time.
+const BLOCK_LEN: usize = 256;
+
+/// Dither to apply when reducing resolution.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Dither {
+    /// No dither.
+    None,
+    /// Triangular probability density function dither, of ±1 LSB of the output format.
+    Triangular,
+}
+
+impl Default for Dither {
+    fn default() -> Self {
+        Dither::None
+    }
+}
+
+/// A sample format converter.
+#[derive(Debug, Clone)]
+pub struct Converter {
+    from: Format,
+    to: Format,
+    dither: Dither,
+    rng: Rng,
+}
+
+impl Converter {
+    /// Creates a new converter.
+    ///
+    /// Returns an error if either format is invalid.
+    pub fn new(from: Format, to: Format) -> Result<Self, PAErr> {
+        if sample_size(from).is_none() || sample_size(to).is_none() {
+            return Err(PAErr::from(Code::Invalid));
+        }
+        Ok(Self { from: from, to: to, dither: Dither::None, rng: Rng::new() })
+    }
+
+    /// Sets the dither to be applied when the output format has a lower resolution than the input.
+    ///
+    /// Dither is never applied when the output format is a floating point format, nor when the
+    /// resolution is not being reduced.
+    pub fn set_dither(&mut self, dither: Dither) {
+        self.dither = dither;
+    }
+
+    /// Gets the input format.
+    pub fn get_input_format(&self) -> Format {
+        self.from
+    }
+
+    /// Gets the output format.
+    pub fn get_output_format(&self) -> Format {
+        self.to
+    }
+
+    /// Converts a buffer of samples.
+    ///
+    /// The input buffer must contain a whole number of samples, and the output buffer must be large
+    /// enough to hold the converted result. Returns the number of samples converted.
+    pub fn convert(&mut self, src: &[u8], dst: &mut [u8]) -> Result<usize, PAErr> {
+        let in_size = sample_size(self.from).unwrap();
+        let out_size = sample_size(self.to).unwrap();
+        if src.len() % in_size != 0 {
+            return Err(PAErr::from(Code::Invalid));
+        }
+        let samples = src.len() / in_size;
+        if dst.len() < samples * out_size {
+            return Err(PAErr::from(Code::TooLarge));
+        }
+        let dst = &mut dst[..(samples * out_size)];
+
+        if self.from == self.to {
+            dst.copy_from_slice(src);
+            return Ok(samples);
+        }
+        if is_byteswap_pair(self.from, self.to) {
+            byteswap(src, dst, in_size);
+            return Ok(samples);
+        }
+
+        let dither = self.dither == Dither::Triangular && !is_float(self.to)
+            && resolution(self.to) < resolution(self.from);
+
+        if !is_float(self.from) && !is_float(self.to) && !dither {
+            let mut block = [0i32; BLOCK_LEN];
+            let chunks = src.chunks(BLOCK_LEN * in_size).zip(dst.chunks_mut(BLOCK_LEN * out_size));
+            for (s, d) in chunks {
+                let block = &mut block[..(s.len() / in_size)];
+                decode_i32(self.from, s, block);
+                narrow_i32(self.to, block);
+                write_native(self.to, block, d);
+            }
+        }
+        else {
+            let mut block = [0f32; BLOCK_LEN];
+            let mut noise = [0f32; BLOCK_LEN];
+            let mut quantized = [0i32; BLOCK_LEN];
+            let chunks = src.chunks(BLOCK_LEN * in_size).zip(dst.chunks_mut(BLOCK_LEN * out_size));
+            for (s, d) in chunks {
+                let n = s.len() / in_size;
+                let block = &mut block[..n];
+                decode_f32(self.from, s, block);
+                if is_float(self.to) {
+                    write_f32(self.to, block, d);
+                    continue;
+                }
+                let noise = match dither {
+                    true => {
+                        self.rng.fill_tpdf(&mut noise[..n]);
+                        Some(&noise[..n])
+                    },
+                    false => None,
+                };
+                let quantized = &mut quantized[..n];
+                quantize(self.to, block, noise, quantized);
+                write_native(self.to, quantized, d);
+            }
+        }
+        Ok(samples)
+    }
+
+    /// Converts a buffer of samples, returning the result in a new buffer.
+    ///
+    /// The input buffer must contain a whole number of samples.
+    pub fn convert_to_vec(&mut self, src: &[u8]) -> Result<Vec<u8>, PAErr> {
+        let in_size = sample_size(self.from).unwrap();
+        let out_size = sample_size(self.to).unwrap();
+        let mut dst = vec![0u8; (src.len() / in_size) * out_size];
+        self.convert(src, &mut dst)?;
+        Ok(dst)
+    }
+}
+
+/// Converts a buffer of samples from one format to another, without dither.
+///
+/// The input buffer must contain a whole number of samples.
+pub fn convert(src: &[u8], from: Format, to: Format) -> Result<Vec<u8>, PAErr> {
+    Converter::new(from, to)?.convert_to_vec(src)
+}
+
+/// Converts a buffer of samples of the given format to native floating point values.
+///
+/// The output buffer must be large enough to hold one value per sample. Returns the number of
+/// samples converted.
+pub fn to_f32(src: &[u8], format: Format, dst: &mut [f32]) -> Result<usize, PAErr> {
+    let size = sample_size(format).ok_or(PAErr::from(Code::Invalid))?;
+    if src.len() % size != 0 {
+        return Err(PAErr::from(Code::Invalid));
+    }
+    let samples = src.len() / size;
+    if dst.len() < samples {
+        return Err(PAErr::from(Code::TooLarge));
+    }
+    decode_f32(format, src, &mut dst[..samples]);
+    Ok(samples)
+}
+
+/// Converts native floating point values to samples of the given format, without dither.
+///
+/// The output buffer must be large enough to hold the converted result. Returns the number of
+/// samples converted.
+pub fn from_f32(src: &[f32], format: Format, dst: &mut [u8]) -> Result<usize, PAErr> {
+    let size = sample_size(format).ok_or(PAErr::from(Code::Invalid))?;
+    if dst.len() < src.len() * size {
+        return Err(PAErr::from(Code::TooLarge));
+    }
+    let dst = &mut dst[..(src.len() * size)];
+    match is_float(format) {
+        true => write_f32(format, src, dst),
+        false => {
+            let mut quantized = [0i32; BLOCK_LEN];
+            for (s, d) in src.chunks(BLOCK_LEN).zip(dst.chunks_mut(BLOCK_LEN * size)) {
+                let quantized = &mut quantized[..s.len()];
+                quantize(format, s, None, quantized);
+                write_native(format, quantized, d);
+            }
+        },
+    }
+    Ok(src.len())
+}
+
+/// Gets the size in bytes of a sample of the given format, without going through the C API.
+pub(crate) fn sample_size(format: Format) -> Option<usize> {
+    match format {
+        Format::U8 | Format::ALaw | Format::ULaw => Some(1),
+        Format::S16le | Format::S16be => Some(2),
+        Format::S24le | Format::S24be => Some(3),
+        Format::F32le | Format::F32be | Format::S32le | Format::S32be | Format::S24_32le |
+        Format::S24_32be => Some(4),
+        Format::Invalid => None,
+    }
+}
+
+fn is_float(format: Format) -> bool {
+    format == Format::F32le || format == Format::F32be
+}
+
+/// Gets the effective resolution of a format, in bits.
+fn resolution(format: Format) -> u32 {
+    match format {
+        Format::U8 => 8,
+        Format::ALaw => 13,
+        Format::ULaw => 14,
+        Format::S16le | Format::S16be => 16,
+        Format::S24le | Format::S24be | Format::S24_32le | Format::S24_32be |
+        Format::F32le | Format::F32be => 24,
+        Format::S32le | Format::S32be => 32,
+        Format::Invalid => 0,
+    }
+}
+
+/// Checks whether the two formats differ only in endianness.
+fn is_byteswap_pair(a: Format, b: Format) -> bool {
+    match (a, b) {
+        (Format::S16le, Format::S16be) | (Format::S16be, Format::S16le) |
+        (Format::F32le, Format::F32be) | (Format::F32be, Format::F32le) |
+        (Format::S32le, Format::S32be) | (Format::S32be, Format::S32le) |
+        (Format::S24le, Format::S24be) | (Format::S24be, Format::S24le) |
+        (Format::S24_32le, Format::S24_32be) | (Format::S24_32be, Format::S24_32le) => true,
+        _ => false,
+    }
+}
+
+fn byteswap(src: &[u8], dst: &mut [u8], size: usize) {
+    for (s, d) in src.chunks_exact(size).zip(dst.chunks_exact_mut(size)) {
+        for i in 0..size {
+            d[i] = s[size - 1 - i];
+        }
+    }
+}
+
+#[inline(always)]
+fn read24le(s: &[u8]) -> i32 {
+    ((s[0] as u32) << 8 | (s[1] as u32) << 16 | (s[2] as u32) << 24) as i32 >> 8
+}
+
+#[inline(always)]
+fn read24be(s: &[u8]) -> i32 {
+    ((s[2] as u32) << 8 | (s[1] as u32) << 16 | (s[0] as u32) << 24) as i32 >> 8
+}
+
+#[inline(always)]
+fn read16(s: &[u8], le: bool) -> i16 {
+    match le {
+        true => i16::from_le_bytes([s[0], s[1]]),
+        false => i16::from_be_bytes([s[0], s[1]]),
+    }
+}
+
+#[inline(always)]
+fn read32(s: &[u8], le: bool) -> u32 {
+    match le {
+        true => u32::from_le_bytes([s[0], s[1], s[2], s[3]]),
+        false => u32::from_be_bytes([s[0], s[1], s[2], s[3]]),
+    }
+}
+
+/// Decodes samples to floating point.
+fn decode_f32(format: Format, src: &[u8], dst: &mut [f32]) {
+    const S16: f32 = 1.0 / (1u32 << 15) as f32;
+    const S24: f32 = 1.0 / (1u32 << 23) as f32;
+    const S32: f32 = 1.0 / (1u32 << 31) as f32;
+    match format {
+        Format::U8 => for (d, s) in dst.iter_mut().zip(src) {
+            *d = (*s as i32 - 128) as f32 * (1.0 / 128.0);
+        },
+        Format::ALaw => for (d, s) in dst.iter_mut().zip(src) {
+            *d = alaw_to_linear16(*s) as f32 * S16;
+        },
+        Format::ULaw => for (d, s) in dst.iter_mut().zip(src) {
+            *d = ulaw_to_linear16(*s) as f32 * S16;
+        },
+        Format::S16le | Format::S16be => {
+            let le = format == Format::S16le;
+            for (d, s) in dst.iter_mut().zip(src.chunks_exact(2)) {
+                *d = read16(s, le) as f32 * S16;
+            }
+        },
+        Format::F32le | Format::F32be => {
+            let le = format == Format::F32le;
+            for (d, s) in dst.iter_mut().zip(src.chunks_exact(4)) {
+                *d = f32::from_bits(read32(s, le));
+            }
+        },
+        Format::S32le | Format::S32be => {
+            let le = format == Format::S32le;
+            for (d, s) in dst.iter_mut().zip(src.chunks_exact(4)) {
+                *d = read32(s, le) as i32 as f32 * S32;
+            }
+        },
+        Format::S24le => for (d, s) in dst.iter_mut().zip(src.chunks_exact(3)) {
+            *d = read24le(s) as f32 * S24;
+        },
+        Format::S24be => for (d, s) in dst.iter_mut().zip(src.chunks_exact(3)) {
+            *d = read24be(s) as f32 * S24;
+        },
+        Format::S24_32le | Format::S24_32be => {
+            let le = format == Format::S24_32le;
+            for (d, s) in dst.iter_mut().zip(src.chunks_exact(4)) {
+                *d = ((read32(s, le) << 8) as i32 >> 8) as f32 * S24;
+            }
+        },
+        Format::Invalid => unreachable!(),
+    }
+}
+
+/// Decodes integer format samples to 32-bit values, with the significant bits at the top.
+fn decode_i32(format: Format, src: &[u8], dst: &mut [i32]) {
+    match format {
+        Format::U8 => for (d, s) in dst.iter_mut().zip(src) {
+            *d = (*s as i32 - 128) << 24;
+        },
+        Format::ALaw => for (d, s) in dst.iter_mut().zip(src) {
+            *d = (alaw_to_linear16(*s) as i32) << 16;
+        },
+        Format::ULaw => for (d, s) in dst.iter_mut().zip(src) {
+            *d = (ulaw_to_linear16(*s) as i32) << 16;
+        },
+        Format::S16le | Format::S16be => {
+            let le = format == Format::S16le;
+            for (d, s) in dst.iter_mut().zip(src.chunks_exact(2)) {
+                *d = (read16(s, le) as i32) << 16;
+            }
+        },
+        Format::S32le | Format::S32be => {
+            let le = format == Format::S32le;
+            for (d, s) in dst.iter_mut().zip(src.chunks_exact(4)) {
+                *d = read32(s, le) as i32;
+            }
+        },
+        Format::S24le => for (d, s) in dst.iter_mut().zip(src.chunks_exact(3)) {
+            *d = read24le(s) << 8;
+        },
+        Format::S24be => for (d, s) in dst.iter_mut().zip(src.chunks_exact(3)) {
+            *d = read24be(s) << 8;
+        },
+        Format::S24_32le | Format::S24_32be => {
+            let le = format == Format::S24_32le;
+            for (d, s) in dst.iter_mut().zip(src.chunks_exact(4)) {
+                *d = (read32(s, le) << 8) as i32;
+            }
+        },
+        Format::F32le | Format::F32be | Format::Invalid => unreachable!(),
+    }
+}
+
+/// Converts 32-bit values, with the significant bits at the top, to the native scale of the given
+/// integer format, in place, by truncation.
+fn narrow_i32(format: Format, buf: &mut [i32]) {
+    match format {
+        Format::U8 => for v in buf.iter_mut() { *v = (*v >> 24) + 128; },
+        Format::ALaw => for v in buf.iter_mut() {
+            *v = linear13_to_alaw((*v >> 19) as i16) as i32;
+        },
+        Format::ULaw => for v in buf.iter_mut() {
+            *v = linear14_to_ulaw((*v >> 18) as i16) as i32;
+        },
+        Format::S16le | Format::S16be => for v in buf.iter_mut() { *v >>= 16; },
+        Format::S24le | Format::S24be | Format::S24_32le | Format::S24_32be =>
+            for v in buf.iter_mut() { *v >>= 8; },
+        Format::S32le | Format::S32be => {},
+        Format::F32le | Format::F32be | Format::Invalid => unreachable!(),
+    }
+}
+
+/// Rounds to the nearest integer, ties to even. Only valid for `|x| < 2^22`.
+#[inline(always)]
+fn rint32(x: f32) -> f32 {
+    const MAGIC: f32 = 12582912.0; // 1.5 * 2^23
+    (x + MAGIC) - MAGIC
+}
+
+/// Rounds to the nearest integer, ties to even. Only valid for `|x| < 2^51`.
+#[inline(always)]
+fn rint64(x: f64) -> f64 {
+    const MAGIC: f64 = 6755399441055744.0; // 1.5 * 2^52
+    (x + MAGIC) - MAGIC
+}
+
+/// Quantizes floating point values to the native scale of the given integer format, clipping
+/// out-of-range values, with optional dither noise (in units of output LSB).
+fn quantize(format: Format, src: &[f32], noise: Option<&[f32]>, dst: &mut [i32]) {
+    // Scale, lower bound, upper bound, offset
+    let (scale, lo, hi, offset) = match format {
+        Format::U8 => (127.0, 0.0, 255.0, 128.0),
+        Format::ALaw => (4095.0, -4096.0, 4095.0, 0.0),
+        Format::ULaw => (8191.0, -8192.0, 8191.0, 0.0),
+        Format::S16le | Format::S16be => (32768.0, -32768.0, 32767.0, 0.0),
+        Format::S24le | Format::S24be | Format::S24_32le | Format::S24_32be =>
+            (8388608.0, -8388608.0, 8388607.0, 0.0),
+        Format::S32le | Format::S32be => (2147483648.0, -2147483648.0, 2147483647.0, 0.0),
+        Format::F32le | Format::F32be | Format::Invalid => unreachable!(),
+    };
+    match resolution(format) <= 16 {
+        true => {
+            let (scale, lo, hi, offset) = (scale as f32, lo as f32, hi as f32, offset as f32);
+            match noise {
+                Some(noise) => for ((d, s), n) in dst.iter_mut().zip(src).zip(noise) {
+                    *d = rint32((*s * scale + offset + *n).max(lo).min(hi)) as i32;
+                },
+                None => for (d, s) in dst.iter_mut().zip(src) {
+                    *d = rint32((*s * scale + offset).max(lo).min(hi)) as i32;
+                },
+            }
+        },
+        false => match noise {
+            Some(noise) => for ((d, s), n) in dst.iter_mut().zip(src).zip(noise) {
+                *d = rint64((*s as f64 * scale + *n as f64).max(lo).min(hi)) as i32;
+            },
+            None => for (d, s) in dst.iter_mut().zip(src) {
+                *d = rint64((*s as f64 * scale).max(lo).min(hi)) as i32;
+            },
+        },
+    }
+    match format {
+        Format::ALaw => for v in dst.iter_mut() { *v = linear13_to_alaw(*v as i16) as i32; },
+        Format::ULaw => for v in dst.iter_mut() { *v = linear14_to_ulaw(*v as i16) as i32; },
+        _ => {},
+    }
+}
+
+/// Writes values in the native scale of the given integer format.
+fn write_native(format: Format, src: &[i32], dst: &mut [u8]) {
+    match format {
+        Format::U8 | Format::ALaw | Format::ULaw => for (d, s) in dst.iter_mut().zip(src) {
+            *d = *s as u8;
+        },
+        Format::S16le => for (d, s) in dst.chunks_exact_mut(2).zip(src) {
+            d.copy_from_slice(&(*s as i16).to_le_bytes());
+        },
+        Format::S16be => for (d, s) in dst.chunks_exact_mut(2).zip(src) {
+            d.copy_from_slice(&(*s as i16).to_be_bytes());
+        },
+        Format::S32le | Format::S24_32le => for (d, s) in dst.chunks_exact_mut(4).zip(src) {
+            d.copy_from_slice(&s.to_le_bytes());
+        },
+        Format::S32be | Format::S24_32be => for (d, s) in dst.chunks_exact_mut(4).zip(src) {
+            d.copy_from_slice(&s.to_be_bytes());
+        },
+        Format::S24le => for (d, s) in dst.chunks_exact_mut(3).zip(src) {
+            d.copy_from_slice(&s.to_le_bytes()[..3]);
+        },
+        Format::S24be => for (d, s) in dst.chunks_exact_mut(3).zip(src) {
+            d.copy_from_slice(&s.to_be_bytes()[1..]);
+        },
+        Format::F32le | Format::F32be | Format::Invalid => unreachable!(),
+    }
+}
+
+/// Writes floating point values.
+fn write_f32(format: Format, src: &[f32], dst: &mut [u8]) {
+    match format {
+        Format::F32le => for (d, s) in dst.chunks_exact_mut(4).zip(src) {
+            d.copy_from_slice(&s.to_bits().to_le_bytes());
+        },
+        Format::F32be => for (d, s) in dst.chunks_exact_mut(4).zip(src) {
+            d.copy_from_slice(&s.to_bits().to_be_bytes());
+        },
+        _ => unreachable!(),
+    }
+}
+
+/* G.711 A-law and µ-law, following the widely used Sun Microsystems reference implementation, as
+   also used by PulseAudio. */
+
+const SIGN_BIT: u8 = 0x80;
+const QUANT_MASK: u8 = 0x0f;
+const SEG_SHIFT: u8 = 4;
+const SEG_MASK: u8 = 0x70;
+const ULAW_BIAS: i16 = 0x84;
+const ULAW_CLIP: i16 = 8159;
+
+const SEG_AEND: [i16; 8] = [0x1F, 0x3F, 0x7F, 0xFF, 0x1FF, 0x3FF, 0x7FF, 0xFFF];
+const SEG_UEND: [i16; 8] = [0x3F, 0x7F, 0xFF, 0x1FF, 0x3FF, 0x7FF, 0xFFF, 0x1FFF];
+
+fn segment(value: i16, table: &[i16; 8]) -> usize {
+    table.iter().position(|end| value <= *end).unwrap_or(8)
+}
+
+/// Converts a 13-bit linear value to A-law.
+fn linear13_to_alaw(pcm: i16) -> u8 {
+    let (mask, pcm) = match pcm >= 0 {
+        true => (0xD5, pcm),
+        false => (0x55, -pcm - 1),
+    };
+    let seg = segment(pcm, &SEG_AEND);
+    if seg >= 8 {
+        return 0x7F ^ mask;
+    }
+    let quant = match seg < 2 {
+        true => (pcm >> 1) as u8 & QUANT_MASK,
+        false => (pcm >> seg) as u8 & QUANT_MASK,
+    };
+    ((seg as u8) << SEG_SHIFT | quant) ^ mask
+}
+
+/// Converts A-law to a 16-bit linear value.
+fn alaw_to_linear16(alaw: u8) -> i16 {
+    let alaw = alaw ^ 0x55;
+    let mut t = ((alaw & QUANT_MASK) as i16) << 4;
+    let seg = (alaw & SEG_MASK) >> SEG_SHIFT;
+    match seg {
+        0 => t += 8,
+        1 => t += 0x108,
+        _ => {
+            t += 0x108;
+            t <<= seg - 1;
+        },
+    }
+    match alaw & SIGN_BIT {
+        0 => -t,
+        _ => t,
+    }
+}
+
+/// Converts a 14-bit linear value to µ-law.
+fn linear14_to_ulaw(pcm: i16) -> u8 {
+    let (mask, pcm) = match pcm < 0 {
+        true => (0x7F, -pcm),
+        false => (0xFF, pcm),
+    };
+    let pcm = pcm.min(ULAW_CLIP) + (ULAW_BIAS >> 2);
+    let seg = segment(pcm, &SEG_UEND);
+    if seg >= 8 {
+        return 0x7F ^ mask;
+    }
+    ((seg as u8) << 4 | ((pcm >> (seg + 1)) as u8 & QUANT_MASK)) ^ mask
+}
+
+/// Converts µ-law to a 16-bit linear value.
+fn ulaw_to_linear16(ulaw: u8) -> i16 {
+    let ulaw = !ulaw;
+    let mut t = (((ulaw & QUANT_MASK) as i16) << 3) + ULAW_BIAS;
+    t <<= (ulaw & SEG_MASK) >> SEG_SHIFT;
+    match ulaw & SIGN_BIT {
+        0 => t - ULAW_BIAS,
+        _ => ULAW_BIAS - t,
+    }
+}
+
+/// Simple xorshift pseudo-random number generator, used for dither.
+#[derive(Debug, Clone)]
+struct Rng(u32);
+
+impl Rng {
+    fn new() -> Self {
+        Rng(0x9E37_79B9)
+    }
+
+    #[inline(always)]
+    fn next_f32(&mut self) -> f32 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.0 = x;
+        (x >> 8) as f32 * (1.0 / (1u32 << 24) as f32)
+    }
+
+    /// Fills with triangular distribution noise in the range (-1, 1).
+    fn fill_tpdf(&mut self, buf: &mut [f32]) {
+        for v in buf.iter_mut() {
+            *v = self.next_f32() - self.next_f32();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [Format; 13] = [Format::U8, Format::ALaw, Format::ULaw, Format::S16le,
+        Format::S16be, Format::F32le, Format::F32be, Format::S32le, Format::S32be, Format::S24le,
+        Format::S24be, Format::S24_32le, Format::S24_32be];
+
+    fn s16le(values: &[i16]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes().to_vec()).collect()
+    }
+
+    fn f32le(values: &[f32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_bits().to_le_bytes().to_vec()).collect()
+    }
+
+    /* Reference values below follow PulseAudio’s own conversion routines (`sconv`, `g711`) */
+
+    #[test]
+    fn ulaw_matches_pulseaudio() {
+        assert_eq!(ulaw_to_linear16(0x00), -32124);
+        assert_eq!(ulaw_to_linear16(0x7F), 0);
+        assert_eq!(ulaw_to_linear16(0x80), 32124);
+        assert_eq!(ulaw_to_linear16(0xFF), 0);
+        assert_eq!(ulaw_to_linear16(0xE7), 260);
+        assert_eq!(linear14_to_ulaw(0), 0xFF);
+        assert_eq!(linear14_to_ulaw(-1), 0x7E);
+        assert_eq!(linear14_to_ulaw(8191), 0x80);
+        assert_eq!(linear14_to_ulaw(-8192), 0x00);
+        for code in 0..=255u8 {
+            let linear = ulaw_to_linear16(code);
+            let back = linear14_to_ulaw(linear >> 2);
+            // Both 0x7F and 0xFF represent zero
+            match linear {
+                0 => assert_eq!(back, 0xFF),
+                _ => assert_eq!(back, code),
+            }
+        }
+    }
+
+    #[test]
+    fn alaw_matches_pulseaudio() {
+        assert_eq!(alaw_to_linear16(0xD5), 8);
+        assert_eq!(alaw_to_linear16(0x55), -8);
+        assert_eq!(alaw_to_linear16(0xAA), 32256);
+        assert_eq!(alaw_to_linear16(0x2A), -32256);
+        assert_eq!(linear13_to_alaw(0), 0xD5);
+        assert_eq!(linear13_to_alaw(-1), 0x55);
+        assert_eq!(linear13_to_alaw(4095), 0xAA);
+        assert_eq!(linear13_to_alaw(-4096), 0x2A);
+        for code in 0..=255u8 {
+            assert_eq!(linear13_to_alaw(alaw_to_linear16(code) >> 3), code);
+        }
+    }
+
+    #[test]
+    fn s16_float_matches_pulseaudio() {
+        let src = s16le(&[0, 0x4000, -0x8000, 0x7FFF, -1]);
+        let out = convert(&src, Format::S16le, Format::F32le).unwrap();
+        assert_eq!(out, f32le(&[0.0, 0.5, -1.0, 32767.0 / 32768.0, -1.0 / 32768.0]));
+
+        let src = f32le(&[0.0, 0.5, 1.0, -1.0, 2.0, -2.0, 0.25 / 32768.0, 1.5 / 32768.0]);
+        let out = convert(&src, Format::F32le, Format::S16le).unwrap();
+        assert_eq!(out, s16le(&[0, 16384, 32767, -32768, 32767, -32768, 0, 2]));
+    }
+
+    #[test]
+    fn u8_matches_pulseaudio() {
+        let out = convert(&[0, 64, 128, 255], Format::U8, Format::F32le).unwrap();
+        assert_eq!(out, f32le(&[-1.0, -0.5, 0.0, 127.0 / 128.0]));
+
+        let out = convert(&f32le(&[-1.0, 0.0, 1.0, 5.0]), Format::F32le, Format::U8).unwrap();
+        assert_eq!(out, vec![1, 128, 255, 255]);
+
+        let out = convert(&[0, 128, 255], Format::U8, Format::S16le).unwrap();
+        assert_eq!(out, s16le(&[-0x8000, 0, 0x7F00]));
+
+        let out = convert(&s16le(&[-0x8000, 0, 0x7FFF, 0x00FF]), Format::S16le, Format::U8)
+            .unwrap();
+        assert_eq!(out, vec![0, 128, 255, 128]);
+    }
+
+    #[test]
+    fn s24_layouts() {
+        let src = vec![0x56, 0x34, 0x12, 0x00, 0x00, 0x80];
+        let out = convert(&src, Format::S24le, Format::S32le).unwrap();
+        assert_eq!(out, vec![0x00, 0x56, 0x34, 0x12, 0x00, 0x00, 0x00, 0x80]);
+
+        let out = convert(&src, Format::S24le, Format::S24be).unwrap();
+        assert_eq!(out, vec![0x12, 0x34, 0x56, 0x80, 0x00, 0x00]);
+
+        let out = convert(&src, Format::S24le, Format::S24_32le).unwrap();
+        assert_eq!(out, vec![0x56, 0x34, 0x12, 0x00, 0x00, 0x00, 0x80, 0xFF]);
+
+        let out = convert(&src, Format::S24le, Format::F32le).unwrap();
+        assert_eq!(out, f32le(&[0x123456 as f32 / 8388608.0, -1.0]));
+    }
+
+    #[test]
+    fn lossless_round_trips() {
+        // All values representable in every format with at least 16 bits of resolution
+        let values: Vec<i16> = (-32768..32768).step_by(97).map(|v| v as i16).collect();
+        let src = s16le(&values);
+        for format in ALL.iter().filter(|f| resolution(**f) >= 16) {
+            let there = convert(&src, Format::S16le, *format).unwrap();
+            let back = convert(&there, *format, Format::S16le).unwrap();
+            assert_eq!(back, src, "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn all_pairs() {
+        let src = f32le(&[0.0, 0.1, -0.3, 0.7, -0.999, 0.5]);
+        for from in ALL.iter() {
+            let data = convert(&src, Format::F32le, *from).unwrap();
+            for to in ALL.iter() {
+                let out = convert(&data, *from, *to).unwrap();
+                assert_eq!(out.len(), 6 * sample_size(*to).unwrap());
+                let mut check = [0f32; 6];
+                to_f32(&out, *to, &mut check).unwrap();
+                let tolerance = match (resolution(*from)).min(resolution(*to)) {
+                    8 => 0.02,
+                    13 | 14 => 0.03,
+                    _ => 0.0001,
+                };
+                let mut expected = [0f32; 6];
+                to_f32(&src, Format::F32le, &mut expected).unwrap();
+                for (c, e) in check.iter().zip(expected.iter()) {
+                    assert!((c - e).abs() <= tolerance, "{:?} -> {:?}: {} vs {}", from, to, c, e);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn dither() {
+        let src = f32le(&[0.25 / 32768.0; 1000]);
+        let mut converter = Converter::new(Format::F32le, Format::S16le).unwrap();
+        let plain = converter.convert_to_vec(&src).unwrap();
+        assert!(plain.iter().all(|b| *b == 0));
+
+        converter.set_dither(Dither::Triangular);
+        let dithered = converter.convert_to_vec(&src).unwrap();
+        let values: Vec<i16> = dithered.chunks(2)
+            .map(|c| i16::from_le_bytes([c[0], c[1]]))
+            .collect();
+        assert!(values.iter().all(|v| *v >= -1 && *v <= 1));
+        assert!(values.iter().any(|v| *v != 0));
+        let mean = values.iter().map(|v| *v as f64).sum::<f64>() / values.len() as f64;
+        assert!((mean - 0.25).abs() < 0.1);
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(Converter::new(Format::Invalid, Format::U8).is_err());
+        assert!(convert(&[0u8; 3], Format::S16le, Format::U8).is_err());
+        let mut converter = Converter::new(Format::U8, Format::S16le).unwrap();
+        assert!(converter.convert(&[0u8; 4], &mut [0u8; 7]).is_err());
+    }
+}
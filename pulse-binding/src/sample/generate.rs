@@ -0,0 +1,455 @@
+// Copyright 2020 Lyndon Brown
+//
+// This file is part of the PulseAudio Rust language binding.
+//
+// Licensed under the MIT license or the Apache license (version 2.0), at your option. You may not
+// copy, modify, or distribute this file except in compliance with said license. You can find copies
+// of these licenses either in the LICENSE-MIT and LICENSE-APACHE files, or alternatively at
+// <http://opensource.org/licenses/MIT> and <http://www.apache.org/licenses/LICENSE-2.0>
+// respectively.
+//
+// Portions of documentation are copied from the LGPL 2.1+ licensed PulseAudio C headers on a
+// fair-use basis, as discussed in the overall project readme (available in the git repository).
+
+//! Test signal generation.
+//!
+//! # Overview
+//!
+//! A [`Generator`] produces a continuous test signal, as described by a [`Signal`], in any sample
+//! format. Available signals are:
+//!
+//!  * A sine wave of a given frequency.
+//!  * White or pink noise.
+//!  * A repeating logarithmic sweep between two frequencies.
+//!  * Periodic impulses.
+//!  * Channel identification: a tone played on each channel in turn, for checking speaker wiring.
+//!    The channel currently playing can be found with [`Generator::get_active_position`], and
+//!    named for the user with [`Position::to_pretty_string`].
+//!
+//! Except for channel identification, the signal is played on all channels; noise is independent
+//! on each channel.
+//!
+//! To play a test signal directly, see the [`stream::signal`] module.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use pulse::sample::generate::{Generator, Signal};
+//!
+//! let mut generator = Generator::new(&spec, &map, Signal::Sine { frequency: 440.0 }).unwrap();
+//! generator.fill(&mut data).unwrap();
+//! ```
+//!
+//! [`Generator`]: struct.Generator.html
+//! [`Generator::get_active_position`]: struct.Generator.html#method.get_active_position
+//! [`Signal`]: enum.Signal.html
+//! [`Position::to_pretty_string`]: ../../channelmap/enum.Position.html#method.to_pretty_string
+//! [`stream::signal`]: ../../stream/signal/index.html
+
+use std::f64::consts::PI;
+use crate::channelmap::{Map, Position};
+use crate::error::{Code, PAErr};
+use crate::time::{MicroSeconds, MICROS_PER_SEC};
+use super::{Spec, CHANNELS_MAX, RATE_MAX};
+use super::convert;
+
+/// Default amplitude of generated signals (-6 dBFS).
+pub const DEFAULT_AMPLITUDE: f32 = 0.5;
+
+/// Number of frames processed at a time.
+const BLOCK_FRAMES: usize = 256;
+
+/// A test signal.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Signal {
+    /// A sine wave.
+    Sine {
+        /// Frequency, in Hz.
+        frequency: f64,
+    },
+    /// White noise, i.e. with equal power at all frequencies.
+    WhiteNoise,
+    /// Pink noise, i.e. with equal power in each octave.
+    PinkNoise,
+    /// A sine wave sweeping logarithmically from one frequency to another, then starting again.
+    Sweep {
+        /// Starting frequency, in Hz.
+        start: f64,
+        /// Ending frequency, in Hz.
+        end: f64,
+        /// Time taken by each sweep.
+        duration: MicroSeconds,
+    },
+    /// Single full amplitude samples, separated by silence.
+    Impulse {
+        /// Time between impulses.
+        interval: MicroSeconds,
+    },
+    /// A sine wave tone on each channel in turn, in channel map order, then starting again.
+    ChannelIdent {
+        /// Frequency of the tone, in Hz.
+        frequency: f64,
+        /// Length of the tone on each channel.
+        tone: MicroSeconds,
+        /// Length of the silence following each tone.
+        gap: MicroSeconds,
+    },
+}
+
+/// Generates test signals.
+#[derive(Debug, Clone)]
+pub struct Generator {
+    spec: Spec,
+    map: Map,
+    channels: usize,
+    signal: Signal,
+    amplitude: f32,
+    /// Frames generated since the signal was set.
+    frame: u64,
+    /// Phase of the current tone, in radians.
+    phase: f64,
+    /// Random number generator state.
+    seed: u32,
+    /// Pink noise filter state, per channel.
+    pink: Vec<[f64; 7]>,
+    buffer: Vec<f32>,
+}
+
+impl Generator {
+    /// Creates a new generator, for data of the given specification and channel map, producing the
+    /// given signal at [`DEFAULT_AMPLITUDE`].
+    ///
+    /// Returns an error if the specification is invalid, does not match the channel map, or the
+    /// signal is invalid for it (e.g. has a frequency above the Nyquist frequency).
+    ///
+    /// [`DEFAULT_AMPLITUDE`]: constant.DEFAULT_AMPLITUDE.html
+    pub fn new(spec: &Spec, map: &Map, signal: Signal) -> Result<Self, PAErr> {
+        if convert::sample_size(spec.format).is_none() || spec.channels == 0 ||
+           spec.channels as usize > CHANNELS_MAX || spec.rate == 0 || spec.rate > RATE_MAX ||
+           map.len() != spec.channels
+        {
+            return Err(PAErr::from(Code::Invalid));
+        }
+        let mut generator = Self {
+            spec: *spec,
+            map: *map,
+            channels: spec.channels as usize,
+            signal: signal,
+            amplitude: DEFAULT_AMPLITUDE,
+            frame: 0,
+            phase: 0.0,
+            seed: 1,
+            pink: vec![[0.0; 7]; spec.channels as usize],
+            buffer: Vec::new(),
+        };
+        generator.set_signal(signal)?;
+        Ok(generator)
+    }
+
+    /// Gets the specification of the data generated.
+    #[inline]
+    pub fn get_spec(&self) -> &Spec {
+        &self.spec
+    }
+
+    /// Gets the channel map of the data generated.
+    #[inline]
+    pub fn get_channel_map(&self) -> &Map {
+        &self.map
+    }
+
+    /// Gets the signal being generated.
+    #[inline]
+    pub fn get_signal(&self) -> Signal {
+        self.signal
+    }
+
+    /// Changes the signal being generated, starting it from the beginning.
+    ///
+    /// Returns an error if the signal is invalid, in which case the current signal continues.
+    pub fn set_signal(&mut self, signal: Signal) -> Result<(), PAErr> {
+        let nyquist = self.spec.rate as f64 / 2.0;
+        let valid_frequency = |f: f64| f > 0.0 && f <= nyquist;
+        let valid = match signal {
+            Signal::Sine { frequency } => valid_frequency(frequency),
+            Signal::WhiteNoise | Signal::PinkNoise => true,
+            Signal::Sweep { start, end, duration } => {
+                valid_frequency(start) && valid_frequency(end) && self.frames(duration) > 0
+            },
+            Signal::Impulse { interval } => self.frames(interval) > 0,
+            Signal::ChannelIdent { frequency, tone, gap: _ } => {
+                valid_frequency(frequency) && self.frames(tone) > 0
+            },
+        };
+        if !valid {
+            return Err(PAErr::from(Code::Invalid));
+        }
+        self.signal = signal;
+        self.frame = 0;
+        self.phase = 0.0;
+        for s in self.pink.iter_mut() {
+            *s = [0.0; 7];
+        }
+        Ok(())
+    }
+
+    /// Gets the amplitude of generated signals, as a linear factor of full scale.
+    #[inline]
+    pub fn get_amplitude(&self) -> f32 {
+        self.amplitude
+    }
+
+    /// Sets the amplitude of generated signals, as a linear factor of full scale, clamped to the
+    /// range `0.0` to `1.0`.
+    ///
+    /// Impulses are always generated at full scale.
+    pub fn set_amplitude(&mut self, amplitude: f32) {
+        self.amplitude = amplitude.max(0.0).min(1.0);
+    }
+
+    /// Gets the position of the channel currently being identified, if generating a channel
+    /// identification signal and not currently in the gap between tones.
+    pub fn get_active_position(&self) -> Option<Position> {
+        self.ident_channel(self.frame).map(|c| self.map.get()[c])
+    }
+
+    /// Fills a buffer with interleaved frames, in the format of the specification.
+    ///
+    /// The buffer must have space for a whole number of frames. Returns the number of frames
+    /// generated.
+    pub fn fill(&mut self, data: &mut [u8]) -> Result<usize, PAErr> {
+        let frame_size = convert::sample_size(self.spec.format).unwrap() * self.channels;
+        if data.len() % frame_size != 0 {
+            return Err(PAErr::from(Code::Invalid));
+        }
+        let mut buffer = std::mem::replace(&mut self.buffer, Vec::new());
+        buffer.resize(BLOCK_FRAMES * self.channels, 0.0);
+        for chunk in data.chunks_mut(BLOCK_FRAMES * frame_size) {
+            let samples = &mut buffer[..(chunk.len() / frame_size * self.channels)];
+            self.generate(samples);
+            convert::from_f32(samples, self.spec.format, chunk)?;
+        }
+        self.buffer = buffer;
+        Ok(data.len() / frame_size)
+    }
+
+    /// Fills a buffer with interleaved native floating point frames, regardless of the sample
+    /// format of the specification.
+    ///
+    /// The buffer must have space for a whole number of frames. Returns the number of frames
+    /// generated.
+    pub fn fill_f32(&mut self, data: &mut [f32]) -> Result<usize, PAErr> {
+        if data.len() % self.channels != 0 {
+            return Err(PAErr::from(Code::Invalid));
+        }
+        self.generate(data);
+        Ok(data.len() / self.channels)
+    }
+
+    /// Converts a duration to a number of frames.
+    fn frames(&self, duration: MicroSeconds) -> u64 {
+        duration.0 * self.spec.rate as u64 / MICROS_PER_SEC
+    }
+
+    /// Gets the channel being identified at the given frame, if any.
+    fn ident_channel(&self, frame: u64) -> Option<usize> {
+        match self.signal {
+            Signal::ChannelIdent { frequency: _, tone, gap } => {
+                let (tone, gap) = (self.frames(tone), self.frames(gap));
+                let offset = frame % ((tone + gap) * self.channels as u64);
+                match offset % (tone + gap) < tone {
+                    true => Some((offset / (tone + gap)) as usize),
+                    false => None,
+                }
+            },
+            _ => None,
+        }
+    }
+
+    /// Advances the tone phase by one sample at the given frequency, returning the previous value.
+    fn advance(&mut self, frequency: f64) -> f64 {
+        let phase = self.phase;
+        self.phase += 2.0 * PI * frequency / self.spec.rate as f64;
+        if self.phase >= 2.0 * PI {
+            self.phase -= 2.0 * PI;
+        }
+        phase
+    }
+
+    /// Gets a uniformly distributed random value between -1 and 1 (xorshift).
+    fn random(&mut self) -> f64 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        self.seed as f64 / std::u32::MAX as f64 * 2.0 - 1.0
+    }
+
+    fn generate(&mut self, data: &mut [f32]) {
+        let channels = self.channels;
+        let amplitude = self.amplitude as f64;
+        for frame in data.chunks_exact_mut(channels) {
+            match self.signal {
+                Signal::Sine { frequency } => {
+                    let v = (self.advance(frequency).sin() * amplitude) as f32;
+                    for s in frame.iter_mut() {
+                        *s = v;
+                    }
+                },
+                Signal::WhiteNoise => {
+                    for s in frame.iter_mut() {
+                        *s = (self.random() * amplitude) as f32;
+                    }
+                },
+                Signal::PinkNoise => {
+                    for c in 0..channels {
+                        let white = self.random();
+                        frame[c] = (self.pink_filter(c, white) * amplitude) as f32;
+                    }
+                },
+                Signal::Sweep { start, end, duration } => {
+                    let length = self.frames(duration);
+                    let progress = (self.frame % length) as f64 / length as f64;
+                    if self.frame % length == 0 {
+                        self.phase = 0.0;
+                    }
+                    let frequency = start * (end / start).powf(progress);
+                    let v = (self.advance(frequency).sin() * amplitude) as f32;
+                    for s in frame.iter_mut() {
+                        *s = v;
+                    }
+                },
+                Signal::Impulse { interval } => {
+                    let v = match self.frame % self.frames(interval) {
+                        0 => 1.0,
+                        _ => 0.0,
+                    };
+                    for s in frame.iter_mut() {
+                        *s = v;
+                    }
+                },
+                Signal::ChannelIdent { frequency, tone: _, gap: _ } => {
+                    for s in frame.iter_mut() {
+                        *s = 0.0;
+                    }
+                    match self.ident_channel(self.frame) {
+                        Some(c) => {
+                            // Start each tone at a zero crossing
+                            if self.ident_channel(self.frame.wrapping_sub(1)) != Some(c) {
+                                self.phase = 0.0;
+                            }
+                            frame[c] = (self.advance(frequency).sin() * amplitude) as f32;
+                        },
+                        None => {},
+                    }
+                },
+            }
+            self.frame += 1;
+        }
+    }
+
+    /// Filters white noise to pink noise (Paul Kellet’s refined method).
+    fn pink_filter(&mut self, channel: usize, white: f64) -> f64 {
+        let b = &mut self.pink[channel];
+        b[0] = 0.99886 * b[0] + white * 0.0555179;
+        b[1] = 0.99332 * b[1] + white * 0.0750759;
+        b[2] = 0.96900 * b[2] + white * 0.1538520;
+        b[3] = 0.86650 * b[3] + white * 0.3104856;
+        b[4] = 0.55000 * b[4] + white * 0.5329522;
+        b[5] = -0.7616 * b[5] - white * 0.0168980;
+        let pink = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] + white * 0.5362;
+        b[6] = white * 0.115926;
+        // Normalise to roughly the same peak level as the white noise
+        (pink * 0.11).max(-1.0).min(1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample::Format;
+    use crate::test_util::{float_spec, front_map};
+
+    #[test]
+    fn sine() {
+        let signal = Signal::Sine { frequency: 2000.0 };
+        let mut generator = Generator::new(&float_spec(8000, 2), &front_map(2), signal).unwrap();
+        generator.set_amplitude(1.0);
+        let mut data = [0.0f32; 10];
+        assert_eq!(generator.fill_f32(&mut data).unwrap(), 5);
+        let expected = [0.0, 0.0, 1.0, 1.0, 0.0, 0.0, -1.0, -1.0, 0.0, 0.0];
+        for (v, e) in data.iter().zip(expected.iter()) {
+            assert!((v - e).abs() < 1e-6);
+        }
+
+        let spec = Spec { format: Format::S16le, channels: 1, rate: 8000 };
+        let mut generator = Generator::new(&spec, &front_map(1), signal).unwrap();
+        let mut data = [0u8; 6];
+        generator.fill(&mut data).unwrap();
+        assert_eq!(i16::from_le_bytes([data[2], data[3]]), 16384);
+        assert!(generator.fill(&mut data[..3]).is_err());
+    }
+
+    #[test]
+    fn noise() {
+        for signal in [Signal::WhiteNoise, Signal::PinkNoise].iter() {
+            let mut generator = Generator::new(&float_spec(8000, 2), &front_map(2), *signal).unwrap();
+            let mut data = vec![0.0f32; 8000];
+            generator.fill_f32(&mut data).unwrap();
+            assert!(data.iter().all(|v| v.abs() <= DEFAULT_AMPLITUDE));
+            let mean_square = data.iter().map(|v| v * v).sum::<f32>() / data.len() as f32;
+            assert!(mean_square > 0.001);
+            // Channels are independent
+            assert!(data.chunks(2).any(|f| f[0] != f[1]));
+        }
+    }
+
+    #[test]
+    fn sweep_and_impulse() {
+        let signal = Signal::Sweep { start: 100.0, end: 1000.0, duration: MicroSeconds(1000) };
+        let mut generator = Generator::new(&float_spec(8000, 1), &front_map(1), signal).unwrap();
+        let mut data = [0.0f32; 16];
+        generator.fill_f32(&mut data).unwrap();
+        // Restarts after 8 frames
+        assert_eq!(data[0], 0.0);
+        assert_eq!(data[8], 0.0);
+        assert!(data[1] > 0.0 && (data[1] - data[9]).abs() < 1e-6);
+
+        let signal = Signal::Impulse { interval: MicroSeconds(500) };
+        let mut generator = Generator::new(&float_spec(8000, 1), &front_map(1), signal).unwrap();
+        let mut data = [0.5f32; 10];
+        generator.fill_f32(&mut data).unwrap();
+        assert_eq!(data, [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn channel_ident() {
+        let signal = Signal::ChannelIdent {
+            frequency: 2000.0,
+            tone: MicroSeconds(500),
+            gap: MicroSeconds(250),
+        };
+        let mut generator = Generator::new(&float_spec(8000, 2), &front_map(2), signal).unwrap();
+        assert_eq!(generator.get_active_position(), Some(Position::FrontLeft));
+        // Tone on the left only, then the gap
+        let mut data = [0.0f32; 10];
+        generator.fill_f32(&mut data).unwrap();
+        assert!(data[2] > 0.0 && data[3] == 0.0);
+        assert!(data[8..].iter().all(|v| *v == 0.0));
+        assert_eq!(generator.get_active_position(), None);
+        // Then on the right only, then back to the left
+        let mut data = [0.0f32; 14];
+        generator.fill_f32(&mut data).unwrap();
+        assert!(data[..5].iter().all(|v| *v == 0.0) && data[5] > 0.0);
+        assert_eq!(generator.get_active_position(), Some(Position::FrontLeft));
+    }
+
+    #[test]
+    fn invalid() {
+        let sine = Signal::Sine { frequency: 5000.0 };
+        assert!(Generator::new(&float_spec(8000, 1), &front_map(1), sine).is_err());
+        assert!(Generator::new(&float_spec(8000, 2), &front_map(1), Signal::WhiteNoise).is_err());
+        let mut generator = Generator::new(&float_spec(8000, 1), &front_map(1), Signal::WhiteNoise).unwrap();
+        assert!(generator.set_signal(Signal::Impulse { interval: MicroSeconds(0) }).is_err());
+        assert_eq!(generator.get_signal(), Signal::WhiteNoise);
+    }
+}
@@ -67,8 +67,14 @@
 //! [`Spec::sample_size`]: struct.Spec.html#method.sample_size
 //! [`Spec::bytes_to_usec`]: struct.Spec.html#method.bytes_to_usec
 
+pub mod convert;
+pub mod generate;
+pub mod resample;
+
 use std::ffi::{CStr, CString};
 use std::borrow::Cow;
+use std::str::FromStr;
+use crate::error::{Code, PAErr};
 use crate::time::MicroSeconds;
 
 pub use capi::PA_CHANNELS_MAX as CHANNELS_MAX;
@@ -76,7 +82,7 @@ pub use capi::PA_RATE_MAX as RATE_MAX;
 
 /// Sample format
 #[repr(C)]
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 #[allow(non_camel_case_types)]
 pub enum Format {
     /* NOTE: This enum’s variants and variant values **must** remain identical to the `sys` crate
@@ -201,8 +207,12 @@ mod ei_formats {
 pub const SAMPLE_FLOAT32: Format = SAMPLE_FLOAT32NE;
 
 /// A sample format and attribute specification.
+///
+/// With the `serde` feature enabled, this serializes as a structure, with the format given by name
+/// (e.g. `"s16le"`).
 #[repr(C)]
 #[derive(Debug, Copy, Clone, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Spec {
     /* NOTE: This struct must be directly usable by the C API, thus same attributes/layout/etc */
     /// The sample format.
@@ -426,3 +436,70 @@ impl Format {
         self.is_ne().and_then(|b| Some(!b))
     }
 }
+
+impl std::fmt::Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match Format::to_string(self) {
+            Some(s) => f.write_str(&s),
+            None => f.write_str("(invalid)"),
+        }
+    }
+}
+
+/// Parses a sample format name, as accepted by [`Format::parse`](enum.Format.html#method.parse).
+///
+/// Fails with `Code::Invalid` if the name is not recognised.
+impl FromStr for Format {
+    type Err = PAErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match Format::parse(s) {
+            Format::Invalid => Err(PAErr::from(Code::Invalid)),
+            format => Ok(format),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+serde_via_string!(Format, "sample format");
+
+impl std::fmt::Display for Spec {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&self.print())
+    }
+}
+
+/// Parses a sample specification of the form produced by [`Spec::print`], e.g. `s16le 2ch 44100Hz`.
+///
+/// Fails with `Code::Invalid` if the string is malformed, or describes an invalid specification.
+///
+/// [`Spec::print`]: struct.Spec.html#method.print
+impl FromStr for Spec {
+    type Err = PAErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split_whitespace().collect();
+        let (format, channels, rate) = match parts[..] {
+            [format, channels, rate] => (format, channels, rate),
+            _ => return Err(PAErr::from(Code::Invalid)),
+        };
+        let channels = match channels.ends_with("ch") {
+            true => channels[..channels.len() - 2].parse::<u8>().ok(),
+            false => None,
+        };
+        let rate = match rate.ends_with("Hz") {
+            true => rate[..rate.len() - 2].parse::<u32>().ok(),
+            false => None,
+        };
+        let spec = match (format.parse::<Format>(), channels, rate) {
+            (Ok(format), Some(channels), Some(rate)) => {
+                Spec { format: format, channels: channels, rate: rate }
+            },
+            _ => return Err(PAErr::from(Code::Invalid)),
+        };
+        match spec.is_valid() {
+            true => Ok(spec),
+            false => Err(PAErr::from(Code::Invalid)),
+        }
+    }
+}
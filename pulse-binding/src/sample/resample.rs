@@ -0,0 +1,553 @@
+// Copyright 2020 Lyndon Brown
+//
+// This file is part of the PulseAudio Rust language binding.
+//
+// Licensed under the MIT license or the Apache license (version 2.0), at your option. You may not
+// copy, modify, or distribute this file except in compliance with said license. You can find copies
+// of these licenses either in the LICENSE-MIT and LICENSE-APACHE files, or alternatively at
+// <http://opensource.org/licenses/MIT> and <http://www.apache.org/licenses/LICENSE-2.0>
+// respectively.
+//
+// Portions of documentation are copied from the LGPL 2.1+ licensed PulseAudio C headers on a
+// fair-use basis, as discussed in the overall project readme (available in the git repository).
+
+//! Sample rate conversion.
+//!
+//! # Overview
+//!
+//! A [`Resampler`] converts a continuous stream of interleaved audio, as described by a [`Spec`],
+//! from one sample rate to another within the client. This is useful where server-side resampling
+//! is unavailable or unwanted, for instance with streams connected using the
+//! [`stream::flags::FIX_RATE`] flag.
+//!
+//! Data is fed through [`Resampler::process`] in buffers of any size; the resampler keeps whatever
+//! history it needs between calls. The sample format and channel count are unchanged by
+//! resampling. At the end of a stream, [`Resampler::flush`] produces the remaining output.
+//!
+//! Several levels of [`Quality`] are available, from cheap linear interpolation to windowed sinc
+//! interpolation with long filters.
+//!
+//! # Variable ratio
+//!
+//! The conversion ratio can be changed at any time, without discontinuity, with
+//! [`Resampler::set_ratio`] or [`Resampler::set_output_rate`]. This allows the resampler to be used
+//! to compensate for clock drift between the application’s audio source and the device, as an
+//! alternative to, or alongside, [`Stream::update_sample_rate`] with streams connected using the
+//! [`stream::flags::VARIABLE_RATE`] flag.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use pulse::sample::{Spec, Format};
+//! use pulse::sample::resample::{Resampler, Quality};
+//!
+//! let spec = Spec { format: Format::S16le, channels: 2, rate: 44100 };
+//! let mut resampler = Resampler::new(&spec, 48000, Quality::SincMedium).unwrap();
+//!
+//! let mut output = Vec::new();
+//! resampler.process(&input, &mut output).unwrap();
+//! ```
+//!
+//! [`Resampler`]: struct.Resampler.html
+//! [`Resampler::process`]: struct.Resampler.html#method.process
+//! [`Resampler::flush`]: struct.Resampler.html#method.flush
+//! [`Resampler::set_ratio`]: struct.Resampler.html#method.set_ratio
+//! [`Resampler::set_output_rate`]: struct.Resampler.html#method.set_output_rate
+//! [`Quality`]: enum.Quality.html
+//! [`Spec`]: ../struct.Spec.html
+//! [`Stream::update_sample_rate`]: ../../stream/struct.Stream.html#method.update_sample_rate
+//! [`stream::flags::FIX_RATE`]: ../../stream/flags/constant.FIX_RATE.html
+//! [`stream::flags::VARIABLE_RATE`]: ../../stream/flags/constant.VARIABLE_RATE.html
+
+use std::f64::consts::PI;
+use crate::error::{Code, PAErr};
+use super::{Spec, CHANNELS_MAX, RATE_MAX};
+use super::convert;
+
+/// Lowest supported conversion ratio.
+pub const RATIO_MIN: f64 = 1.0 / 256.0;
+
+/// Highest supported conversion ratio.
+pub const RATIO_MAX: f64 = 256.0;
+
+/// Resolution of the filter table, in entries per zero crossing.
+const TABLE_RESOLUTION: usize = 256;
+
+/// Kaiser window shape parameter.
+const KAISER_BETA: f64 = 8.0;
+
+/// Fraction of the output Nyquist frequency passed when downsampling.
+const ROLLOFF: f64 = 0.95;
+
+/// Resampling quality.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Quality {
+    /// Linear interpolation. Very cheap, but with poor high frequency response and aliasing.
+    Linear,
+    /// Windowed sinc interpolation, with a filter of 8 zero crossings either side.
+    SincFast,
+    /// Windowed sinc interpolation, with a filter of 16 zero crossings either side.
+    SincMedium,
+    /// Windowed sinc interpolation, with a filter of 32 zero crossings either side.
+    SincBest,
+}
+
+impl Quality {
+    fn zero_crossings(self) -> usize {
+        match self {
+            Quality::Linear => 0,
+            Quality::SincFast => 8,
+            Quality::SincMedium => 16,
+            Quality::SincBest => 32,
+        }
+    }
+}
+
+impl Default for Quality {
+    fn default() -> Self {
+        Quality::SincMedium
+    }
+}
+
+/// A sample rate converter.
+#[derive(Debug, Clone)]
+pub struct Resampler {
+    spec: Spec,
+    channels: usize,
+    quality: Quality,
+    /// Output frames per input frame.
+    ratio: f64,
+    /// Filter cutoff, relative to the input Nyquist frequency.
+    cutoff: f64,
+    /// Windowed sinc function, sampled from zero to the last zero crossing.
+    table: Vec<f32>,
+    /// Interleaved input history.
+    history: Vec<f32>,
+    /// Position of the next output frame, in input frames from the start of the history.
+    position: f64,
+    /// Scratch buffers for format conversion.
+    input: Vec<f32>,
+    output: Vec<f32>,
+}
+
+impl Resampler {
+    /// Creates a new resampler, converting data described by `spec` to the given output rate.
+    ///
+    /// Returns an error if the specification or output rate is invalid.
+    pub fn new(spec: &Spec, output_rate: u32, quality: Quality) -> Result<Self, PAErr> {
+        if convert::sample_size(spec.format).is_none() || spec.channels == 0 ||
+           spec.channels as usize > CHANNELS_MAX || !valid_rate(spec.rate) ||
+           !valid_rate(output_rate)
+        {
+            return Err(PAErr::from(Code::Invalid));
+        }
+        let mut resampler = Self {
+            spec: *spec,
+            channels: spec.channels as usize,
+            quality: quality,
+            ratio: 1.0,
+            cutoff: 1.0,
+            table: build_table(quality.zero_crossings()),
+            history: Vec::new(),
+            position: 0.0,
+            input: Vec::new(),
+            output: Vec::new(),
+        };
+        resampler.set_output_rate(output_rate)?;
+        resampler.reset();
+        Ok(resampler)
+    }
+
+    /// Gets the specification of the data handled, including the input rate.
+    #[inline]
+    pub fn get_spec(&self) -> &Spec {
+        &self.spec
+    }
+
+    /// Gets the quality in use.
+    #[inline]
+    pub fn get_quality(&self) -> Quality {
+        self.quality
+    }
+
+    /// Gets the current conversion ratio, in output frames per input frame.
+    #[inline]
+    pub fn get_ratio(&self) -> f64 {
+        self.ratio
+    }
+
+    /// Sets the conversion ratio, in output frames per input frame.
+    ///
+    /// This may be called at any time, taking effect from the next output frame. Fine adjustments
+    /// can be used to correct for clock drift.
+    ///
+    /// Returns an error if the ratio is outside of the range [`RATIO_MIN`] to [`RATIO_MAX`].
+    ///
+    /// [`RATIO_MIN`]: constant.RATIO_MIN.html
+    /// [`RATIO_MAX`]: constant.RATIO_MAX.html
+    pub fn set_ratio(&mut self, ratio: f64) -> Result<(), PAErr> {
+        if !(ratio >= RATIO_MIN && ratio <= RATIO_MAX) {
+            return Err(PAErr::from(Code::Invalid));
+        }
+        self.ratio = ratio;
+        self.cutoff = match ratio < 1.0 {
+            true => ratio * ROLLOFF,
+            false => 1.0,
+        };
+        Ok(())
+    }
+
+    /// Sets the conversion ratio from an output rate, relative to the input rate of the
+    /// specification.
+    ///
+    /// This may be called at any time, e.g. upon changing the rate of a stream with
+    /// [`Stream::update_sample_rate`].
+    ///
+    /// [`Stream::update_sample_rate`]: ../../stream/struct.Stream.html#method.update_sample_rate
+    pub fn set_output_rate(&mut self, rate: u32) -> Result<(), PAErr> {
+        if !valid_rate(rate) {
+            return Err(PAErr::from(Code::Invalid));
+        }
+        self.set_ratio(rate as f64 / self.spec.rate as f64)
+    }
+
+    /// Gets the delay introduced by the resampler, in input frames.
+    ///
+    /// This is the amount of input held back awaiting further input before it can be resampled.
+    pub fn get_delay(&self) -> f64 {
+        (self.history.len() / self.channels) as f64 - self.position
+    }
+
+    /// Discards all history, returning the resampler to its initial state.
+    pub fn reset(&mut self) {
+        let reach = self.reach();
+        self.history.clear();
+        self.history.resize(reach * self.channels, 0.0);
+        self.position = reach as f64;
+    }
+
+    /// Resamples a buffer of interleaved frames, appending the result to `dst`.
+    ///
+    /// The input buffer must contain a whole number of frames. Returns the number of frames
+    /// appended.
+    pub fn process(&mut self, src: &[u8], dst: &mut Vec<u8>) -> Result<usize, PAErr> {
+        let frame_size = convert::sample_size(self.spec.format).unwrap() * self.channels;
+        if src.len() % frame_size != 0 {
+            return Err(PAErr::from(Code::Invalid));
+        }
+        let mut input = std::mem::replace(&mut self.input, Vec::new());
+        input.resize(src.len() / frame_size * self.channels, 0.0);
+        convert::to_f32(src, self.spec.format, &mut input)?;
+        let result = self.process_converted(&input, dst);
+        self.input = input;
+        result
+    }
+
+    /// Resamples a buffer of interleaved native floating point frames, appending the result to
+    /// `dst`, regardless of the sample format this resampler was created for.
+    ///
+    /// The input buffer must contain a whole number of frames. Returns the number of frames
+    /// appended.
+    pub fn process_f32(&mut self, src: &[f32], dst: &mut Vec<f32>) -> Result<usize, PAErr> {
+        if src.len() % self.channels != 0 {
+            return Err(PAErr::from(Code::Invalid));
+        }
+        self.history.extend_from_slice(src);
+        let before = dst.len();
+        self.run(dst);
+        Ok((dst.len() - before) / self.channels)
+    }
+
+    /// Produces the output remaining for all input given so far, appending it to `dst`, and resets
+    /// the resampler.
+    ///
+    /// Returns the number of frames appended.
+    pub fn flush(&mut self, dst: &mut Vec<u8>) -> Result<usize, PAErr> {
+        let silence = vec![0.0; self.flush_frames() * self.channels];
+        let result = self.process_converted(&silence, dst);
+        self.reset();
+        result
+    }
+
+    /// Produces the output remaining for all input given so far, appending it to `dst`, and resets
+    /// the resampler.
+    ///
+    /// Returns the number of frames appended.
+    pub fn flush_f32(&mut self, dst: &mut Vec<f32>) -> Result<usize, PAErr> {
+        let silence = vec![0.0; self.flush_frames() * self.channels];
+        let result = self.process_f32(&silence, dst);
+        self.reset();
+        result
+    }
+
+    fn process_converted(&mut self, src: &[f32], dst: &mut Vec<u8>) -> Result<usize, PAErr> {
+        let mut output = std::mem::replace(&mut self.output, Vec::new());
+        output.clear();
+        let frames = self.process_f32(src, &mut output)?;
+        let start = dst.len();
+        dst.resize(start + output.len() * convert::sample_size(self.spec.format).unwrap(), 0);
+        let result = convert::from_f32(&output, self.spec.format, &mut dst[start..]);
+        self.output = output;
+        result.and(Ok(frames))
+    }
+
+    /// Number of frames of silence needed to push out all pending output.
+    fn flush_frames(&self) -> usize {
+        // Output up to the last frame given requires `reach` frames beyond it
+        self.reach() + 1
+    }
+
+    /// Number of input frames needed on each side of an output frame position.
+    fn reach(&self) -> usize {
+        reach(self.quality, self.cutoff)
+    }
+
+    /// Number of input frames retained before the current position.
+    ///
+    /// This covers the reach at the lowest possible ratio, such that the ratio can be lowered at
+    /// any time without the wider filter running short of input.
+    fn retained(&self) -> usize {
+        reach(self.quality, RATIO_MIN * ROLLOFF)
+    }
+
+    /// Produces as much output as possible from the history, then discards unneeded history.
+    fn run(&mut self, dst: &mut Vec<f32>) {
+        let channels = self.channels;
+        let frames = self.history.len() / channels;
+        let reach = self.reach();
+        let step = 1.0 / self.ratio;
+        let mut frame = [0f32; CHANNELS_MAX];
+        let frame = &mut frame[..channels];
+
+        while (self.position.floor() as usize) + reach < frames {
+            match self.quality {
+                Quality::Linear => self.interpolate_linear(frame),
+                _ => self.interpolate_sinc(frame, reach),
+            }
+            dst.extend_from_slice(frame);
+            self.position += step;
+        }
+
+        let discard = (self.position.floor() as usize).saturating_sub(self.retained()).min(frames);
+        self.history.drain(..(discard * channels));
+        self.position -= discard as f64;
+    }
+
+    fn interpolate_linear(&self, out: &mut [f32]) {
+        let i = self.position.floor() as usize;
+        let f = (self.position - i as f64) as f32;
+        let a = &self.history[(i * self.channels)..((i + 1) * self.channels)];
+        let b = &self.history[((i + 1) * self.channels)..((i + 2) * self.channels)];
+        for (o, (a, b)) in out.iter_mut().zip(a.iter().zip(b)) {
+            *o = *a + (*b - *a) * f;
+        }
+    }
+
+    fn interpolate_sinc(&self, out: &mut [f32], reach: usize) {
+        for o in out.iter_mut() {
+            *o = 0.0;
+        }
+        let i = self.position.floor() as usize;
+        let first = (i + 1).saturating_sub(reach);
+        let scale = self.cutoff * TABLE_RESOLUTION as f64;
+        let limit = (self.table.len() - 1) as f64;
+        for j in first..=(i + reach) {
+            let t = ((self.position - j as f64).abs() * scale).min(limit);
+            let index = t as usize;
+            let frac = (t - index as f64) as f32;
+            let mut gain = self.table[index];
+            if let Some(next) = self.table.get(index + 1) {
+                gain += (*next - gain) * frac;
+            }
+            let gain = gain * self.cutoff as f32;
+            let frame = &self.history[(j * self.channels)..((j + 1) * self.channels)];
+            for (o, v) in out.iter_mut().zip(frame) {
+                *o += *v * gain;
+            }
+        }
+    }
+}
+
+/// Number of input frames needed on each side of an output frame position, for the given quality
+/// and cutoff frequency.
+fn reach(quality: Quality, cutoff: f64) -> usize {
+    match quality {
+        Quality::Linear => 1,
+        q => (q.zero_crossings() as f64 / cutoff).ceil() as usize,
+    }
+}
+
+fn valid_rate(rate: u32) -> bool {
+    rate > 0 && rate <= RATE_MAX
+}
+
+/// Builds the table of the Kaiser windowed sinc function, from zero to the given number of zero
+/// crossings (inclusive).
+fn build_table(zero_crossings: usize) -> Vec<f32> {
+    let len = zero_crossings * TABLE_RESOLUTION + 1;
+    let norm = bessel_i0(KAISER_BETA);
+    (0..len).map(|n| {
+        let x = n as f64 / TABLE_RESOLUTION as f64;
+        let sinc = match n {
+            0 => 1.0,
+            _ => (PI * x).sin() / (PI * x),
+        };
+        let r = x / zero_crossings as f64;
+        let window = bessel_i0(KAISER_BETA * (1.0 - r * r).max(0.0).sqrt()) / norm;
+        (sinc * window) as f32
+    }).collect()
+}
+
+/// Zeroth order modified Bessel function of the first kind.
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half = x / 2.0;
+    for k in 1..50 {
+        term *= half / k as f64;
+        sum += term * term;
+        if term * term < sum * 1e-17 {
+            break;
+        }
+    }
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::Format;
+    use crate::test_util::float_spec;
+
+    fn sine(freq: f64, rate: f64, frames: usize) -> Vec<f32> {
+        (0..frames).map(|n| (2.0 * PI * freq * n as f64 / rate).sin() as f32 * 0.5).collect()
+    }
+
+    /// Resamples in uneven chunks, then flushes.
+    fn run(resampler: &mut Resampler, input: &[f32]) -> Vec<f32> {
+        let channels = resampler.get_spec().channels as usize;
+        let mut output = Vec::new();
+        let mut rest = input;
+        let mut chunk = 7;
+        while !rest.is_empty() {
+            let n = (chunk * channels).min(rest.len());
+            resampler.process_f32(&rest[..n], &mut output).unwrap();
+            rest = &rest[n..];
+            chunk = chunk * 3 % 1000 + 1;
+        }
+        resampler.flush_f32(&mut output).unwrap();
+        output
+    }
+
+    fn max_error(output: &[f32], expected: &[f32], skip: usize) -> f32 {
+        output.iter().zip(expected).skip(skip).take(output.len().min(expected.len()) - 2 * skip)
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn identity() {
+        let input = sine(1000.0, 48000.0, 1000);
+        for quality in [Quality::Linear, Quality::SincFast, Quality::SincBest].iter() {
+            let mut resampler = Resampler::new(&float_spec(48000, 1), 48000, *quality).unwrap();
+            let output = run(&mut resampler, &input);
+            assert!(output.len() >= input.len());
+            assert!(max_error(&output, &input, 0) < 1e-6, "{:?}", quality);
+        }
+    }
+
+    #[test]
+    fn upsample_sine() {
+        let input = sine(1000.0, 44100.0, 4410);
+        let expected = sine(1000.0, 48000.0, 4800);
+        let tolerances = [(Quality::Linear, 5e-3), (Quality::SincFast, 1e-3),
+            (Quality::SincMedium, 5e-4), (Quality::SincBest, 5e-4)];
+        for (quality, tolerance) in tolerances.iter() {
+            let mut resampler = Resampler::new(&float_spec(44100, 1), 48000, *quality).unwrap();
+            let output = run(&mut resampler, &input);
+            assert!((output.len() as i64 - 4800).abs() <= 2 * resampler.reach() as i64,
+                "{:?}: {}", quality, output.len());
+            let error = max_error(&output, &expected, 100);
+            assert!(error < *tolerance, "{:?}: {}", quality, error);
+        }
+    }
+
+    #[test]
+    fn downsample_rejects_above_nyquist() {
+        // 20 kHz content cannot be represented at 22.05 kHz and must be filtered out
+        let input = sine(20000.0, 44100.0, 8820);
+        let mut resampler = Resampler::new(&float_spec(44100, 1), 22050, Quality::SincBest).unwrap();
+        let output = run(&mut resampler, &input);
+        let peak = output[200..4000].iter().map(|v| v.abs()).fold(0.0, f32::max);
+        assert!(peak < 0.01, "{}", peak);
+
+        // Whereas low frequency content passes
+        let input = sine(1000.0, 44100.0, 8820);
+        let expected = sine(1000.0, 22050.0, 4410);
+        let mut resampler = Resampler::new(&float_spec(44100, 1), 22050, Quality::SincBest).unwrap();
+        let output = run(&mut resampler, &input);
+        assert!(max_error(&output, &expected, 100) < 1e-3);
+    }
+
+    #[test]
+    fn interleaved_channels() {
+        let left = sine(500.0, 32000.0, 3200);
+        let input: Vec<f32> = left.iter().flat_map(|v| vec![*v, -*v]).collect();
+        let mut resampler = Resampler::new(&float_spec(32000, 2), 48000, Quality::SincFast).unwrap();
+        let output = run(&mut resampler, &input);
+        for frame in output.chunks(2) {
+            assert_eq!(frame[0], -frame[1]);
+        }
+    }
+
+    #[test]
+    fn variable_ratio() {
+        let input = vec![0.25f32; 10000];
+        let mut resampler = Resampler::new(&float_spec(48000, 1), 48000, Quality::Linear).unwrap();
+        let mut output = Vec::new();
+        resampler.process_f32(&input[..5000], &mut output).unwrap();
+        resampler.set_ratio(1.01).unwrap();
+        resampler.process_f32(&input[5000..], &mut output).unwrap();
+        assert!((output.len() as i64 - 10050).abs() <= 2, "{}", output.len());
+        assert!(output.iter().all(|v| (*v - 0.25).abs() < 1e-6));
+
+        assert!(resampler.set_ratio(0.0).is_err());
+        assert!(resampler.set_ratio(std::f64::NAN).is_err());
+        resampler.set_output_rate(24000).unwrap();
+        assert_eq!(resampler.get_ratio(), 0.5);
+    }
+
+    #[test]
+    fn lowered_ratio() {
+        // Lowering the ratio widens the filter, which must still find the input it needs
+        let input = vec![0.25f32; 20000];
+        for quality in [Quality::SincFast, Quality::SincBest].iter() {
+            let mut resampler = Resampler::new(&float_spec(48000, 1), 48000, *quality).unwrap();
+            let mut output = Vec::new();
+            resampler.process_f32(&input[..10000], &mut output).unwrap();
+            let switch = output.len();
+            resampler.set_ratio(0.25).unwrap();
+            resampler.process_f32(&input[10000..], &mut output).unwrap();
+            let error = output[1000..].iter().map(|v| (*v - 0.25).abs()).fold(0.0, f32::max);
+            assert!(error < 2e-3, "{:?}: {} (switch at {})", quality, error, switch);
+        }
+    }
+
+    #[test]
+    fn sample_formats() {
+        let spec = Spec { format: Format::S16le, rate: 8000, channels: 1 };
+        let input: Vec<u8> = [1000i16; 800].iter().flat_map(|v| v.to_le_bytes().to_vec()).collect();
+        let mut resampler = Resampler::new(&spec, 16000, Quality::SincMedium).unwrap();
+        let mut output = Vec::new();
+        resampler.process(&input, &mut output).unwrap();
+        resampler.flush(&mut output).unwrap();
+        let values: Vec<i16> = output.chunks(2).map(|c| i16::from_le_bytes([c[0], c[1]])).collect();
+        assert!(values.len() >= 1600);
+        assert!(values[100..1500].iter().all(|v| (*v - 1000).abs() <= 2));
+
+        assert!(resampler.process(&[0u8; 3], &mut output).is_err());
+        let bad = Spec { format: Format::Invalid, rate: 8000, channels: 1 };
+        assert!(Resampler::new(&bad, 16000, Quality::Linear).is_err());
+        assert!(Resampler::new(&spec, 0, Quality::Linear).is_err());
+    }
+}
@@ -0,0 +1,229 @@
+// Copyright 2020 Lyndon Brown
+//
+// This file is part of the PulseAudio Rust language binding.
+//
+// Licensed under the MIT license or the Apache license (version 2.0), at your option. You may not
+// copy, modify, or distribute this file except in compliance with said license. You can find copies
+// of these licenses either in the LICENSE-MIT and LICENSE-APACHE files, or alternatively at
+// <http://opensource.org/licenses/MIT> and <http://www.apache.org/licenses/LICENSE-2.0>
+// respectively.
+//
+// Portions of documentation are copied from the LGPL 2.1+ licensed PulseAudio C headers on a
+// fair-use basis, as discussed in the overall project readme (available in the git repository).
+
+//! Follow-the-default-device mode for streams.
+//!
+//! # Overview
+//!
+//! When the user changes the default sink or source, existing streams normally stay where they
+//! are, unless moved by server-side logic. A [`Follower`] watches for server changes and, whenever
+//! the default sink (for playback streams) or default source (for record streams) changes, moves
+//! the stream to the new default device.
+//!
+//! Streams connected with the [`flags::DONT_MOVE`] flag are not eligible, and neither are upload
+//! streams or streams not yet connected.
+//!
+//! Each move performed is reported through the stream’s usual moved callback, as set with
+//! [`Stream::set_moved_callback`].
+//!
+//! The mode stays active for as long as the [`Follower`] object is kept alive.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use pulse::context::subscribe::Dispatcher;
+//!
+//! let dispatcher = Dispatcher::new(&mut context);
+//! let follower = stream.follow_default_device(&context, &dispatcher).unwrap();
+//! ```
+//!
+//! [`Follower`]: struct.Follower.html
+//! [`flags::DONT_MOVE`]: ../flags/constant.DONT_MOVE.html
+//! [`Stream::set_moved_callback`]: ../struct.Stream.html#method.set_moved_callback
+
+use std::rc::Rc;
+use std::cell::RefCell;
+use capi::pa_stream as StreamInternal;
+use crate::context::Context;
+use crate::context::introspect::{Introspector, ServerInfo};
+use crate::context::subscribe::{subscription_masks, Dispatcher, Facility, Operation,
+    Subscription};
+use crate::error::{Code, PAErr};
+use super::{Stream, Direction, State, FlagSet, flags};
+
+/// Keeps a stream on the default device.
+///
+/// Dropping this object ends follow mode.
+pub struct Follower {
+    inner: Rc<Inner>,
+    /// Registration with the dispatcher, if subscribed.
+    subscription: Option<Subscription>,
+}
+
+struct Inner {
+    /// The stream, on which we hold a reference.
+    stream: *mut StreamInternal,
+    direction: Direction,
+    introspector: RefCell<Introspector>,
+    /// The last known default device name.
+    last_default: RefCell<Option<String>>,
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        unsafe { capi::pa_stream_unref(self.stream) };
+    }
+}
+
+impl Follower {
+    /// Creates a new follower for the given stream.
+    ///
+    /// This registers with the dispatcher for [`subscription_masks::SERVER`] events, until dropped.
+    /// If you need to manage subscriptions yourself, use [`new_unsubscribed`] instead.
+    ///
+    /// Returns an error with code `Code::BadState` if the stream is not connected for playback or
+    /// record, or `Code::NotSupported` if it was connected with [`flags::DONT_MOVE`].
+    ///
+    /// [`subscription_masks::SERVER`]: ../../context/subscribe/subscription_masks/constant.SERVER.html
+    /// [`new_unsubscribed`]: #method.new_unsubscribed
+    /// [`flags::DONT_MOVE`]: ../flags/constant.DONT_MOVE.html
+    pub fn new(stream: &Stream, context: &Context, dispatcher: &Dispatcher)
+        -> Result<Self, PAErr>
+    {
+        let mut follower = Self::new_unsubscribed(stream, context)?;
+
+        let weak = Rc::downgrade(&follower.inner);
+        follower.subscription = Some(dispatcher.register(subscription_masks::SERVER,
+            Box::new(move |facility, operation, _| {
+                if facility == Some(Facility::Server) && operation == Some(Operation::Changed) {
+                    if let Some(inner) = weak.upgrade() {
+                        Inner::check(&inner);
+                    }
+                }
+            }))?);
+
+        Ok(follower)
+    }
+
+    /// Creates a new follower for the given stream, without subscribing to server changes.
+    ///
+    /// You must call [`check`] yourself upon receiving a `Facility::Server` change event.
+    ///
+    /// [`check`]: #method.check
+    pub fn new_unsubscribed(stream: &Stream, context: &Context) -> Result<Self, PAErr> {
+        check_eligible(stream.direction, stream.flags)?;
+
+        unsafe { capi::pa_stream_ref(stream.ptr) };
+        let inner = Rc::new(Inner {
+            stream: stream.ptr,
+            direction: stream.direction,
+            introspector: RefCell::new(context.introspect()),
+            last_default: RefCell::new(None),
+        });
+        // Learn the current default, without moving
+        Inner::check(&inner);
+        Ok(Self { inner: inner, subscription: None })
+    }
+
+    /// Checks the current default device of the server, moving the stream if it has changed.
+    pub fn check(&self) {
+        Inner::check(&self.inner);
+    }
+}
+
+impl Inner {
+    fn check(inner: &Rc<Inner>) {
+        let weak = Rc::downgrade(inner);
+        let _ = inner.introspector.borrow().get_server_info(move |info| {
+            if let Some(inner) = weak.upgrade() {
+                inner.on_server_info(info);
+            }
+        });
+    }
+
+    fn on_server_info(&self, info: &ServerInfo) {
+        let default = match self.direction {
+            Direction::Playback => info.default_sink_name.as_ref(),
+            _ => info.default_source_name.as_ref(),
+        };
+        let default = match default {
+            Some(name) => name.to_string(),
+            None => return,
+        };
+
+        if !record_default(&self.last_default, &default) {
+            return;
+        }
+
+        let stream = Stream::from_raw_weak(self.stream);
+        if stream.get_state() != State::Ready {
+            return;
+        }
+        if stream.get_device_name().map_or(false, |d| d == default.as_str()) {
+            return;
+        }
+        let index = match stream.get_index() {
+            Some(index) => index,
+            None => return,
+        };
+        let mut introspector = self.introspector.borrow_mut();
+        let _ = match self.direction {
+            Direction::Playback => introspector.move_sink_input_by_name(index, &default, None),
+            _ => introspector.move_source_output_by_name(index, &default, None),
+        };
+    }
+}
+
+/// Checks whether a stream of the given direction and flags can follow the default device.
+fn check_eligible(direction: Direction, flags: FlagSet) -> Result<(), PAErr> {
+    match direction {
+        Direction::Playback | Direction::Record => {},
+        _ => return Err(PAErr::from(Code::BadState)),
+    }
+    match flags & flags::DONT_MOVE != 0 {
+        true => Err(PAErr::from(Code::NotSupported)),
+        false => Ok(()),
+    }
+}
+
+/// Records the current default device, returning whether it changed from the last known one.
+///
+/// The first default recorded is not a change.
+fn record_default(last: &RefCell<Option<String>>, default: &str) -> bool {
+    let previous = last.replace(Some(default.to_string()));
+    match previous {
+        None => false,
+        Some(previous) => previous != default,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eligibility() {
+        assert!(check_eligible(Direction::Playback, flags::NOFLAGS).is_ok());
+        assert!(check_eligible(Direction::Record, flags::ADJUST_LATENCY).is_ok());
+        assert_eq!(check_eligible(Direction::Upload, flags::NOFLAGS),
+            Err(PAErr::from(Code::BadState)));
+        assert_eq!(check_eligible(Direction::Invalid, flags::NOFLAGS),
+            Err(PAErr::from(Code::BadState)));
+        assert_eq!(check_eligible(Direction::Playback, flags::DONT_MOVE),
+            Err(PAErr::from(Code::NotSupported)));
+        assert_eq!(check_eligible(Direction::Record, flags::DONT_MOVE | flags::START_CORKED),
+            Err(PAErr::from(Code::NotSupported)));
+    }
+
+    #[test]
+    fn default_changes() {
+        let last = RefCell::new(None);
+        // Learning the initial default is not a change
+        assert_eq!(record_default(&last, "speakers"), false);
+        assert_eq!(record_default(&last, "speakers"), false);
+        assert_eq!(record_default(&last, "headphones"), true);
+        assert_eq!(*last.borrow(), Some("headphones".to_string()));
+        assert_eq!(record_default(&last, "headphones"), false);
+        assert_eq!(record_default(&last, "speakers"), true);
+    }
+}
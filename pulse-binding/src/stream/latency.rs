@@ -0,0 +1,595 @@
+// Copyright 2020 Lyndon Brown
+//
+// This file is part of the PulseAudio Rust language binding.
+//
+// Licensed under the MIT license or the Apache license (version 2.0), at your option. You may not
+// copy, modify, or distribute this file except in compliance with said license. You can find copies
+// of these licenses either in the LICENSE-MIT and LICENSE-APACHE files, or alternatively at
+// <http://opensource.org/licenses/MIT> and <http://www.apache.org/licenses/LICENSE-2.0>
+// respectively.
+//
+// Portions of documentation are copied from the LGPL 2.1+ licensed PulseAudio C headers on a
+// fair-use basis, as discussed in the overall project readme (available in the git repository).
+
+//! Round-trip latency measurement.
+//!
+//! # Overview
+//!
+//! A [`Meter`] measures the real latency of a sink, by playing a probe signal (an impulse, or a
+//! maximum length sequence) through a playback stream, capturing it on a record stream from a
+//! source that hears the sink (via a loopback cable, or acoustically with a microphone), and
+//! locating it in the captured audio by cross-correlation.
+//!
+//! The latencies reported by the server for the two streams, at the moment the probe is written,
+//! are used to work out when the probe was expected to be heard. The resulting [`Measurement`]
+//! gives the part of the round-trip latency not accounted for by the server. This is typically
+//! latency within the hardware (or the acoustic path) that the server does not know about.
+//!
+//! The unaccounted latency can be written back as the latency offset of the sink’s active port
+//! with [`Measurement::calibrate_sink_port`], such that the server reports the true latency to
+//! applications, for correct audio/video synchronisation. Since capture latency unknown to the
+//! server cannot be told apart from that of the sink, this is only accurate if the latency of the
+//! source is itself accounted for.
+//!
+//! A maximum length sequence is far more robust against noise than an impulse, and is quiet enough
+//! to be used with speakers and a microphone.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use pulse::stream::latency::{Meter, Options};
+//!
+//! let mut meter = Meter::new(&mut context, 48000, Options::default()).unwrap();
+//! meter.start(None, None, Box::new(|result| {
+//!     match result {
+//!         Ok(m) => println!("unaccounted latency: {} µs", m.offset),
+//!         Err(e) => eprintln!("measurement failed: {}", e),
+//!     }
+//! })).unwrap();
+//! ```
+//!
+//! [`Meter`]: struct.Meter.html
+//! [`Measurement`]: struct.Measurement.html
+//! [`Measurement::calibrate_sink_port`]: struct.Measurement.html#method.calibrate_sink_port
+
+use std::rc::Rc;
+use std::cell::RefCell;
+use capi::pa_stream as StreamInternal;
+use crate::channelmap::Map;
+use crate::context::Context;
+use crate::callbacks::ListResult;
+use crate::error::{Code, PAErr};
+use crate::sample::{self, Spec};
+use crate::sample::convert;
+use crate::time::{MicroSeconds, MICROS_PER_MILLI, MICROS_PER_SEC};
+use super::{Stream, Latency, PeekResult, SeekMode, State, flags};
+
+/// The probe signal played.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Probe {
+    /// A single full scale sample.
+    Impulse,
+    /// A maximum length sequence (MLS) of length 2<sup>order</sup>-1 samples. The order must be
+    /// between 8 and 16.
+    Mls {
+        /// Order of the sequence.
+        order: u8,
+    },
+}
+
+/// Measurement options.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Options {
+    /// Probe signal.
+    pub probe: Probe,
+    /// Amplitude of the probe, as a linear factor of full scale (except for impulses, which are
+    /// always full scale).
+    pub amplitude: f32,
+    /// Time to let the streams settle, playing silence, before playing the probe.
+    pub warmup: MicroSeconds,
+    /// Maximum unaccounted latency to look for.
+    pub max_latency: MicroSeconds,
+    /// Minimum correlation (from `0.0` to `1.0`) between the probe and the captured signal for a
+    /// successful measurement.
+    pub min_confidence: f32,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            probe: Probe::Mls { order: 14 },
+            amplitude: 0.25,
+            warmup: MicroSeconds(500 * MICROS_PER_MILLI),
+            max_latency: MicroSeconds(MICROS_PER_SEC),
+            min_confidence: 0.2,
+        }
+    }
+}
+
+/// The result of a latency measurement.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Measurement {
+    /// Time between the probe being written to the playback stream and it arriving at the source.
+    pub round_trip: MicroSeconds,
+    /// Playback latency reported by the server when the probe was written, i.e. how long the probe
+    /// was expected to take to be played. This includes any port latency offset already set.
+    pub reported: MicroSeconds,
+    /// Latency not accounted for by the server, in microseconds (i.e. `round_trip` less
+    /// `reported`). This may be negative if the server over-estimates latency.
+    ///
+    /// The record latency reported by the server is already excluded from `round_trip`, but any
+    /// capture latency the server does not know about (e.g. within a USB microphone) is included
+    /// here, being indistinguishable from unaccounted playback latency.
+    pub offset: i64,
+    /// Correlation between the probe and the captured signal, from `0.0` to `1.0`.
+    pub confidence: f32,
+}
+
+impl Measurement {
+    /// Adds the unaccounted latency to the latency offset of the active port of the given sink
+    /// (which may be `@DEFAULT_SINK@`).
+    ///
+    /// All of [`offset`](#structfield.offset) is attributed to the sink, including any unaccounted
+    /// capture latency. For an accurate result, the latency offset of the source’s port should be
+    /// set correctly first, or a source with negligible unaccounted latency used (such as a
+    /// loopback cable into a line input).
+    ///
+    /// The callback is given `true` on success, or `false` if the sink has no card or active port,
+    /// or the offset could not be set.
+    pub fn calibrate_sink_port(&self, context: &Context, sink: &str,
+        callback: Box<dyn FnMut(bool) + 'static>)
+    {
+        let introspector = Rc::new(RefCell::new(context.introspect()));
+        let callback = Rc::new(RefCell::new(callback));
+        let offset = self.offset;
+        let introspector_ref = Rc::clone(&introspector);
+        introspector.borrow().get_sink_info_by_name(sink, move |result| {
+            let info = match result {
+                ListResult::Item(info) => info,
+                ListResult::End => return,
+                ListResult::Error => return (callback.borrow_mut())(false),
+            };
+            let (card, port) = match (info.card, info.active_port.as_ref()) {
+                (Some(card), Some(port)) => match port.name.as_ref() {
+                    Some(name) => (card, name.to_string()),
+                    None => return (callback.borrow_mut())(false),
+                },
+                _ => return (callback.borrow_mut())(false),
+            };
+            let callback = Rc::clone(&callback);
+            let introspector = Rc::clone(&introspector_ref);
+            introspector_ref.borrow().get_card_info_by_index(card, move |result| {
+                let info = match result {
+                    ListResult::Item(info) => info,
+                    ListResult::End => return,
+                    ListResult::Error => return (callback.borrow_mut())(false),
+                };
+                let current = info.ports.iter()
+                    .find(|p| p.name.as_ref().map(|n| *n == port).unwrap_or(false))
+                    .map(|p| p.latency_offset);
+                match (info.name.as_ref(), current) {
+                    (Some(name), Some(current)) => {
+                        let callback = Rc::clone(&callback);
+                        introspector.borrow_mut().set_port_latency_offset(name, &port,
+                            current + offset, Some(Box::new(move |success| {
+                                (callback.borrow_mut())(success)
+                            })));
+                    },
+                    _ => (callback.borrow_mut())(false),
+                }
+            });
+        });
+    }
+}
+
+/// Measures round-trip latency.
+///
+/// Measurement stops once the result has been delivered, or when this object is dropped. The
+/// streams are disconnected when this object is dropped.
+pub struct Meter {
+    inner: Rc<RefCell<Inner>>,
+    playback: Stream,
+    record: Stream,
+}
+
+struct Inner {
+    spec: Spec,
+    options: Options,
+    probe: Vec<f32>,
+    phase: Phase,
+    /// Frames of the probe written so far.
+    probe_written: usize,
+    /// Captured audio.
+    captured: Vec<f32>,
+    callback: Option<Box<dyn FnMut(Result<Measurement, PAErr>) + 'static>>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Phase {
+    Idle,
+    /// Playing silence, before playing the probe.
+    Warmup,
+    /// The probe has been written.
+    Sent {
+        /// Frames captured at the time.
+        marker: usize,
+        /// Playback latency at the time, in microseconds.
+        playback: i64,
+        /// Record latency at the time, in microseconds.
+        record: i64,
+    },
+    Done,
+}
+
+impl Meter {
+    /// Creates the playback and record streams (mono, in floating point format) for measuring
+    /// latency at the given sample rate.
+    ///
+    /// Returns an error if the options are invalid, or the streams could not be created.
+    pub fn new(context: &mut Context, rate: u32, options: Options) -> Result<Self, PAErr> {
+        let spec = Spec { format: sample::SAMPLE_FLOAT32, channels: 1, rate: rate };
+        let valid_order = match options.probe {
+            Probe::Mls { order } => order >= 8 && order <= 16,
+            Probe::Impulse => true,
+        };
+        if !spec.is_valid() || !valid_order {
+            return Err(PAErr::from(Code::Invalid));
+        }
+        let mut map = Map::default();
+        map.init_mono();
+
+        let playback = Stream::new(context, "Latency measurement", &spec, Some(&map))
+            .ok_or_else(|| context.errno())?;
+        let record = Stream::new(context, "Latency measurement", &spec, Some(&map))
+            .ok_or_else(|| context.errno())?;
+
+        let probe = match options.probe {
+            Probe::Impulse => vec![1.0],
+            Probe::Mls { order } => mls(order).iter().map(|v| v * options.amplitude).collect(),
+        };
+        let inner = Rc::new(RefCell::new(Inner {
+            spec: spec,
+            options: options,
+            probe: probe,
+            phase: Phase::Idle,
+            probe_written: 0,
+            captured: Vec::new(),
+            callback: None,
+        }));
+        Ok(Self { inner: inner, playback: playback, record: record })
+    }
+
+    /// Starts measuring, playing to the given sink and recording from the given source (or the
+    /// defaults).
+    ///
+    /// The callback is called once, with the measurement, or an error. The error code is
+    /// `Code::NoData` if the probe could not be found in the captured audio with sufficient
+    /// confidence, or `Code::BadState` if either stream failed.
+    ///
+    /// If the streams cannot be connected, the error is returned, and the meter is left idle, such
+    /// that starting can be retried.
+    pub fn start(&mut self, sink: Option<&str>, source: Option<&str>,
+        callback: Box<dyn FnMut(Result<Measurement, PAErr>) + 'static>) -> Result<(), PAErr>
+    {
+        {
+            let mut inner = self.inner.borrow_mut();
+            if inner.phase != Phase::Idle {
+                return Err(PAErr::from(Code::BadState));
+            }
+            inner.phase = Phase::Warmup;
+            inner.callback = Some(callback);
+        }
+
+        let playback_ptr = self.playback.ptr;
+        let record_ptr = self.record.ptr;
+
+        let inner = Rc::clone(&self.inner);
+        self.playback.set_write_callback(Some(Box::new(move |nbytes| {
+            let mut playback = Stream::from_raw_weak(playback_ptr);
+            let record = Stream::from_raw_weak(record_ptr);
+            inner.borrow_mut().write(&mut playback, &record, nbytes);
+        })));
+
+        let inner = Rc::clone(&self.inner);
+        self.record.set_read_callback(Some(Box::new(move |_| {
+            let mut record = Stream::from_raw_weak(record_ptr);
+            Inner::read(&inner, &mut record);
+        })));
+
+        let callback = Self::state_callback(&self.inner, playback_ptr);
+        self.playback.set_state_callback(Some(callback));
+        let callback = Self::state_callback(&self.inner, record_ptr);
+        self.record.set_state_callback(Some(callback));
+
+        let result = self.connect(sink, source);
+        if result.is_err() {
+            self.playback.set_write_callback(None);
+            self.record.set_read_callback(None);
+            self.playback.set_state_callback(None);
+            self.record.set_state_callback(None);
+            let mut inner = self.inner.borrow_mut();
+            inner.phase = Phase::Idle;
+            inner.callback = None;
+        }
+        result
+    }
+
+    /// Connects the streams, other than any already connected by an earlier attempt.
+    fn connect(&mut self, sink: Option<&str>, source: Option<&str>) -> Result<(), PAErr> {
+        let flags = flags::AUTO_TIMING_UPDATE | flags::INTERPOLATE_TIMING;
+        if self.record.get_state() == State::Unconnected {
+            self.record.connect_record(source, None, flags)?;
+        }
+        self.playback.connect_playback(sink, None, flags, None, None)
+    }
+
+    /// Creates a state callback for a stream, ending measurement if it fails.
+    fn state_callback(inner: &Rc<RefCell<Inner>>, ptr: *mut StreamInternal)
+        -> Box<dyn FnMut() + 'static>
+    {
+        let inner = Rc::clone(inner);
+        Box::new(move || {
+            match Stream::from_raw_weak(ptr).get_state() {
+                State::Failed | State::Terminated => {
+                    Inner::finish(&inner, Err(PAErr::from(Code::BadState)));
+                },
+                _ => {},
+            }
+        })
+    }
+}
+
+impl Inner {
+    fn write(&mut self, playback: &mut Stream, record: &Stream, nbytes: usize) {
+        let frame_size = self.spec.frame_size();
+        let buffer = match playback.begin_write(Some(nbytes)) {
+            Ok(Some(buffer)) => buffer,
+            _ => return,
+        };
+        let len = std::cmp::min(buffer.len(), nbytes) / frame_size * frame_size;
+        let data = &mut buffer[..len];
+        let mut samples = vec![0.0f32; len / frame_size];
+
+        if self.phase == Phase::Warmup && self.frames(self.options.warmup) <= self.captured.len() {
+            // Note the latencies at the moment the probe is written
+            if let (Ok(p), Ok(r)) = (playback.get_latency(), record.get_latency()) {
+                self.phase = Phase::Sent {
+                    marker: self.captured.len(),
+                    playback: latency_micros(p),
+                    record: latency_micros(r),
+                };
+            }
+        }
+        if let Phase::Sent { .. } = self.phase {
+            let remaining = &self.probe[self.probe_written..];
+            let n = std::cmp::min(remaining.len(), samples.len());
+            samples[..n].copy_from_slice(&remaining[..n]);
+            self.probe_written += n;
+        }
+
+        match convert::from_f32(&samples, self.spec.format, data) {
+            Ok(_) => { let _ = playback.write(data, None, 0, SeekMode::Relative); },
+            Err(_) => { let _ = playback.cancel_write(); },
+        }
+    }
+
+    fn read(inner: &Rc<RefCell<Self>>, record: &mut Stream) {
+        loop {
+            let result = {
+                let mut this = inner.borrow_mut();
+                let format = this.spec.format;
+                match record.peek() {
+                    // Once done, audio is just discarded
+                    Ok(PeekResult::Data(_)) | Ok(PeekResult::Hole(_))
+                        if this.phase == Phase::Done => {},
+                    Ok(PeekResult::Data(data)) => {
+                        let start = this.captured.len();
+                        this.captured.resize(start + data.len() / 4, 0.0);
+                        let _ = convert::to_f32(data, format, &mut this.captured[start..]);
+                    },
+                    Ok(PeekResult::Hole(size)) => {
+                        let len = this.captured.len() + size / 4;
+                        this.captured.resize(len, 0.0);
+                    },
+                    Ok(PeekResult::Empty) | Err(_) => break,
+                }
+                let _ = record.discard();
+                this.analyse()
+            };
+            if let Some(result) = result {
+                Self::finish(inner, result);
+                break;
+            }
+        }
+    }
+
+    /// Analyses the captured audio, if enough has been captured since the probe was sent.
+    fn analyse(&mut self) -> Option<Result<Measurement, PAErr>> {
+        let (marker, playback, record) = match self.phase {
+            Phase::Sent { marker, playback, record } => (marker, playback, record),
+            _ => return None,
+        };
+        let record_frames = self.frames(MicroSeconds(record.max(0) as u64));
+        let window = record_frames + self.frames(self.options.max_latency) +
+            self.frames(MicroSeconds(playback.max(0) as u64)) + self.probe.len();
+        if self.captured.len() < marker + window {
+            return None;
+        }
+        self.phase = Phase::Done;
+
+        let (lag, confidence) = match correlate(&self.probe, &self.captured[marker..]) {
+            Some((lag, confidence)) if confidence >= self.options.min_confidence => {
+                (lag, confidence)
+            },
+            _ => return Some(Err(PAErr::from(Code::NoData))),
+        };
+        let round_trip = lag as i64 * MICROS_PER_SEC as i64 / self.spec.rate as i64 - record;
+        Some(Ok(Measurement {
+            round_trip: MicroSeconds(round_trip.max(0) as u64),
+            reported: MicroSeconds(playback.max(0) as u64),
+            offset: round_trip - playback,
+            confidence: confidence,
+        }))
+    }
+
+    fn finish(inner: &Rc<RefCell<Self>>, result: Result<Measurement, PAErr>) {
+        let callback = {
+            let mut this = inner.borrow_mut();
+            this.phase = Phase::Done;
+            this.captured = Vec::new();
+            this.callback.take()
+        };
+        if let Some(mut callback) = callback {
+            callback(result);
+        }
+    }
+
+    /// Converts a duration to a number of frames.
+    fn frames(&self, duration: MicroSeconds) -> usize {
+        (duration.0 * self.spec.rate as u64 / MICROS_PER_SEC) as usize
+    }
+}
+
+fn latency_micros(latency: Latency) -> i64 {
+    match latency {
+        Latency::None => 0,
+        Latency::Positive(t) => t.0 as i64,
+        Latency::Negative(t) => -(t.0 as i64),
+    }
+}
+
+/// Generates a maximum length sequence of the given order (8 to 16), as values of ±1.
+fn mls(order: u8) -> Vec<f32> {
+    let taps: &[u8] = match order {
+        8 => &[8, 6, 5, 4],
+        9 => &[9, 5],
+        10 => &[10, 7],
+        11 => &[11, 9],
+        12 => &[12, 11, 10, 4],
+        13 => &[13, 12, 11, 8],
+        14 => &[14, 13, 12, 2],
+        15 => &[15, 14],
+        16 => &[16, 15, 13, 4],
+        _ => unreachable!(),
+    };
+    let mut state: u32 = (1 << order) - 1;
+    (0..((1u32 << order) - 1)).map(|_| {
+        let out = state & 1;
+        let bit = taps.iter().fold(0, |b, t| b ^ (state >> (order - t)) & 1);
+        state = (state >> 1) | (bit << (order - 1));
+        match out { 1 => 1.0, _ => -1.0 }
+    }).collect()
+}
+
+/// Finds the position of `probe` within `signal` by cross-correlation.
+///
+/// Returns the offset of the best match, and the normalised correlation there (from `0.0` to
+/// `1.0`, ignoring polarity), or `None` if the signal is shorter than the probe or silent.
+fn correlate(probe: &[f32], signal: &[f32]) -> Option<(usize, f32)> {
+    if probe.is_empty() || signal.len() < probe.len() {
+        return None;
+    }
+    let n = (signal.len() + probe.len()).next_power_of_two();
+    let mut a: Vec<(f64, f64)> = signal.iter().map(|v| (*v as f64, 0.0)).collect();
+    a.resize(n, (0.0, 0.0));
+    let mut b: Vec<(f64, f64)> = probe.iter().map(|v| (*v as f64, 0.0)).collect();
+    b.resize(n, (0.0, 0.0));
+    fft(&mut a, false);
+    fft(&mut b, false);
+    // Multiply by the conjugate of the probe’s spectrum
+    for (x, y) in a.iter_mut().zip(b.iter()) {
+        *x = (x.0 * y.0 + x.1 * y.1, x.1 * y.0 - x.0 * y.1);
+    }
+    fft(&mut a, true);
+
+    let lags = signal.len() - probe.len() + 1;
+    let (lag, peak) = a[..lags].iter().enumerate()
+        .map(|(i, v)| (i, v.0.abs()))
+        .fold((0, 0.0), |best, cur| match cur.1 > best.1 { true => cur, false => best });
+
+    let energy = |s: &[f32]| s.iter().map(|v| *v as f64 * *v as f64).sum::<f64>();
+    let norm = (energy(probe) * energy(&signal[lag..(lag + probe.len())])).sqrt();
+    match norm > 0.0 {
+        true => Some((lag, (peak / norm).min(1.0) as f32)),
+        false => None,
+    }
+}
+
+/// Performs an in-place radix-2 fast Fourier transform (or its inverse, scaled). The length must be
+/// a power of two.
+fn fft(data: &mut [(f64, f64)], inverse: bool) {
+    let n = data.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            data.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= n {
+        let sign = match inverse { true => 1.0, false => -1.0 };
+        let angle = sign * 2.0 * std::f64::consts::PI / len as f64;
+        let w = (angle.cos(), angle.sin());
+        for start in (0..n).step_by(len) {
+            let mut wk = (1.0, 0.0);
+            for k in 0..(len / 2) {
+                let u = data[start + k];
+                let v = data[start + k + len / 2];
+                let v = (v.0 * wk.0 - v.1 * wk.1, v.0 * wk.1 + v.1 * wk.0);
+                data[start + k] = (u.0 + v.0, u.1 + v.1);
+                data[start + k + len / 2] = (u.0 - v.0, u.1 - v.1);
+                wk = (wk.0 * w.0 - wk.1 * w.1, wk.0 * w.1 + wk.1 * w.0);
+            }
+        }
+        len <<= 1;
+    }
+    if inverse {
+        for v in data.iter_mut() {
+            *v = (v.0 / n as f64, v.1 / n as f64);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sequences() {
+        for order in 8..=16 {
+            let s = mls(order);
+            assert_eq!(s.len(), (1 << order) - 1);
+            // Circular autocorrelation of an MLS is -1 at every non-zero lag
+            for lag in 1..4 {
+                let sum: f32 = (0..s.len()).map(|i| s[i] * s[(i + lag) % s.len()]).sum();
+                assert_eq!(sum, -1.0);
+            }
+        }
+    }
+
+    #[test]
+    fn correlation() {
+        let probe: Vec<f32> = mls(10).iter().map(|v| v * 0.25).collect();
+        let mut signal = vec![0.0f32; 5000];
+        for (i, v) in probe.iter().enumerate() {
+            signal[1234 + i] = -0.5 * v;
+        }
+        // Add some deterministic noise
+        for (i, v) in signal.iter_mut().enumerate() {
+            *v += ((i * 7919 % 101) as f32 / 101.0 - 0.5) * 0.2;
+        }
+        let (lag, confidence) = correlate(&probe, &signal).unwrap();
+        assert_eq!(lag, 1234);
+        assert!(confidence > 0.5);
+
+        assert!(correlate(&probe, &vec![0.0; 2000]).is_none());
+        assert!(correlate(&probe, &signal[..100]).is_none());
+    }
+}
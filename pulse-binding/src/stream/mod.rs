@@ -250,6 +250,11 @@
 //! [`Stream::writable_size`]: struct.Stream.html#method.writable_size
 //! [`Stream::write`]: struct.Stream.html#method.write
 
+pub mod follow;
+pub mod latency;
+pub mod signal;
+pub mod stats;
+
 use std::os::raw::{c_char, c_void};
 use std::ffi::{CStr, CString};
 use std::ptr::{null, null_mut};
@@ -257,10 +262,16 @@ use std::borrow::Cow;
 use capi::pa_stream as StreamInternal;
 use crate::{channelmap, format, def, proplist, sample};
 use crate::callbacks::{self, box_closure_get_capi_ptr, get_su_capi_params, get_su_callback};
-use crate::error::{self, PAErr};
+use crate::callbacks::{callback_for_list_instance, ListInstanceCallback};
+use crate::context::introspect::Introspector;
+use crate::context::subscribe::Dispatcher;
+use crate::error::{self, Code, PAErr};
 use crate::format::InfoInternal;
+use crate::mainloop::api::Mainloop;
 use crate::proplist::{Proplist, ProplistInternal};
-use crate::{context::Context, volume::ChannelVolumes, operation::Operation, time::MicroSeconds};
+use crate::volume::{ChannelVolumes, Volume};
+use crate::volume::ramp::{self, VolumeRamp};
+use crate::{context::Context, operation::Operation, time::MicroSeconds};
 
 pub use capi::pa_seek_mode_t as SeekMode;
 pub use capi::pa_stream_direction_t as Direction;
@@ -271,6 +282,12 @@ pub use capi::pa_stream_direction_t as Direction;
 pub struct Stream {
     /// The actual C object.
     ptr: *mut StreamInternal,
+    /// Used to avoid freeing the internal object when used as a weak wrapper in callbacks.
+    weak: bool,
+    /// Direction, as established upon connecting.
+    direction: Direction,
+    /// Flags used upon connecting.
+    flags: FlagSet,
     /// Multi-use callback closure pointers.
     cb_ptrs: CallbackPointers,
 }
@@ -650,7 +667,30 @@ impl Stream {
     #[inline]
     fn from_raw(ptr: *mut StreamInternal) -> Self {
         assert_eq!(false, ptr.is_null());
-        Self { ptr: ptr, cb_ptrs: Default::default() }
+        Self {
+            ptr: ptr,
+            weak: false,
+            direction: Direction::Invalid,
+            flags: flags::NOFLAGS,
+            cb_ptrs: Default::default(),
+        }
+    }
+
+    /// Creates a new `Stream` from an existing [`StreamInternal`](enum.StreamInternal.html)
+    /// pointer, without taking a reference to it.
+    ///
+    /// This is intended for use by helpers that need to query a stream from within its own
+    /// callbacks. Callbacks must not be set on such an object.
+    #[inline]
+    pub(crate) fn from_raw_weak(ptr: *mut StreamInternal) -> Self {
+        assert_eq!(false, ptr.is_null());
+        Self {
+            ptr: ptr,
+            weak: true,
+            direction: Direction::Invalid,
+            flags: flags::NOFLAGS,
+            cb_ptrs: Default::default(),
+        }
     }
 
     /// Gets the current state of the stream.
@@ -726,6 +766,21 @@ impl Stream {
         }
     }
 
+    /// Enables follow-the-default-device mode for this stream.
+    ///
+    /// Whenever the default sink (or source, for record streams) of the server changes, the stream
+    /// will be moved to it, for as long as the returned object is kept alive. Moves are reported
+    /// through the callback set with [`set_moved_callback`](#method.set_moved_callback).
+    ///
+    /// This is a convenience wrapper around [`follow::Follower::new`]; see that for details.
+    ///
+    /// [`follow::Follower::new`]: follow/struct.Follower.html#method.new
+    pub fn follow_default_device(&self, context: &Context, dispatcher: &Dispatcher)
+        -> Result<follow::Follower, PAErr>
+    {
+        follow::Follower::new(self, context, dispatcher)
+    }
+
     /// Checks whether or not this stream has been corked.
     pub fn is_corked(&self) -> Result<bool, PAErr> {
         match unsafe { capi::pa_stream_is_corked(self.ptr) } {
@@ -793,7 +848,11 @@ impl Stream {
             capi::pa_stream_connect_playback(self.ptr, p_dev, p_attr, flags, p_vol, p_sync)
         };
         match r {
-            0 => Ok(()),
+            0 => {
+                self.direction = Direction::Playback;
+                self.flags = flags;
+                Ok(())
+            },
             e => Err(PAErr(e)),
         }
     }
@@ -819,7 +878,11 @@ impl Stream {
         let p_dev = dev.map_or(null::<c_char>(), |_| c_dev.as_ptr() as *const c_char);
 
         match unsafe { capi::pa_stream_connect_record(self.ptr, p_dev, p_attr, flags) } {
-            0 => Ok(()),
+            0 => {
+                self.direction = Direction::Record;
+                self.flags = flags;
+                Ok(())
+            },
             e => Err(PAErr(e)),
         }
     }
@@ -829,7 +892,11 @@ impl Stream {
     /// (See [`scache`](../context/scache/index.html)).
     pub fn connect_upload(&mut self, length: usize) -> Result<(), PAErr> {
         match unsafe { capi::pa_stream_connect_upload(self.ptr, length) } {
-            0 => Ok(()),
+            0 => {
+                self.direction = Direction::Upload;
+                self.flags = flags::NOFLAGS;
+                Ok(())
+            },
             e => Err(PAErr(e)),
         }
     }
@@ -1679,14 +1746,153 @@ impl Stream {
             r => Some(r),
         }
     }
+
+    /// Sets the volume of this stream, on all channels, on the server.
+    ///
+    /// This resolves whether the stream is a sink input (playback) or source output (record) and
+    /// its index, and sizes the volume to match the stream’s channel map, as needed for use of
+    /// [`context::introspect::Introspector::set_sink_input_volume`] or
+    /// [`context::introspect::Introspector::set_source_output_volume`].
+    ///
+    /// Returns an error if the stream is not connected for playback or record.
+    ///
+    /// The callback must accept a `bool`, which indicates success.
+    ///
+    /// [`context::introspect::Introspector::set_sink_input_volume`]:
+    /// ../context/introspect/struct.Introspector.html#method.set_sink_input_volume
+    /// [`context::introspect::Introspector::set_source_output_volume`]:
+    /// ../context/introspect/struct.Introspector.html#method.set_source_output_volume
+    pub fn set_volume(&mut self, volume: Volume,
+        callback: Option<Box<dyn FnMut(bool) + 'static>>)
+        -> Result<Operation<dyn FnMut(bool)>, PAErr>
+    {
+        let mut cv = ChannelVolumes::default();
+        cv.set(self.get_volume_channels(), volume);
+        self.set_channel_volumes(&cv, callback)
+    }
+
+    /// Sets the volume of this stream, per channel, on the server.
+    ///
+    /// As with [`set_volume`](#method.set_volume), but with individual channel volumes given.
+    ///
+    /// The callback must accept a `bool`, which indicates success.
+    pub fn set_channel_volumes(&mut self, volume: &ChannelVolumes,
+        callback: Option<Box<dyn FnMut(bool) + 'static>>)
+        -> Result<Operation<dyn FnMut(bool)>, PAErr>
+    {
+        let (direction, index) = self.get_volume_target()?;
+        let mut introspector = self.introspect();
+        Ok(match direction {
+            Direction::Playback => introspector.set_sink_input_volume(index, volume, callback),
+            _ => introspector.set_source_output_volume(index, volume, callback),
+        })
+    }
+
+    /// Gets the volume of this stream from the server.
+    ///
+    /// Returns an error if the stream is not connected for playback or record.
+    ///
+    /// The callback is called once, and is given the volume, or `None` if it could not be
+    /// obtained.
+    pub fn get_volume<F>(&self, callback: F)
+        -> Result<Operation<dyn FnMut(Option<ChannelVolumes>)>, PAErr>
+        where F: FnMut(Option<ChannelVolumes>) + 'static
+    {
+        let (direction, index) = self.get_volume_target()?;
+        let context = unsafe { capi::pa_stream_get_context(self.ptr) };
+        let cb_data =
+            box_closure_get_capi_ptr::<dyn FnMut(Option<ChannelVolumes>)>(Box::new(callback));
+        let ptr = match direction {
+            Direction::Playback => unsafe { capi::pa_context_get_sink_input_info(context, index,
+                Some(get_sink_input_volume_cb_proxy), cb_data) },
+            _ => unsafe { capi::pa_context_get_source_output_info(context, index,
+                Some(get_source_output_volume_cb_proxy), cb_data) },
+        };
+        assert!(!ptr.is_null());
+        Ok(Operation::from_raw(ptr, cb_data as *mut Box<dyn FnMut(Option<ChannelVolumes>)>))
+    }
+
+    /// Sets the mute switch of this stream on the server.
+    ///
+    /// Returns an error if the stream is not connected for playback or record.
+    ///
+    /// The callback must accept a `bool`, which indicates success.
+    pub fn set_mute(&mut self, mute: bool, callback: Option<Box<dyn FnMut(bool) + 'static>>)
+        -> Result<Operation<dyn FnMut(bool)>, PAErr>
+    {
+        let (direction, index) = self.get_volume_target()?;
+        let mut introspector = self.introspect();
+        Ok(match direction {
+            Direction::Playback => introspector.set_sink_input_mute(index, mute, callback),
+            _ => introspector.set_source_output_mute(index, mute, callback),
+        })
+    }
+
+    /// Gradually changes the volume of this stream on the server, from `from` to `to`, over the
+    /// given duration, on all channels.
+    ///
+    /// The volume is updated periodically from a timer event created on the given mainloop. The
+    /// ramp runs for as long as the returned object is kept alive; see the [`volume::ramp`] module
+    /// for details.
+    ///
+    /// Returns an error if the stream is not connected for playback or record.
+    ///
+    /// [`volume::ramp`]: ../volume/ramp/index.html
+    pub fn ramp_volume<M>(&mut self, mainloop: &mut M, from: Volume, to: Volume,
+        duration: MicroSeconds, curve: ramp::Curve) -> Result<VolumeRamp<M::MI>, PAErr>
+        where M: Mainloop + ?Sized,
+              M::MI: 'static
+    {
+        let (direction, index) = self.get_volume_target()?;
+        let channels = self.get_volume_channels();
+        let target = match direction {
+            Direction::Playback => ramp::Target::SinkInput(index),
+            _ => ramp::Target::SourceOutput(index),
+        };
+        let mut from_cv = ChannelVolumes::default();
+        from_cv.set(channels, from);
+        let mut to_cv = ChannelVolumes::default();
+        to_cv.set(channels, to);
+        VolumeRamp::new(mainloop, self.introspect(), target, &from_cv, &to_cv, duration, curve)
+            .ok_or(PAErr::from(Code::Internal))
+    }
+
+    /// Gets an introspection object for the context this stream belongs to.
+    fn introspect(&self) -> Introspector {
+        let context = unsafe { capi::pa_stream_get_context(self.ptr) };
+        unsafe { capi::pa_context_ref(context) };
+        Introspector::from_raw(context)
+    }
+
+    /// Gets the direction and index of the stream, for volume control purposes.
+    fn get_volume_target(&self) -> Result<(Direction, u32), PAErr> {
+        match self.direction {
+            Direction::Playback | Direction::Record => {},
+            _ => return Err(PAErr::from(Code::BadState)),
+        }
+        match self.get_index() {
+            Some(index) => Ok((self.direction, index)),
+            None => Err(PAErr::from(Code::BadState)),
+        }
+    }
+
+    /// Gets the number of channels that volumes for this stream should cover.
+    fn get_volume_channels(&mut self) -> u32 {
+        match self.get_channel_map() {
+            Some(map) if map.len() > 0 => map.len() as u32,
+            _ => self.get_sample_spec().map_or(1, |ss| ss.channels as u32),
+        }
+    }
 }
 
 impl Drop for Stream {
     fn drop(&mut self) {
-        // Throw away the `Result` from disconnecting, it may legitimately be bad if stream failed.
-        // See https://github.com/jnqnfe/pulse-binding-rust/issues/11
-        let _ = self.disconnect();
-        unsafe { capi::pa_stream_unref(self.ptr) };
+        if !self.weak {
+            // Throw away the `Result` from disconnecting, it may legitimately be bad if stream
+            // failed. See https://github.com/jnqnfe/pulse-binding-rust/issues/11
+            let _ = self.disconnect();
+            unsafe { capi::pa_stream_unref(self.ptr) };
+        }
         self.ptr = null_mut::<StreamInternal>();
     }
 }
@@ -1748,3 +1954,41 @@ fn event_cb_proxy(_: *mut StreamInternal, name: *const c_char, proplist: *mut Pr
         (callback)(n, pl);
     });
 }
+
+/// Proxy for the sink input info callback used by [`Stream::get_volume`].
+///
+/// Warning: This is for list cases only! On EOL or error it destroys the actual closure callback.
+extern "C"
+fn get_sink_input_volume_cb_proxy(_: *mut capi::pa_context, i: *const capi::pa_sink_input_info,
+    eol: i32, userdata: *mut c_void)
+{
+    let _ = std::panic::catch_unwind(|| {
+        match callback_for_list_instance::<dyn FnMut(Option<ChannelVolumes>)>(eol, userdata) {
+            ListInstanceCallback::Entry(callback) => {
+                assert!(!i.is_null());
+                (callback)(Some(unsafe { (*i).volume }.into()));
+            },
+            ListInstanceCallback::End(_) => {},
+            ListInstanceCallback::Error(mut callback) => { (callback)(None); },
+        }
+    });
+}
+
+/// Proxy for the source output info callback used by [`Stream::get_volume`].
+///
+/// Warning: This is for list cases only! On EOL or error it destroys the actual closure callback.
+extern "C"
+fn get_source_output_volume_cb_proxy(_: *mut capi::pa_context,
+    i: *const capi::pa_source_output_info, eol: i32, userdata: *mut c_void)
+{
+    let _ = std::panic::catch_unwind(|| {
+        match callback_for_list_instance::<dyn FnMut(Option<ChannelVolumes>)>(eol, userdata) {
+            ListInstanceCallback::Entry(callback) => {
+                assert!(!i.is_null());
+                (callback)(Some(unsafe { (*i).volume }.into()));
+            },
+            ListInstanceCallback::End(_) => {},
+            ListInstanceCallback::Error(mut callback) => { (callback)(None); },
+        }
+    });
+}
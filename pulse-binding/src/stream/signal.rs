@@ -0,0 +1,158 @@
+// Copyright 2020 Lyndon Brown
+//
+// This file is part of the PulseAudio Rust language binding.
+//
+// Licensed under the MIT license or the Apache license (version 2.0), at your option. You may not
+// copy, modify, or distribute this file except in compliance with said license. You can find copies
+// of these licenses either in the LICENSE-MIT and LICENSE-APACHE files, or alternatively at
+// <http://opensource.org/licenses/MIT> and <http://www.apache.org/licenses/LICENSE-2.0>
+// respectively.
+//
+// Portions of documentation are copied from the LGPL 2.1+ licensed PulseAudio C headers on a
+// fair-use basis, as discussed in the overall project readme (available in the git repository).
+
+//! Playback of test signals.
+//!
+//! # Overview
+//!
+//! A [`Player`] creates a playback stream fed from a [`Generator`], for checking audio hardware.
+//! The stream is marked with the `test` media role (see [`properties::MEDIA_ROLE`]), such that the
+//! server, and volume control applications, can recognise it as such.
+//!
+//! Data is written whenever the server requests it, from the stream’s write callback. The stream
+//! is disconnected when the [`Player`] is dropped.
+//!
+//! # Example
+//!
+//! Identifying the channels of the default sink:
+//!
+//! ```rust,ignore
+//! use pulse::channelmap::Position;
+//! use pulse::sample::generate::{Generator, Signal};
+//! use pulse::stream::signal::Player;
+//! use pulse::time::{MicroSeconds, MICROS_PER_SEC};
+//!
+//! let signal = Signal::ChannelIdent {
+//!     frequency: 440.0,
+//!     tone: MicroSeconds(MICROS_PER_SEC),
+//!     gap: MicroSeconds(MICROS_PER_SEC / 2),
+//! };
+//! let generator = Generator::new(&spec, &map, signal).unwrap();
+//! let mut player = Player::new(&mut context, "Speaker test", generator).unwrap();
+//! player.connect(None, None).unwrap();
+//!
+//! // Later, e.g. from a timer event
+//! if let Some(position) = player.get_active_position() {
+//!     println!("Playing: {}", Position::to_pretty_string(position).unwrap());
+//! }
+//! ```
+//!
+//! [`Player`]: struct.Player.html
+//! [`Generator`]: ../../sample/generate/struct.Generator.html
+//! [`properties::MEDIA_ROLE`]: ../../proplist/properties/constant.MEDIA_ROLE.html
+
+use std::rc::Rc;
+use std::cell::RefCell;
+use crate::channelmap::Position;
+use crate::context::Context;
+use crate::error::{Code, PAErr};
+use crate::proplist::{properties, Proplist};
+use crate::sample::generate::{Generator, Signal};
+use crate::volume::ChannelVolumes;
+use super::{Stream, SeekMode, flags};
+
+/// Media role of test signal streams.
+pub const MEDIA_ROLE: &str = "test";
+
+/// Plays a test signal.
+pub struct Player {
+    stream: Stream,
+    generator: Rc<RefCell<Generator>>,
+}
+
+impl Player {
+    /// Creates a new (unconnected) playback stream for the signal of the given generator, using its
+    /// sample specification and channel map.
+    ///
+    /// Returns an error if the stream could not be created.
+    pub fn new(context: &mut Context, name: &str, generator: Generator) -> Result<Self, PAErr> {
+        let mut proplist = Proplist::new().ok_or(PAErr::from(Code::Internal))?;
+        proplist.set_str(properties::MEDIA_ROLE, MEDIA_ROLE)
+            .or(Err(PAErr::from(Code::Internal)))?;
+
+        let spec = *generator.get_spec();
+        let map = *generator.get_channel_map();
+        let mut stream = Stream::new_with_proplist(context, name, &spec, Some(&map), &mut proplist)
+            .ok_or_else(|| context.errno())?;
+
+        let generator = Rc::new(RefCell::new(generator));
+        let frame_size = spec.frame_size();
+        let ptr = stream.ptr;
+        let gen = Rc::clone(&generator);
+        stream.set_write_callback(Some(Box::new(move |nbytes| {
+            Self::do_write(&mut Stream::from_raw_weak(ptr), &gen, nbytes, frame_size);
+        })));
+
+        Ok(Self { stream: stream, generator: generator })
+    }
+
+    /// Connects the stream for playback, to the given sink (or the default), at the given volume
+    /// (or a volume chosen by the server).
+    pub fn connect(&mut self, dev: Option<&str>, volume: Option<&ChannelVolumes>)
+        -> Result<(), PAErr>
+    {
+        self.stream.connect_playback(dev, None, flags::NOFLAGS, volume, None)
+    }
+
+    /// Gets the stream, e.g. for monitoring its state, or for connecting with custom options.
+    ///
+    /// The write callback must not be replaced.
+    #[inline]
+    pub fn get_stream(&mut self) -> &mut Stream {
+        &mut self.stream
+    }
+
+    /// Changes the signal being played, as [`Generator::set_signal`] does.
+    ///
+    /// The change is heard once data already written to the server has been played.
+    ///
+    /// [`Generator::set_signal`]: ../../sample/generate/struct.Generator.html#method.set_signal
+    pub fn set_signal(&self, signal: Signal) -> Result<(), PAErr> {
+        self.generator.borrow_mut().set_signal(signal)
+    }
+
+    /// Sets the amplitude of the signal, as [`Generator::set_amplitude`] does.
+    ///
+    /// [`Generator::set_amplitude`]:
+    /// ../../sample/generate/struct.Generator.html#method.set_amplitude
+    pub fn set_amplitude(&self, amplitude: f32) {
+        self.generator.borrow_mut().set_amplitude(amplitude)
+    }
+
+    /// Gets the position of the channel being identified, as [`Generator::get_active_position`]
+    /// does.
+    ///
+    /// Note that this reflects data most recently written to the server, which is ahead of what is
+    /// being heard by the amount of buffered data.
+    ///
+    /// [`Generator::get_active_position`]:
+    /// ../../sample/generate/struct.Generator.html#method.get_active_position
+    pub fn get_active_position(&self) -> Option<Position> {
+        self.generator.borrow().get_active_position()
+    }
+
+    fn do_write(stream: &mut Stream, generator: &RefCell<Generator>, nbytes: usize,
+        frame_size: usize)
+    {
+        let buffer = match stream.begin_write(Some(nbytes)) {
+            Ok(Some(buffer)) => buffer,
+            _ => return,
+        };
+        let len = std::cmp::min(buffer.len(), nbytes) / frame_size * frame_size;
+        let data = &mut buffer[..len];
+        match len > 0 && generator.borrow_mut().fill(data).is_ok() {
+            true => { let _ = stream.write(data, None, 0, SeekMode::Relative); },
+            false => { let _ = stream.cancel_write(); },
+        }
+    }
+}
@@ -0,0 +1,528 @@
+// Copyright 2020 Lyndon Brown
+//
+// This file is part of the PulseAudio Rust language binding.
+//
+// Licensed under the MIT license or the Apache license (version 2.0), at your option. You may not
+// copy, modify, or distribute this file except in compliance with said license. You can find copies
+// of these licenses either in the LICENSE-MIT and LICENSE-APACHE files, or alternatively at
+// <http://opensource.org/licenses/MIT> and <http://www.apache.org/licenses/LICENSE-2.0>
+// respectively.
+//
+// Portions of documentation are copied from the LGPL 2.1+ licensed PulseAudio C headers on a
+// fair-use basis, as discussed in the overall project readme (available in the git repository).
+
+//! Stream statistics collection.
+//!
+//! # Overview
+//!
+//! A [`Collector`] gathers information about the health of a stream over time:
+//!
+//!  * Buffer underflows and overflows (“xruns”), each with a timestamp.
+//!  * Minimum, average and maximum latency, as obtained from [`Stream::get_latency`] on each
+//!    latency update.
+//!  * Changes to the server-side buffer attributes.
+//!  * Moves to a different sink/source, and suspension/resumption of the device.
+//!
+//! A snapshot of what has been gathered can be taken at any time with [`Collector::report`]. The
+//! resulting [`Report`] is plain data, and can be serialized if the `serde` feature is enabled.
+//!
+//! All timestamps are relative to when the collector was created (or last reset).
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use libpulse_binding::stream::stats::Collector;
+//!
+//! let stats = Collector::new();
+//! stats.attach(&mut stream);
+//!
+//! // ...
+//!
+//! let report = stats.report();
+//! println!("underflows: {}", report.underflow_count);
+//! ```
+//!
+//! Note that latency updates are only delivered for streams using the
+//! [`flags::AUTO_TIMING_UPDATE`] flag, or when explicitly requested with
+//! [`Stream::update_timing_info`].
+//!
+//! [`Collector`]: struct.Collector.html
+//! [`Collector::report`]: struct.Collector.html#method.report
+//! [`Report`]: struct.Report.html
+//! [`Stream::get_latency`]: ../struct.Stream.html#method.get_latency
+//! [`Stream::update_timing_info`]: ../struct.Stream.html#method.update_timing_info
+//! [`flags::AUTO_TIMING_UPDATE`]: ../flags/constant.AUTO_TIMING_UPDATE.html
+
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::time::Instant;
+use crate::def::BufferAttr;
+use crate::time::MicroSeconds;
+use super::{Stream, Latency};
+
+/// Default maximum number of individual events retained per event list.
+pub const DEFAULT_HISTORY_LIMIT: usize = 1024;
+
+/// Records statistics about a stream.
+///
+/// The collector is a cheap handle to shared state; clones refer to the same statistics.
+#[derive(Clone)]
+pub struct Collector {
+    state: Rc<RefCell<State>>,
+}
+
+/// A buffer underflow or overflow occurrence.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Xrun {
+    /// When it occurred.
+    pub at: MicroSeconds,
+    /// The stream position at which it occurred, if known (underflows only).
+    pub index: Option<u64>,
+}
+
+/// A change of the server-side buffer attributes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BufferAttrChange {
+    /// When it occurred.
+    pub at: MicroSeconds,
+    /// The new buffer attributes.
+    pub attr: BufferAttr,
+}
+
+/// A device related event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceEvent {
+    /// When it occurred.
+    pub at: MicroSeconds,
+    /// What occurred.
+    pub kind: DeviceEventKind,
+}
+
+/// Kind of device event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DeviceEventKind {
+    /// The stream was moved to a different sink/source.
+    Moved {
+        /// Index of the new sink/source, if known.
+        index: Option<u32>,
+        /// Name of the new sink/source, if known.
+        name: Option<String>,
+    },
+    /// The sink/source was suspended.
+    Suspended,
+    /// The sink/source was resumed.
+    Resumed,
+}
+
+/// Summary of latency measurements.
+///
+/// Negative latencies (which can only occur with monitoring streams) are counted, but excluded
+/// from the minimum, average and maximum.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LatencySummary {
+    /// Number of (non-negative) measurements taken.
+    pub samples: u64,
+    /// Number of negative measurements seen.
+    pub negative_samples: u64,
+    /// Lowest latency seen.
+    pub min: MicroSeconds,
+    /// Mean latency.
+    pub avg: MicroSeconds,
+    /// Highest latency seen.
+    pub max: MicroSeconds,
+}
+
+/// A snapshot of collected statistics.
+///
+/// The per-event lists only hold the most recent events, up to the collector’s history limit,
+/// while the counts cover all events seen.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Report {
+    /// Time covered by the report.
+    pub elapsed: MicroSeconds,
+    /// Total number of underflows.
+    pub underflow_count: u64,
+    /// Total number of overflows.
+    pub overflow_count: u64,
+    /// Recent underflows.
+    pub underflows: Vec<Xrun>,
+    /// Recent overflows.
+    pub overflows: Vec<Xrun>,
+    /// Latency summary, `None` if no measurement has been taken.
+    pub latency: Option<LatencySummary>,
+    /// Recent buffer attribute changes.
+    pub buffer_attr_changes: Vec<BufferAttrChange>,
+    /// Recent device move/suspend events.
+    pub device_events: Vec<DeviceEvent>,
+}
+
+struct State {
+    started: Instant,
+    history_limit: usize,
+    underflow_count: u64,
+    overflow_count: u64,
+    underflows: VecDeque<Xrun>,
+    overflows: VecDeque<Xrun>,
+    latency_samples: u64,
+    latency_negative: u64,
+    latency_min: MicroSeconds,
+    latency_max: MicroSeconds,
+    latency_sum: u128,
+    buffer_attr_changes: VecDeque<BufferAttrChange>,
+    device_events: VecDeque<DeviceEvent>,
+}
+
+impl State {
+    fn new(history_limit: usize) -> Self {
+        Self {
+            started: Instant::now(),
+            history_limit: history_limit,
+            underflow_count: 0,
+            overflow_count: 0,
+            underflows: VecDeque::new(),
+            overflows: VecDeque::new(),
+            latency_samples: 0,
+            latency_negative: 0,
+            latency_min: MicroSeconds(0),
+            latency_max: MicroSeconds(0),
+            latency_sum: 0,
+            buffer_attr_changes: VecDeque::new(),
+            device_events: VecDeque::new(),
+        }
+    }
+
+    fn elapsed(&self) -> MicroSeconds {
+        MicroSeconds(self.started.elapsed().as_micros() as u64)
+    }
+
+    fn push<T>(list: &mut VecDeque<T>, limit: usize, item: T) {
+        if limit == 0 {
+            return;
+        }
+        while list.len() >= limit {
+            list.pop_front();
+        }
+        list.push_back(item);
+    }
+}
+
+impl Collector {
+    /// Creates a new collector, retaining up to [`DEFAULT_HISTORY_LIMIT`] events per event list.
+    ///
+    /// [`DEFAULT_HISTORY_LIMIT`]: constant.DEFAULT_HISTORY_LIMIT.html
+    pub fn new() -> Self {
+        Self::with_history_limit(DEFAULT_HISTORY_LIMIT)
+    }
+
+    /// Creates a new collector, retaining up to `limit` events per event list.
+    ///
+    /// Older events are discarded once the limit is reached; counts are unaffected.
+    pub fn with_history_limit(limit: usize) -> Self {
+        Self { state: Rc::new(RefCell::new(State::new(limit))) }
+    }
+
+    /// Attaches the collector to a stream, such that statistics are gathered automatically.
+    ///
+    /// This sets the underflow, overflow, latency update, buffer attribute, moved and suspended
+    /// callbacks of the stream, replacing any existing ones. If you need any of these callbacks
+    /// yourself, do not use this, instead feed the collector from your own callbacks with the
+    /// `record_*` methods.
+    pub fn attach(&self, stream: &mut Stream) {
+        let ptr = stream.ptr;
+
+        let state = Rc::clone(&self.state);
+        stream.set_underflow_callback(Some(Box::new(move || {
+            let index = Stream::from_raw_weak(ptr).get_underflow_index();
+            Self::do_record_underflow(&state, index);
+        })));
+
+        let state = Rc::clone(&self.state);
+        stream.set_overflow_callback(Some(Box::new(move || {
+            Self::do_record_overflow(&state);
+        })));
+
+        let state = Rc::clone(&self.state);
+        stream.set_latency_update_callback(Some(Box::new(move || {
+            if let Ok(latency) = Stream::from_raw_weak(ptr).get_latency() {
+                Self::do_record_latency(&state, latency);
+            }
+        })));
+
+        let state = Rc::clone(&self.state);
+        stream.set_buffer_attr_callback(Some(Box::new(move || {
+            if let Some(attr) = Stream::from_raw_weak(ptr).get_buffer_attr() {
+                Self::do_record_buffer_attr(&state, attr);
+            }
+        })));
+
+        let state = Rc::clone(&self.state);
+        stream.set_moved_callback(Some(Box::new(move || {
+            let s = Stream::from_raw_weak(ptr);
+            let name = s.get_device_name().map(|n| n.into_owned());
+            Self::do_record_device_event(&state,
+                DeviceEventKind::Moved { index: s.get_device_index(), name: name });
+        })));
+
+        let state = Rc::clone(&self.state);
+        stream.set_suspended_callback(Some(Box::new(move || {
+            match Stream::from_raw_weak(ptr).is_suspended() {
+                Ok(true) => Self::do_record_device_event(&state, DeviceEventKind::Suspended),
+                Ok(false) => Self::do_record_device_event(&state, DeviceEventKind::Resumed),
+                Err(_) => {},
+            }
+        })));
+    }
+
+    /// Detaches the collector from a stream, unsetting the callbacks set by [`attach`].
+    ///
+    /// [`attach`]: #method.attach
+    pub fn detach(&self, stream: &mut Stream) {
+        stream.set_underflow_callback(None);
+        stream.set_overflow_callback(None);
+        stream.set_latency_update_callback(None);
+        stream.set_buffer_attr_callback(None);
+        stream.set_moved_callback(None);
+        stream.set_suspended_callback(None);
+    }
+
+    /// Records an underflow, with the position at which it occurred if known.
+    ///
+    /// See [`Stream::get_underflow_index`](../struct.Stream.html#method.get_underflow_index).
+    pub fn record_underflow(&self, index: Option<u64>) {
+        Self::do_record_underflow(&self.state, index);
+    }
+
+    /// Records an overflow.
+    pub fn record_overflow(&self) {
+        Self::do_record_overflow(&self.state);
+    }
+
+    /// Records a latency measurement.
+    ///
+    /// `Latency::None` is ignored.
+    pub fn record_latency(&self, latency: Latency) {
+        Self::do_record_latency(&self.state, latency);
+    }
+
+    /// Records a change of buffer attributes.
+    pub fn record_buffer_attr(&self, attr: &BufferAttr) {
+        Self::do_record_buffer_attr(&self.state, attr);
+    }
+
+    /// Records a device related event.
+    pub fn record_device_event(&self, kind: DeviceEventKind) {
+        Self::do_record_device_event(&self.state, kind);
+    }
+
+    /// Discards everything collected so far, restarting the clock.
+    pub fn reset(&self) {
+        let mut state = self.state.borrow_mut();
+        let limit = state.history_limit;
+        *state = State::new(limit);
+    }
+
+    /// Takes a snapshot of the statistics collected so far.
+    pub fn report(&self) -> Report {
+        let state = self.state.borrow();
+        let latency = match state.latency_samples + state.latency_negative {
+            0 => None,
+            _ => Some(LatencySummary {
+                samples: state.latency_samples,
+                negative_samples: state.latency_negative,
+                min: state.latency_min,
+                avg: match state.latency_samples {
+                    0 => MicroSeconds(0),
+                    n => MicroSeconds((state.latency_sum / n as u128) as u64),
+                },
+                max: state.latency_max,
+            }),
+        };
+        Report {
+            elapsed: state.elapsed(),
+            underflow_count: state.underflow_count,
+            overflow_count: state.overflow_count,
+            underflows: state.underflows.iter().cloned().collect(),
+            overflows: state.overflows.iter().cloned().collect(),
+            latency: latency,
+            buffer_attr_changes: state.buffer_attr_changes.iter().cloned().collect(),
+            device_events: state.device_events.iter().cloned().collect(),
+        }
+    }
+
+    fn do_record_underflow(state: &RefCell<State>, index: Option<u64>) {
+        let mut state = state.borrow_mut();
+        let xrun = Xrun { at: state.elapsed(), index: index };
+        let limit = state.history_limit;
+        state.underflow_count += 1;
+        State::push(&mut state.underflows, limit, xrun);
+    }
+
+    fn do_record_overflow(state: &RefCell<State>) {
+        let mut state = state.borrow_mut();
+        let xrun = Xrun { at: state.elapsed(), index: None };
+        let limit = state.history_limit;
+        state.overflow_count += 1;
+        State::push(&mut state.overflows, limit, xrun);
+    }
+
+    fn do_record_latency(state: &RefCell<State>, latency: Latency) {
+        let mut state = state.borrow_mut();
+        match latency {
+            Latency::None => {},
+            Latency::Negative(_) => state.latency_negative += 1,
+            Latency::Positive(usecs) => {
+                if state.latency_samples == 0 || usecs < state.latency_min {
+                    state.latency_min = usecs;
+                }
+                if state.latency_samples == 0 || usecs > state.latency_max {
+                    state.latency_max = usecs;
+                }
+                state.latency_samples += 1;
+                state.latency_sum += usecs.0 as u128;
+            },
+        }
+    }
+
+    fn do_record_buffer_attr(state: &RefCell<State>, attr: &BufferAttr) {
+        let mut state = state.borrow_mut();
+        let change = BufferAttrChange { at: state.elapsed(), attr: *attr };
+        let limit = state.history_limit;
+        State::push(&mut state.buffer_attr_changes, limit, change);
+    }
+
+    fn do_record_device_event(state: &RefCell<State>, kind: DeviceEventKind) {
+        let mut state = state.borrow_mut();
+        let event = DeviceEvent { at: state.elapsed(), kind: kind };
+        let limit = state.history_limit;
+        State::push(&mut state.device_events, limit, event);
+    }
+}
+
+impl Default for Collector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attr(tlength: u32) -> BufferAttr {
+        BufferAttr { maxlength: std::u32::MAX, tlength: tlength, prebuf: std::u32::MAX,
+            minreq: std::u32::MAX, fragsize: std::u32::MAX }
+    }
+
+    #[test]
+    fn empty() {
+        let report = Collector::new().report();
+        assert_eq!(report.underflow_count, 0);
+        assert_eq!(report.overflow_count, 0);
+        assert!(report.underflows.is_empty());
+        assert!(report.overflows.is_empty());
+        assert_eq!(report.latency, None);
+        assert!(report.buffer_attr_changes.is_empty());
+        assert!(report.device_events.is_empty());
+    }
+
+    #[test]
+    fn xruns() {
+        let stats = Collector::new();
+        stats.record_underflow(Some(4096));
+        stats.record_underflow(None);
+        stats.record_overflow();
+
+        let report = stats.report();
+        assert_eq!(report.underflow_count, 2);
+        assert_eq!(report.overflow_count, 1);
+        let indexes: Vec<Option<u64>> = report.underflows.iter().map(|x| x.index).collect();
+        assert_eq!(indexes, vec![Some(4096), None]);
+        assert_eq!(report.overflows.len(), 1);
+        assert_eq!(report.overflows[0].index, None);
+        assert!(report.underflows[0].at <= report.underflows[1].at);
+        assert!(report.overflows[0].at <= report.elapsed);
+    }
+
+    #[test]
+    fn history_limit() {
+        let stats = Collector::new();
+        for i in 0..(DEFAULT_HISTORY_LIMIT as u64 + 10) {
+            stats.record_underflow(Some(i));
+        }
+        let report = stats.report();
+        assert_eq!(report.underflow_count, DEFAULT_HISTORY_LIMIT as u64 + 10);
+        assert_eq!(report.underflows.len(), DEFAULT_HISTORY_LIMIT);
+        // The oldest events are the ones discarded
+        assert_eq!(report.underflows[0].index, Some(10));
+        assert_eq!(report.underflows.last().unwrap().index, Some(DEFAULT_HISTORY_LIMIT as u64 + 9));
+
+        let stats = Collector::with_history_limit(0);
+        stats.record_overflow();
+        let report = stats.report();
+        assert_eq!(report.overflow_count, 1);
+        assert!(report.overflows.is_empty());
+    }
+
+    #[test]
+    fn latency() {
+        let stats = Collector::new();
+        stats.record_latency(Latency::None);
+        assert_eq!(stats.report().latency, None);
+
+        stats.record_latency(Latency::Negative(MicroSeconds(500)));
+        assert_eq!(stats.report().latency, Some(LatencySummary { samples: 0, negative_samples: 1,
+            min: MicroSeconds(0), avg: MicroSeconds(0), max: MicroSeconds(0) }));
+
+        stats.record_latency(Latency::Positive(MicroSeconds(2000)));
+        stats.record_latency(Latency::Positive(MicroSeconds(1000)));
+        stats.record_latency(Latency::Negative(MicroSeconds(10)));
+        stats.record_latency(Latency::Positive(MicroSeconds(6000)));
+        assert_eq!(stats.report().latency, Some(LatencySummary { samples: 3, negative_samples: 2,
+            min: MicroSeconds(1000), avg: MicroSeconds(3000), max: MicroSeconds(6000) }));
+    }
+
+    #[test]
+    fn buffer_attr_and_device_events() {
+        let stats = Collector::new();
+        stats.record_buffer_attr(&attr(1024));
+        stats.record_buffer_attr(&attr(2048));
+        stats.record_device_event(DeviceEventKind::Suspended);
+        stats.record_device_event(DeviceEventKind::Moved { index: Some(3), name: None });
+
+        let report = stats.report();
+        let tlengths: Vec<u32> = report.buffer_attr_changes.iter().map(|c| c.attr.tlength)
+            .collect();
+        assert_eq!(tlengths, vec![1024, 2048]);
+        let kinds: Vec<DeviceEventKind> = report.device_events.into_iter().map(|e| e.kind)
+            .collect();
+        assert_eq!(kinds, vec![DeviceEventKind::Suspended,
+            DeviceEventKind::Moved { index: Some(3), name: None }]);
+    }
+
+    #[test]
+    fn reset() {
+        let stats = Collector::with_history_limit(2);
+        let clone = stats.clone();
+        clone.record_overflow();
+        clone.record_latency(Latency::Positive(MicroSeconds(100)));
+        assert_eq!(stats.report().overflow_count, 1);
+
+        stats.reset();
+        let report = clone.report();
+        assert_eq!(report.overflow_count, 0);
+        assert_eq!(report.latency, None);
+
+        // The history limit is kept
+        for _ in 0..3 {
+            stats.record_overflow();
+        }
+        assert_eq!(stats.report().overflows.len(), 2);
+    }
+}
@@ -0,0 +1,47 @@
+// Copyright 2020 Lyndon Brown
+//
+// This file is part of the PulseAudio Rust language binding.
+//
+// Licensed under the MIT license or the Apache license (version 2.0), at your option. You may not
+// copy, modify, or distribute this file except in compliance with said license. You can find copies
+// of these licenses either in the LICENSE-MIT and LICENSE-APACHE files, or alternatively at
+// <http://opensource.org/licenses/MIT> and <http://www.apache.org/licenses/LICENSE-2.0>
+// respectively.
+//
+// Portions of documentation are copied from the LGPL 2.1+ licensed PulseAudio C headers on a
+// fair-use basis, as discussed in the overall project readme (available in the git repository).
+
+//! Fixtures shared between unit tests.
+//!
+//! These build structures directly, without going through the C API, such that tests using them
+//! need no PulseAudio library at runtime.
+
+use crate::channelmap::{Map, Position};
+use crate::sample::{Spec, SAMPLE_FLOAT32NE};
+use crate::volume::{ChannelVolumes, Volume};
+
+/// Creates a channel map with the given positions.
+pub(crate) fn map(positions: &[Position]) -> Map {
+    let mut map = Map::default();
+    map.set_len(positions.len() as u8);
+    map.get_mut().copy_from_slice(positions);
+    map
+}
+
+/// Creates a channel map of up to three front channels (left, right, center).
+pub(crate) fn front_map(channels: u8) -> Map {
+    map(&[Position::FrontLeft, Position::FrontRight, Position::FrontCenter][..channels as usize])
+}
+
+/// Creates a channel volume structure with the given volumes.
+pub(crate) fn volumes(values: &[Volume]) -> ChannelVolumes {
+    let mut cv = ChannelVolumes::default();
+    cv.set_len(values.len() as u8);
+    cv.get_mut().copy_from_slice(values);
+    cv
+}
+
+/// Creates a specification for native floating point samples.
+pub(crate) fn float_spec(rate: u32, channels: u8) -> Spec {
+    Spec { format: SAMPLE_FLOAT32NE, channels: channels, rate: rate }
+}
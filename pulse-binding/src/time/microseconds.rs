@@ -18,6 +18,7 @@ use std::time::Duration;
 
 /// Microseconds. This is an unsigned 64-bit type.
 #[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MicroSeconds(pub u64);
 
 impl MicroSeconds {
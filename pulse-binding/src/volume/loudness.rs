@@ -0,0 +1,477 @@
+// Copyright 2020 Lyndon Brown
+//
+// This file is part of the PulseAudio Rust language binding.
+//
+// Licensed under the MIT license or the Apache license (version 2.0), at your option. You may not
+// copy, modify, or distribute this file except in compliance with said license. You can find copies
+// of these licenses either in the LICENSE-MIT and LICENSE-APACHE files, or alternatively at
+// <http://opensource.org/licenses/MIT> and <http://www.apache.org/licenses/LICENSE-2.0>
+// respectively.
+//
+// Portions of documentation are copied from the LGPL 2.1+ licensed PulseAudio C headers on a
+// fair-use basis, as discussed in the overall project readme (available in the git repository).
+
+//! Loudness normalisation (ReplayGain and EBU R128).
+//!
+//! # Overview
+//!
+//! This module helps with playing tracks at a consistent loudness, by calculating the volume to
+//! play each at. The result is a [`ChannelVolumes`] value, suitable for use as the initial volume
+//! when connecting a playback stream with [`Stream::connect_playback`], or for setting the volume
+//! of an existing stream.
+//!
+//! A track’s [`Gain`] can come from one of two places:
+//!
+//! * Tags stored with the track, from either ReplayGain (gain in dB and peak) or R128 (Opus style
+//!   fixed point gain) tagging, using [`Gain::from_replaygain`] or [`Gain::from_r128_tag`].
+//! * Measurement of the track’s audio data, using a [`Meter`], which measures integrated loudness
+//!   as specified by ITU-R BS.1770 and EBU R128, along with the sample peak.
+//!
+//! Gains are relative to a target loudness, in LUFS. Two common targets are provided:
+//! [`REPLAYGAIN_REFERENCE`] (-18 LUFS), as used by ReplayGain 2.0, and [`R128_REFERENCE`] (-23
+//! LUFS), as used by EBU R128 and R128 tags.
+//!
+//! When the peak of the track is known, the volume produced can be limited such that the track
+//! will not clip.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use pulse::volume::VolumeDB;
+//! use pulse::volume::loudness::{Meter, REPLAYGAIN_REFERENCE};
+//!
+//! let mut meter = Meter::new(&spec, &map).unwrap();
+//! meter.process(&data).unwrap();
+//!
+//! if let Some(gain) = meter.get_gain(REPLAYGAIN_REFERENCE) {
+//!     let volume = gain.to_channel_volumes(spec.channels, VolumeDB(0.0), true).unwrap();
+//!     stream.connect_playback(None, None, flags, Some(&volume), None).unwrap();
+//! }
+//! ```
+//!
+//! [`ChannelVolumes`]: ../struct.ChannelVolumes.html
+//! [`Stream::connect_playback`]: ../../stream/struct.Stream.html#method.connect_playback
+//! [`Gain`]: struct.Gain.html
+//! [`Gain::from_replaygain`]: struct.Gain.html#method.from_replaygain
+//! [`Gain::from_r128_tag`]: struct.Gain.html#method.from_r128_tag
+//! [`Meter`]: struct.Meter.html
+//! [`REPLAYGAIN_REFERENCE`]: constant.REPLAYGAIN_REFERENCE.html
+//! [`R128_REFERENCE`]: constant.R128_REFERENCE.html
+
+use crate::channelmap::{Map, Position};
+use crate::error::{Code, PAErr};
+use crate::sample::{self, Spec};
+use crate::sample::convert;
+use super::{ChannelVolumes, Volume, VolumeDB, VOLUME_MAX, sw_from_linear};
+
+/// Reference loudness of ReplayGain 2.0, in LUFS.
+pub const REPLAYGAIN_REFERENCE: f64 = -18.0;
+
+/// Reference loudness of EBU R128, and of R128 gain tags, in LUFS.
+pub const R128_REFERENCE: f64 = -23.0;
+
+/// Absolute gating threshold, in LUFS.
+const ABSOLUTE_GATE: f64 = -70.0;
+
+/// Relative gating threshold, in LU below the ungated loudness.
+const RELATIVE_GATE: f64 = -10.0;
+
+/// Number of frames processed at a time.
+const BLOCK_FRAMES: usize = 1024;
+
+/// The gain to apply to a track to reach a target loudness.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Gain {
+    /// Gain to apply.
+    pub gain: VolumeDB,
+    /// Sample peak of the track, as a linear amplitude (`1.0` being full scale), if known.
+    pub peak: Option<f64>,
+}
+
+impl Gain {
+    /// Creates a gain from ReplayGain tag values, i.e. the gain in dB and the peak as a linear
+    /// amplitude, if available.
+    pub fn from_replaygain(gain: f64, peak: Option<f64>) -> Self {
+        Self { gain: VolumeDB(gain), peak: peak }
+    }
+
+    /// Creates a gain from an R128 tag value (`R128_TRACK_GAIN` or `R128_ALBUM_GAIN`), which is a
+    /// Q7.8 fixed point number of dB relative to [`R128_REFERENCE`], adjusted for the given target
+    /// loudness (in LUFS).
+    ///
+    /// R128 tags do not record peaks.
+    ///
+    /// [`R128_REFERENCE`]: constant.R128_REFERENCE.html
+    pub fn from_r128_tag(value: i16, target: f64) -> Self {
+        let gain = value as f64 / 256.0 + (target - R128_REFERENCE);
+        Self { gain: VolumeDB(gain), peak: None }
+    }
+
+    /// Creates a gain from a measured integrated loudness (in LUFS), for the given target loudness
+    /// (in LUFS).
+    pub fn from_loudness(loudness: f64, peak: Option<f64>, target: f64) -> Self {
+        Self { gain: VolumeDB(target - loudness), peak: peak }
+    }
+
+    /// Converts to a software volume.
+    ///
+    /// `preamp` is added to the gain. If `prevent_clipping` is `true` and the peak is known, the
+    /// volume is reduced as necessary such that the peak does not exceed full scale.
+    pub fn to_volume(&self, preamp: VolumeDB, prevent_clipping: bool) -> Volume {
+        let mut linear = 10.0f64.powf((self.gain.0 + preamp.0) / 20.0);
+        if let (true, Some(peak)) = (prevent_clipping, self.peak) {
+            if peak > 0.0 && linear * peak > 1.0 {
+                linear = 1.0 / peak;
+            }
+        }
+        std::cmp::min(sw_from_linear(linear), VOLUME_MAX)
+    }
+
+    /// Converts to a channel volume structure, with the same volume on each of the given number of
+    /// channels, as [`to_volume`](#method.to_volume) does.
+    ///
+    /// Returns `None` if the number of channels is zero or exceeds
+    /// [`sample::CHANNELS_MAX`](../../sample/constant.CHANNELS_MAX.html).
+    pub fn to_channel_volumes(&self, channels: u8, preamp: VolumeDB, prevent_clipping: bool)
+        -> Option<ChannelVolumes>
+    {
+        if channels == 0 || channels as usize > sample::CHANNELS_MAX {
+            return None;
+        }
+        let volume = self.to_volume(preamp, prevent_clipping);
+        let mut cv = ChannelVolumes::default();
+        cv.set_len(channels);
+        for v in cv.get_mut() {
+            *v = volume;
+        }
+        Some(cv)
+    }
+}
+
+/// A second order IIR filter section (transposed direct form II).
+#[derive(Debug, Copy, Clone, Default)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+}
+
+impl Biquad {
+    #[inline]
+    fn run(&self, state: &mut [f64; 2], x: f64) -> f64 {
+        let y = self.b[0] * x + state[0];
+        state[0] = self.b[1] * x - self.a[0] * y + state[1];
+        state[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+/// Calculates the two stages of the K-weighting filter (a high shelf modelling the acoustic effect
+/// of the head, then a high pass), for the given sample rate.
+fn k_weighting(rate: u32) -> [Biquad; 2] {
+    let rate = rate as f64;
+
+    let (f0, gain, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+    let k = (std::f64::consts::PI * f0 / rate).tan();
+    let vh = 10.0f64.powf(gain / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad {
+        b: [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    };
+
+    let (f0, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (std::f64::consts::PI * f0 / rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad {
+        b: [1.0, -2.0, 1.0],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    };
+
+    [shelf, high_pass]
+}
+
+/// Gets the BS.1770 weighting of a channel position.
+fn channel_weight(position: Position) -> f64 {
+    match position {
+        Position::Lfe => 0.0,
+        Position::RearLeft | Position::RearRight | Position::SideLeft | Position::SideRight => 1.41,
+        _ => 1.0,
+    }
+}
+
+/// Converts a mean square energy to loudness, in LUFS.
+fn energy_to_loudness(energy: f64) -> f64 {
+    -0.691 + 10.0 * energy.log10()
+}
+
+/// Measures the integrated loudness and sample peak of audio data, per ITU-R BS.1770-4 and EBU
+/// R128.
+///
+/// Loudness is measured over gating blocks of 400 ms, overlapping by 75%, with absolute gating at
+/// -70 LUFS and relative gating at 10 LU below the ungated loudness. The peak measured is the
+/// sample peak, not the (oversampled) true peak.
+#[derive(Debug, Clone)]
+pub struct Meter {
+    spec: Spec,
+    channels: usize,
+    weights: [f64; sample::CHANNELS_MAX],
+    filters: [Biquad; 2],
+    /// Filter state, per channel and stage.
+    state: Vec<[[f64; 2]; 2]>,
+    /// Length of a 100 ms segment, in frames.
+    segment_len: usize,
+    /// Frames in the current segment so far.
+    segment_frames: usize,
+    /// Weighted sum of squares of the current segment so far.
+    segment_sum: f64,
+    /// Mean square energy of each of the last three complete segments.
+    recent: [f64; 3],
+    /// Number of complete segments so far.
+    segments: usize,
+    /// Mean square energy of each complete gating block.
+    blocks: Vec<f64>,
+    peak: f64,
+    buffer: Vec<f32>,
+}
+
+impl Meter {
+    /// Creates a new meter, for data of the given specification and channel map.
+    ///
+    /// The channel map is used to weight channels; LFE channels are ignored, and surround channels
+    /// emphasised.
+    ///
+    /// Returns an error if the specification is invalid, or does not match the channel map.
+    pub fn new(spec: &Spec, map: &Map) -> Result<Self, PAErr> {
+        if convert::sample_size(spec.format).is_none() || spec.channels == 0 ||
+           spec.channels as usize > sample::CHANNELS_MAX || spec.rate < 10 ||
+           spec.rate > sample::RATE_MAX || map.len() != spec.channels
+        {
+            return Err(PAErr::from(Code::Invalid));
+        }
+        let mut weights = [0.0; sample::CHANNELS_MAX];
+        for (w, p) in weights.iter_mut().zip(map.get()) {
+            *w = channel_weight(*p);
+        }
+        Ok(Self {
+            spec: *spec,
+            channels: spec.channels as usize,
+            weights: weights,
+            filters: k_weighting(spec.rate),
+            state: vec![[[0.0; 2]; 2]; spec.channels as usize],
+            segment_len: ((spec.rate + 5) / 10) as usize,
+            segment_frames: 0,
+            segment_sum: 0.0,
+            recent: [0.0; 3],
+            segments: 0,
+            blocks: Vec::new(),
+            peak: 0.0,
+            buffer: Vec::new(),
+        })
+    }
+
+    /// Gets the specification of the data measured.
+    #[inline]
+    pub fn get_spec(&self) -> &Spec {
+        &self.spec
+    }
+
+    /// Discards everything measured so far, to start measuring a new track.
+    pub fn reset(&mut self) {
+        for s in self.state.iter_mut() {
+            *s = [[0.0; 2]; 2];
+        }
+        self.segment_frames = 0;
+        self.segment_sum = 0.0;
+        self.recent = [0.0; 3];
+        self.segments = 0;
+        self.blocks.clear();
+        self.peak = 0.0;
+    }
+
+    /// Measures a buffer of interleaved frames, in the format given at creation.
+    ///
+    /// The buffer must contain a whole number of frames. Returns the number of frames measured.
+    pub fn process(&mut self, data: &[u8]) -> Result<usize, PAErr> {
+        let frame_size = convert::sample_size(self.spec.format).unwrap() * self.channels;
+        if data.len() % frame_size != 0 {
+            return Err(PAErr::from(Code::Invalid));
+        }
+        let mut buffer = std::mem::replace(&mut self.buffer, Vec::new());
+        buffer.resize(BLOCK_FRAMES * self.channels, 0.0);
+        for chunk in data.chunks(BLOCK_FRAMES * frame_size) {
+            let samples = &mut buffer[..(chunk.len() / frame_size * self.channels)];
+            convert::to_f32(chunk, self.spec.format, samples)?;
+            self.measure(samples);
+        }
+        self.buffer = buffer;
+        Ok(data.len() / frame_size)
+    }
+
+    /// Measures a buffer of interleaved native floating point frames, regardless of the sample
+    /// format given at creation.
+    ///
+    /// The buffer must contain a whole number of frames. Returns the number of frames measured.
+    pub fn process_f32(&mut self, data: &[f32]) -> Result<usize, PAErr> {
+        if data.len() % self.channels != 0 {
+            return Err(PAErr::from(Code::Invalid));
+        }
+        self.measure(data);
+        Ok(data.len() / self.channels)
+    }
+
+    /// Gets the integrated loudness of everything measured so far, in LUFS.
+    ///
+    /// Returns `None` if nothing above the absolute gating threshold has been measured (including
+    /// where less than 400 ms of data has been measured).
+    pub fn get_loudness(&self) -> Option<f64> {
+        let gated_mean = |threshold: f64| {
+            let (sum, count) = self.blocks.iter()
+                .filter(|e| energy_to_loudness(**e) > threshold)
+                .fold((0.0, 0usize), |(sum, count), e| (sum + e, count + 1));
+            match count {
+                0 => None,
+                _ => Some(sum / count as f64),
+            }
+        };
+        let ungated = gated_mean(ABSOLUTE_GATE)?;
+        let threshold = energy_to_loudness(ungated) + RELATIVE_GATE;
+        gated_mean(threshold.max(ABSOLUTE_GATE)).map(energy_to_loudness)
+    }
+
+    /// Gets the sample peak of everything measured so far, as a linear amplitude (`1.0` being full
+    /// scale).
+    #[inline]
+    pub fn get_peak(&self) -> f64 {
+        self.peak
+    }
+
+    /// Gets the gain needed to reach the given target loudness (in LUFS), along with the peak, from
+    /// everything measured so far.
+    ///
+    /// Returns `None` if the loudness could not be measured (see
+    /// [`get_loudness`](#method.get_loudness)).
+    pub fn get_gain(&self, target: f64) -> Option<Gain> {
+        self.get_loudness().map(|l| Gain::from_loudness(l, Some(self.peak), target))
+    }
+
+    fn measure(&mut self, data: &[f32]) {
+        for frame in data.chunks_exact(self.channels) {
+            let mut sum = 0.0;
+            for (c, x) in frame.iter().enumerate() {
+                let x = *x as f64;
+                self.peak = self.peak.max(x.abs());
+                let state = &mut self.state[c];
+                let y = self.filters[0].run(&mut state[0], x);
+                let y = self.filters[1].run(&mut state[1], y);
+                sum += self.weights[c] * y * y;
+            }
+            self.segment_sum += sum;
+            self.segment_frames += 1;
+            if self.segment_frames == self.segment_len {
+                self.end_segment();
+            }
+        }
+    }
+
+    /// Completes a 100 ms segment, and with it a 400 ms gating block, once there are enough
+    /// segments.
+    fn end_segment(&mut self) {
+        let energy = self.segment_sum / self.segment_len as f64;
+        if self.segments >= 3 {
+            let block = (self.recent.iter().sum::<f64>() + energy) / 4.0;
+            self.blocks.push(block);
+        }
+        self.recent = [self.recent[1], self.recent[2], energy];
+        self.segments += 1;
+        self.segment_frames = 0;
+        self.segment_sum = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample::Format;
+    use crate::test_util::map;
+    use crate::volume::{VOLUME_NORM, sw_to_db};
+
+    fn sine(spec: &Spec, amplitude: f32, seconds: usize) -> Vec<f32> {
+        let frames = spec.rate as usize * seconds;
+        let mut data = Vec::with_capacity(frames * spec.channels as usize);
+        for i in 0..frames {
+            let t = i as f32 / spec.rate as f32;
+            let v = amplitude * (2.0 * std::f32::consts::PI * 1000.0 * t).sin();
+            for _ in 0..spec.channels {
+                data.push(v);
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn sine_loudness() {
+        // A stereo 1 kHz sine wave at -20 dBFS measures -20 LUFS
+        let spec = Spec { format: Format::F32le, channels: 2, rate: 48000 };
+        let mut meter = Meter::new(&spec, &map(&[Position::FrontLeft, Position::FrontRight]))
+            .unwrap();
+        assert_eq!(meter.get_loudness(), None);
+        meter.process_f32(&sine(&spec, 0.1, 3)).unwrap();
+        assert!((meter.get_loudness().unwrap() + 20.0).abs() < 0.1);
+        assert!((meter.get_peak() - 0.1).abs() < 1e-3);
+        let gain = meter.get_gain(REPLAYGAIN_REFERENCE).unwrap();
+        assert!((gain.gain.0 - 2.0).abs() < 0.1);
+
+        // LFE is ignored
+        let mut meter = Meter::new(&spec, &map(&[Position::FrontLeft, Position::Lfe])).unwrap();
+        meter.process_f32(&sine(&spec, 0.1, 1)).unwrap();
+        assert!((meter.get_loudness().unwrap() + 23.01).abs() < 0.1);
+
+        meter.reset();
+        meter.process_f32(&vec![0.0; 96000]).unwrap();
+        assert_eq!(meter.get_loudness(), None);
+        assert_eq!(meter.get_peak(), 0.0);
+    }
+
+    #[test]
+    fn integer_formats() {
+        let spec = Spec { format: Format::S16le, channels: 1, rate: 44100 };
+        let mut meter = Meter::new(&spec, &map(&[Position::Mono])).unwrap();
+        let mut data = vec![0u8; 44100 * 2];
+        let samples = sine(&Spec { channels: 1, ..spec }, 0.5, 1);
+        convert::from_f32(&samples, Format::S16le, &mut data).unwrap();
+        assert_eq!(meter.process(&data).unwrap(), 44100);
+        assert!((meter.get_loudness().unwrap() + 9.03).abs() < 0.1);
+        assert!(meter.process(&data[..3]).is_err());
+
+        assert!(Meter::new(&spec, &map(&[Position::FrontLeft, Position::FrontRight])).is_err());
+    }
+
+    #[test]
+    fn gains() {
+        let gain = Gain::from_replaygain(-6.0, Some(0.5));
+        assert!((sw_to_db(gain.to_volume(VolumeDB(0.0), true)) + 6.0).abs() < 0.01);
+        // +12 dB would clip a peak of 0.5, so is limited to +6 dB
+        let gain = Gain::from_replaygain(12.0, Some(0.5));
+        assert!((sw_to_db(gain.to_volume(VolumeDB(0.0), true)) - 6.02).abs() < 0.01);
+        assert!((sw_to_db(gain.to_volume(VolumeDB(0.0), false)) - 12.0).abs() < 0.01);
+
+        let gain = Gain::from_r128_tag(-256, REPLAYGAIN_REFERENCE);
+        assert_eq!(gain.gain, VolumeDB(4.0));
+        assert_eq!(Gain::from_r128_tag(0, R128_REFERENCE).to_volume(VolumeDB(0.0), true),
+            VOLUME_NORM);
+
+        let gain = Gain::from_loudness(-18.0, None, -18.0);
+        let cv = gain.to_channel_volumes(2, VolumeDB(0.0), true).unwrap();
+        assert_eq!(cv.get(), &[VOLUME_NORM, VOLUME_NORM][..]);
+        let cv = gain.to_channel_volumes(sample::CHANNELS_MAX as u8, VolumeDB(0.0), true).unwrap();
+        assert_eq!(cv.len() as usize, sample::CHANNELS_MAX);
+        assert!(gain.to_channel_volumes(0, VolumeDB(0.0), true).is_none());
+        assert!(gain.to_channel_volumes(sample::CHANNELS_MAX as u8 + 1, VolumeDB(0.0), true)
+            .is_none());
+    }
+}
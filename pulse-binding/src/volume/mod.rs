@@ -67,6 +67,11 @@
 //! [`def::sink_flags::DECIBEL_VOLUME`]: ../def/sink_flags/constant.DECIBEL_VOLUME.html
 //! [`def::source_flags::DECIBEL_VOLUME`]: ../def/source_flags/constant.DECIBEL_VOLUME.html
 
+pub mod loudness;
+pub mod ramp;
+pub mod software;
+pub mod ui;
+
 use std::borrow::{Borrow, BorrowMut};
 use std::ffi::CStr;
 #[cfg(any(feature = "pa_v5", feature = "dox"))]
@@ -807,3 +812,44 @@ impl std::fmt::Display for ChannelVolumes {
         write!(f, "{}", &self.print())
     }
 }
+
+/* Pure Rust equivalents of the software volume conversion functions, for use within this crate
+   where calling into the C API is undesirable (e.g. in sample processing loops). */
+
+/// Converts a software volume to a linear factor, as `pa_sw_volume_to_linear()` does.
+pub(crate) fn sw_to_linear(v: Volume) -> f64 {
+    if v <= VOLUME_MUTED {
+        return 0.0;
+    }
+    if v == VOLUME_NORM {
+        return 1.0;
+    }
+    let f = v.0 as f64 / VOLUME_NORM.0 as f64;
+    f * f * f
+}
+
+/// Converts a linear factor to a software volume, as `pa_sw_volume_from_linear()` does.
+pub(crate) fn sw_from_linear(v: f64) -> Volume {
+    if !(v > 0.0) {
+        return VOLUME_MUTED;
+    }
+    let v = (v.cbrt() * VOLUME_NORM.0 as f64).round();
+    Volume(v.min(VOLUME_MAX.0 as f64) as u32)
+}
+
+/// Converts a software volume to decibels, as `pa_sw_volume_to_dB()` does, but with minus
+/// infinity for muted.
+pub(crate) fn sw_to_db(v: Volume) -> f64 {
+    match v <= VOLUME_MUTED {
+        true => std::f64::NEG_INFINITY,
+        false => 20.0 * sw_to_linear(v).log10(),
+    }
+}
+
+/// Converts decibels to a software volume, as `pa_sw_volume_from_dB()` does.
+pub(crate) fn sw_from_db(db: f64) -> Volume {
+    match db == std::f64::NEG_INFINITY || db <= DECIBEL_MINUS_INFINITY.0 {
+        true => VOLUME_MUTED,
+        false => sw_from_linear(10.0f64.powf(db / 20.0)),
+    }
+}
@@ -0,0 +1,411 @@
+// Copyright 2020 Lyndon Brown
+//
+// This file is part of the PulseAudio Rust language binding.
+//
+// Licensed under the MIT license or the Apache license (version 2.0), at your option. You may not
+// copy, modify, or distribute this file except in compliance with said license. You can find copies
+// of these licenses either in the LICENSE-MIT and LICENSE-APACHE files, or alternatively at
+// <http://opensource.org/licenses/MIT> and <http://www.apache.org/licenses/LICENSE-2.0>
+// respectively.
+//
+// Portions of documentation are copied from the LGPL 2.1+ licensed PulseAudio C headers on a
+// fair-use basis, as discussed in the overall project readme (available in the git repository).
+
+//! Smooth volume ramps (fades).
+//!
+//! # Overview
+//!
+//! A [`VolumeRamp`] gradually changes the volume of a sink, source, sink input or source output
+//! from one [`ChannelVolumes`] value to another over a given duration. It is driven by a timer
+//! event on the application’s mainloop, which periodically sends an updated volume to the server.
+//!
+//! How intermediate values are calculated is controlled by the [`Curve`] chosen.
+//!
+//! To avoid flooding the server, a new volume is only sent once the operation setting the previous
+//! one has completed; intermediate values are skipped as necessary. The final target volume is
+//! always sent.
+//!
+//! The ramp runs for as long as the [`VolumeRamp`] object is kept alive, or until cancelled with
+//! [`VolumeRamp::cancel`]. Cancelling (or dropping) it leaves the volume wherever it had reached.
+//!
+//! # Example
+//!
+//! Fading out a sink input over half a second:
+//!
+//! ```rust,ignore
+//! use pulse::volume::{ChannelVolumes, VOLUME_NORM, VOLUME_MUTED};
+//! use pulse::volume::ramp::{VolumeRamp, Target, Curve};
+//! use pulse::time::{MicroSeconds, MICROS_PER_MILLI};
+//!
+//! let mut from = ChannelVolumes::default();
+//! from.set(2, VOLUME_NORM);
+//! let mut to = ChannelVolumes::default();
+//! to.set(2, VOLUME_MUTED);
+//!
+//! let ramp = VolumeRamp::new(&mut mainloop, context.introspect(), Target::SinkInput(index),
+//!     &from, &to, MicroSeconds(500 * MICROS_PER_MILLI), Curve::Decibel);
+//! ```
+//!
+//! [`VolumeRamp`]: struct.VolumeRamp.html
+//! [`VolumeRamp::cancel`]: struct.VolumeRamp.html#method.cancel
+//! [`ChannelVolumes`]: ../struct.ChannelVolumes.html
+//! [`Curve`]: enum.Curve.html
+
+use std::rc::Rc;
+use std::cell::RefCell;
+use crate::context::introspect::Introspector;
+use crate::mainloop::api::{Mainloop, MainloopInnerType};
+use crate::mainloop::events::timer::{TimeEvent, TimeEventRef};
+use crate::operation::{self, Operation};
+use crate::time::{MicroSeconds, MonotonicTs, MICROS_PER_MILLI};
+use super::{ChannelVolumes, Volume, VolumeDB, sw_from_db, sw_from_linear, sw_to_db, sw_to_linear};
+
+/// Interval between volume updates.
+pub const UPDATE_INTERVAL: MicroSeconds = MicroSeconds(20 * MICROS_PER_MILLI);
+
+/// Lowest decibel value used for interpolation with [`Curve::Decibel`].
+///
+/// Muted volumes (minus infinity dB) are treated as this value for the purpose of calculating
+/// intermediate values.
+///
+/// [`Curve::Decibel`]: enum.Curve.html#variant.Decibel
+pub const DECIBEL_FLOOR: VolumeDB = VolumeDB(-60.0);
+
+/// The scale on which intermediate volumes are interpolated.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Curve {
+    /// Linear in [`Volume`](../struct.Volume.html) (cubic) units.
+    Volume,
+    /// Linear in decibels.
+    Decibel,
+    /// Linear in [`VolumeLinear`](../struct.VolumeLinear.html) (amplitude factor) units.
+    Linear,
+}
+
+/// The object whose volume is to be ramped.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// A sink, by index.
+    Sink(u32),
+    /// A source, by index.
+    Source(u32),
+    /// A sink input, by index.
+    SinkInput(u32),
+    /// A source output, by index.
+    SourceOutput(u32),
+}
+
+/// A volume ramp in progress.
+pub struct VolumeRamp<T>
+    where T: MainloopInnerType + 'static
+{
+    state: Rc<RefCell<State<Operation<dyn FnMut(bool)>>>>,
+    timer: Option<TimeEvent<T>>,
+}
+
+type VolumeSetter = dyn FnMut(&ChannelVolumes) -> Operation<dyn FnMut(bool)>;
+
+/// The application of a volume, which may still be in progress.
+trait Pending {
+    fn is_running(&self) -> bool;
+}
+
+impl Pending for Operation<dyn FnMut(bool)> {
+    fn is_running(&self) -> bool {
+        self.get_state() == operation::State::Running
+    }
+}
+
+struct State<P: Pending> {
+    from: ChannelVolumes,
+    to: ChannelVolumes,
+    curve: Curve,
+    start: MonotonicTs,
+    duration: MicroSeconds,
+    setter: Box<dyn FnMut(&ChannelVolumes) -> P>,
+    /// Application of the volume most recently sent, used for coalescing.
+    in_flight: Option<P>,
+    /// Volume most recently sent, if any.
+    current: Option<ChannelVolumes>,
+    finished: bool,
+}
+
+impl<T> VolumeRamp<T>
+    where T: MainloopInnerType + 'static
+{
+    /// Starts a new volume ramp on the given target.
+    ///
+    /// If `from` and `to` differ in number of channels, the average of `from` is used as the
+    /// starting point of every channel of `to`.
+    ///
+    /// Returns `None` if the timer event could not be created.
+    pub fn new<M>(mainloop: &mut M, mut introspector: Introspector, target: Target,
+        from: &ChannelVolumes, to: &ChannelVolumes, duration: MicroSeconds, curve: Curve)
+        -> Option<Self>
+        where M: Mainloop<MI=T> + ?Sized
+    {
+        let setter = Box::new(move |volume: &ChannelVolumes| {
+            match target {
+                Target::Sink(i) => introspector.set_sink_volume_by_index(i, volume, None),
+                Target::Source(i) => introspector.set_source_volume_by_index(i, volume, None),
+                Target::SinkInput(i) => introspector.set_sink_input_volume(i, volume, None),
+                Target::SourceOutput(i) => introspector.set_source_output_volume(i, volume, None),
+            }
+        });
+        Self::new_with_setter(mainloop, setter, from, to, duration, curve)
+    }
+
+    /// Starts a new volume ramp, using a custom function to apply each volume.
+    ///
+    /// The setter is given each volume to be applied, and must return the operation that applies
+    /// it, which is used for coalescing.
+    ///
+    /// Returns `None` if the timer event could not be created.
+    pub fn new_with_setter<M>(mainloop: &mut M, setter: Box<VolumeSetter>, from: &ChannelVolumes,
+        to: &ChannelVolumes, duration: MicroSeconds, curve: Curve) -> Option<Self>
+        where M: Mainloop<MI=T> + ?Sized
+    {
+        let mut start_volume = *from;
+        if from.len() != to.len() {
+            start_volume.set(to.len() as u32, from.avg());
+        }
+
+        let start = MonotonicTs::now();
+        let state = Rc::new(RefCell::new(State {
+            from: start_volume,
+            to: *to,
+            curve: curve,
+            start: start,
+            duration: duration,
+            setter: setter,
+            in_flight: None,
+            current: None,
+            finished: false,
+        }));
+
+        let state_ref = Rc::clone(&state);
+        let callback = Box::new(move |mut event: TimeEventRef<T>| {
+            let now = MonotonicTs::now();
+            if state_ref.borrow_mut().tick(now) {
+                event.restart_rt(now + UPDATE_INTERVAL);
+            }
+        });
+        let timer = mainloop.new_timer_event_rt(start, callback)?;
+        Some(Self { state: state, timer: Some(timer) })
+    }
+
+    /// Cancels the ramp, leaving the volume wherever it had reached.
+    pub fn cancel(&mut self) {
+        self.timer = None;
+        self.state.borrow_mut().finished = true;
+    }
+
+    /// Checks whether the ramp has completed (or been cancelled).
+    pub fn is_finished(&self) -> bool {
+        self.state.borrow().finished
+    }
+
+    /// Gets the volume most recently sent to the server, if any.
+    pub fn get_current(&self) -> Option<ChannelVolumes> {
+        self.state.borrow().current
+    }
+}
+
+impl<P: Pending> State<P> {
+    /// Performs an update. Returns `true` if further updates are needed.
+    fn tick(&mut self, now: MonotonicTs) -> bool {
+        if self.finished {
+            return false;
+        }
+
+        // Coalesce: wait for the previous change to complete before sending another
+        if self.in_flight.as_ref().map_or(false, |pending| pending.is_running()) {
+            return true;
+        }
+
+        let elapsed = (now.0).checked_sub(self.start.0).unwrap_or(MicroSeconds(0));
+        let done = elapsed >= self.duration;
+        let volume = match done {
+            true => self.to,
+            false => self.value_at(elapsed.0 as f64 / self.duration.0 as f64),
+        };
+
+        if self.current.map_or(true, |c| c.get() != volume.get()) {
+            self.in_flight = Some((self.setter)(&volume));
+            self.current = Some(volume);
+        }
+
+        self.finished = done;
+        !done
+    }
+
+    /// Calculates the volume at the given point in the ramp (`0.0` to `1.0`).
+    fn value_at(&self, progress: f64) -> ChannelVolumes {
+        let mut volume = self.to;
+        let from = self.from.get();
+        for (i, v) in volume.get_mut().iter_mut().enumerate() {
+            *v = interpolate(from[i], *v, progress, self.curve);
+        }
+        volume
+    }
+}
+
+/// Calculates an intermediate volume between `a` and `b` on the given curve.
+fn interpolate(a: Volume, b: Volume, progress: f64, curve: Curve) -> Volume {
+    match curve {
+        Curve::Volume => {
+            let (a, b) = (a.0 as f64, b.0 as f64);
+            Volume((a + (b - a) * progress).round() as u32)
+        },
+        Curve::Decibel => {
+            let a = sw_to_db(a).max(DECIBEL_FLOOR.0);
+            let b = sw_to_db(b).max(DECIBEL_FLOOR.0);
+            sw_from_db(a + (b - a) * progress)
+        },
+        Curve::Linear => {
+            let (a, b) = (sw_to_linear(a), sw_to_linear(b));
+            sw_from_linear(a + (b - a) * progress)
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use crate::test_util::volumes;
+    use crate::volume::{VOLUME_MUTED, VOLUME_NORM};
+
+    const CURVES: [Curve; 3] = [Curve::Volume, Curve::Decibel, Curve::Linear];
+
+    impl Pending for Rc<Cell<bool>> {
+        fn is_running(&self) -> bool {
+            self.get()
+        }
+    }
+
+    #[test]
+    fn curve_endpoints() {
+        let (quiet, loud) = (Volume(VOLUME_NORM.0 / 4), VOLUME_NORM);
+        for &curve in &CURVES {
+            assert_eq!(interpolate(quiet, loud, 0.0, curve), quiet, "{:?}", curve);
+            assert_eq!(interpolate(quiet, loud, 1.0, curve), loud, "{:?}", curve);
+            assert_eq!(interpolate(loud, quiet, 0.0, curve), loud, "{:?}", curve);
+            assert_eq!(interpolate(loud, quiet, 1.0, curve), quiet, "{:?}", curve);
+            assert_eq!(interpolate(loud, loud, 0.5, curve), loud, "{:?}", curve);
+        }
+        assert_eq!(interpolate(VOLUME_MUTED, loud, 0.0, Curve::Volume), VOLUME_MUTED);
+        assert_eq!(interpolate(VOLUME_MUTED, loud, 0.0, Curve::Linear), VOLUME_MUTED);
+    }
+
+    #[test]
+    fn curve_monotonicity() {
+        for &curve in &CURVES {
+            for &(a, b) in &[(VOLUME_MUTED, VOLUME_NORM), (VOLUME_NORM, VOLUME_MUTED)] {
+                let values: Vec<Volume> = (0..=50)
+                    .map(|step| interpolate(a, b, step as f64 / 50.0, curve))
+                    .collect();
+                for pair in values.windows(2) {
+                    match a < b {
+                        true => assert!(pair[0] <= pair[1], "{:?}: {:?}", curve, values),
+                        false => assert!(pair[0] >= pair[1], "{:?}: {:?}", curve, values),
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn curve_shapes() {
+        let (a, b) = (VOLUME_MUTED, VOLUME_NORM);
+        assert_eq!(interpolate(a, b, 0.5, Curve::Volume), Volume(VOLUME_NORM.0 / 2));
+        assert_eq!(interpolate(a, b, 0.5, Curve::Linear), sw_from_linear(0.5));
+
+        // Muted is treated as the floor value
+        assert_eq!(interpolate(a, b, 0.0, Curve::Decibel), sw_from_db(DECIBEL_FLOOR.0));
+        assert_eq!(interpolate(b, a, 1.0, Curve::Decibel), sw_from_db(DECIBEL_FLOOR.0));
+        assert_eq!(interpolate(a, b, 0.5, Curve::Decibel), sw_from_db(DECIBEL_FLOOR.0 / 2.0));
+        // Values below the floor are clamped too
+        let below = sw_from_db(DECIBEL_FLOOR.0 - 20.0);
+        assert_eq!(interpolate(below, b, 0.0, Curve::Decibel), sw_from_db(DECIBEL_FLOOR.0));
+    }
+
+    /// Creates a ramp state whose volumes sent are recorded, along with a flag controlling whether
+    /// the latest is still in progress.
+    fn new_state(from: Volume, to: Volume, duration: MicroSeconds)
+        -> (State<Rc<Cell<bool>>>, Rc<RefCell<Vec<ChannelVolumes>>>, Rc<Cell<bool>>)
+    {
+        let sent = Rc::new(RefCell::new(Vec::new()));
+        let running = Rc::new(Cell::new(false));
+        let (sent_ref, running_ref) = (Rc::clone(&sent), Rc::clone(&running));
+        let state = State {
+            from: volumes(&[from, from]),
+            to: volumes(&[to, to]),
+            curve: Curve::Volume,
+            start: MonotonicTs(MicroSeconds(1_000_000)),
+            duration: duration,
+            setter: Box::new(move |volume: &ChannelVolumes| {
+                sent_ref.borrow_mut().push(*volume);
+                Rc::clone(&running_ref)
+            }),
+            in_flight: None,
+            current: None,
+            finished: false,
+        };
+        (state, sent, running)
+    }
+
+    fn at(millis: u64) -> MonotonicTs {
+        MonotonicTs(MicroSeconds(1_000_000 + millis * MICROS_PER_MILLI))
+    }
+
+    fn firsts(sent: &RefCell<Vec<ChannelVolumes>>) -> Vec<Volume> {
+        sent.borrow().iter().map(|cv| cv.get()[0]).collect()
+    }
+
+    #[test]
+    fn tick_completion() {
+        let duration = MicroSeconds(100 * MICROS_PER_MILLI);
+        let (mut state, sent, _) = new_state(VOLUME_MUTED, VOLUME_NORM, duration);
+
+        assert_eq!(state.tick(at(0)), true);
+        assert_eq!(state.tick(at(50)), true);
+        assert_eq!(state.finished, false);
+        // Overshooting the end gives exactly the target
+        assert_eq!(state.tick(at(120)), false);
+        assert_eq!(state.finished, true);
+        assert_eq!(firsts(&sent), vec![VOLUME_MUTED, Volume(VOLUME_NORM.0 / 2), VOLUME_NORM]);
+        assert_eq!(sent.borrow()[2].get(), &[VOLUME_NORM, VOLUME_NORM]);
+
+        // Nothing more once finished
+        assert_eq!(state.tick(at(200)), false);
+        assert_eq!(sent.borrow().len(), 3);
+        assert_eq!(state.current.map(|cv| cv.get()[0]), Some(VOLUME_NORM));
+    }
+
+    #[test]
+    fn tick_coalescing() {
+        let duration = MicroSeconds(100 * MICROS_PER_MILLI);
+        let (mut state, sent, running) = new_state(VOLUME_MUTED, VOLUME_NORM, duration);
+
+        assert_eq!(state.tick(at(25)), true);
+        assert_eq!(sent.borrow().len(), 1);
+
+        // No further volume is sent while the previous is still being applied, even at the end
+        running.set(true);
+        assert_eq!(state.tick(at(30)), true);
+        assert_eq!(state.tick(at(150)), true);
+        assert_eq!(sent.borrow().len(), 1);
+
+        running.set(false);
+        assert_eq!(state.tick(at(150)), false);
+        assert_eq!(firsts(&sent), vec![Volume(VOLUME_NORM.0 / 4), VOLUME_NORM]);
+
+        // An unchanged volume is not sent again
+        let (mut state, sent, _) = new_state(VOLUME_NORM, VOLUME_NORM, duration);
+        assert_eq!(state.tick(at(10)), true);
+        assert_eq!(state.tick(at(20)), true);
+        assert_eq!(state.tick(at(100)), false);
+        assert_eq!(sent.borrow().len(), 1);
+    }
+}
@@ -0,0 +1,287 @@
+// Copyright 2020 Lyndon Brown
+//
+// This file is part of the PulseAudio Rust language binding.
+//
+// Licensed under the MIT license or the Apache license (version 2.0), at your option. You may not
+// copy, modify, or distribute this file except in compliance with said license. You can find copies
+// of these licenses either in the LICENSE-MIT and LICENSE-APACHE files, or alternatively at
+// <http://opensource.org/licenses/MIT> and <http://www.apache.org/licenses/LICENSE-2.0>
+// respectively.
+//
+// Portions of documentation are copied from the LGPL 2.1+ licensed PulseAudio C headers on a
+// fair-use basis, as discussed in the overall project readme (available in the git repository).
+
+//! Software volume applied to audio data.
+//!
+//! # Overview
+//!
+//! This module scales interleaved sample data by a [`ChannelVolumes`] value within the client, as
+//! the server does when applying software volume. Each channel of the data (as described by a
+//! channel map) is scaled by the corresponding volume, converted to a linear factor with the same
+//! cubic mapping used for [`VolumeLinear`].
+//!
+//! For one-off changes, [`apply`] and [`apply_ramp`] work on a single buffer. A [`Scaler`] keeps
+//! track of the current volume across buffers, and can ramp smoothly to a new volume over a given
+//! number of frames, avoiding the “zipper” noise of abrupt changes.
+//!
+//! Integer sample formats are clipped when amplified beyond their range; floating point formats are
+//! not.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use pulse::volume::software::Scaler;
+//!
+//! let mut scaler = Scaler::new(Format::S16le, &map, &volume).unwrap();
+//! scaler.process(&mut data).unwrap();
+//!
+//! // Fade to the new volume over 10 ms
+//! scaler.set_volume(&new_volume, 441).unwrap();
+//! scaler.process(&mut data).unwrap();
+//! ```
+//!
+//! [`ChannelVolumes`]: ../struct.ChannelVolumes.html
+//! [`VolumeLinear`]: ../struct.VolumeLinear.html
+//! [`apply`]: fn.apply.html
+//! [`apply_ramp`]: fn.apply_ramp.html
+//! [`Scaler`]: struct.Scaler.html
+
+use crate::channelmap::Map;
+use crate::error::{Code, PAErr};
+use crate::sample::{self, Format};
+use crate::sample::convert;
+use super::{ChannelVolumes, VOLUME_MAX, sw_to_linear};
+
+/// Number of frames processed at a time.
+const BLOCK_FRAMES: usize = 128;
+
+/// Scales a buffer of interleaved frames by the given volume, in place.
+///
+/// The volume must have one value per channel of the map, and the buffer must contain a whole
+/// number of frames.
+pub fn apply(data: &mut [u8], format: Format, map: &Map, volume: &ChannelVolumes)
+    -> Result<(), PAErr>
+{
+    Scaler::new(format, map, volume)?.process(data).and(Ok(()))
+}
+
+/// Scales a buffer of interleaved frames by a volume ramping from `from` to `to` across the length
+/// of the buffer, in place.
+///
+/// Both volumes must have one value per channel of the map, and the buffer must contain a whole
+/// number of frames.
+pub fn apply_ramp(data: &mut [u8], format: Format, map: &Map, from: &ChannelVolumes,
+    to: &ChannelVolumes) -> Result<(), PAErr>
+{
+    let mut scaler = Scaler::new(format, map, from)?;
+    let frames = data.len() / scaler.frame_size();
+    scaler.set_volume(to, frames)?;
+    scaler.process(data).and(Ok(()))
+}
+
+/// Applies a volume to successive buffers, with optional ramping between volumes.
+#[derive(Debug, Clone)]
+pub struct Scaler {
+    format: Format,
+    channels: usize,
+    /// Current linear factor of each channel.
+    current: [f32; sample::CHANNELS_MAX],
+    /// Linear factor of each channel at the end of the ramp.
+    target: [f32; sample::CHANNELS_MAX],
+    /// Per-frame change of each channel during the ramp.
+    step: [f32; sample::CHANNELS_MAX],
+    /// Frames left in the ramp.
+    remaining: usize,
+    buffer: Vec<f32>,
+}
+
+impl Scaler {
+    /// Creates a new scaler, for data of the given format and channel map, starting at the given
+    /// volume.
+    ///
+    /// Returns an error if the format is invalid, or the volume does not have one valid value per
+    /// channel of the map.
+    pub fn new(format: Format, map: &Map, volume: &ChannelVolumes) -> Result<Self, PAErr> {
+        if convert::sample_size(format).is_none() || map.len() == 0 {
+            return Err(PAErr::from(Code::Invalid));
+        }
+        let mut scaler = Self {
+            format: format,
+            channels: map.len() as usize,
+            current: [0.0; sample::CHANNELS_MAX],
+            target: [0.0; sample::CHANNELS_MAX],
+            step: [0.0; sample::CHANNELS_MAX],
+            remaining: 0,
+            buffer: Vec::new(),
+        };
+        scaler.set_volume(volume, 0)?;
+        Ok(scaler)
+    }
+
+    /// Sets a new volume, reached by ramping linearly over the given number of frames.
+    ///
+    /// With a ramp length of zero, the new volume takes effect immediately. Setting a new volume
+    /// while a ramp is in progress starts a new ramp from wherever the previous one had reached.
+    ///
+    /// Returns an error if the volume does not have one valid value per channel.
+    pub fn set_volume(&mut self, volume: &ChannelVolumes, ramp_frames: usize)
+        -> Result<(), PAErr>
+    {
+        if volume.len() as usize != self.channels || volume.get().iter().any(|v| *v > VOLUME_MAX) {
+            return Err(PAErr::from(Code::Invalid));
+        }
+        for (c, v) in volume.get().iter().enumerate() {
+            self.target[c] = sw_to_linear(*v) as f32;
+            self.step[c] = match ramp_frames {
+                0 => 0.0,
+                n => (self.target[c] - self.current[c]) / n as f32,
+            };
+        }
+        self.remaining = ramp_frames;
+        if ramp_frames == 0 {
+            self.current = self.target;
+        }
+        Ok(())
+    }
+
+    /// Checks whether a ramp is in progress.
+    #[inline]
+    pub fn is_ramping(&self) -> bool {
+        self.remaining > 0
+    }
+
+    /// Scales a buffer of interleaved frames, in place.
+    ///
+    /// The buffer must contain a whole number of frames. Returns the number of frames processed.
+    pub fn process(&mut self, data: &mut [u8]) -> Result<usize, PAErr> {
+        let frame_size = self.frame_size();
+        if data.len() % frame_size != 0 {
+            return Err(PAErr::from(Code::Invalid));
+        }
+        if !self.is_ramping() && self.current[..self.channels].iter().all(|g| *g == 1.0) {
+            return Ok(data.len() / frame_size);
+        }
+
+        let mut buffer = std::mem::replace(&mut self.buffer, Vec::new());
+        buffer.resize(BLOCK_FRAMES * self.channels, 0.0);
+        for chunk in data.chunks_mut(BLOCK_FRAMES * frame_size) {
+            let samples = &mut buffer[..(chunk.len() / frame_size * self.channels)];
+            convert::to_f32(chunk, self.format, samples)?;
+            self.scale(samples);
+            convert::from_f32(samples, self.format, chunk)?;
+        }
+        self.buffer = buffer;
+        Ok(data.len() / frame_size)
+    }
+
+    /// Scales a buffer of interleaved native floating point frames, in place, regardless of the
+    /// sample format this scaler was created for.
+    ///
+    /// The buffer must contain a whole number of frames. Returns the number of frames processed.
+    pub fn process_f32(&mut self, data: &mut [f32]) -> Result<usize, PAErr> {
+        if data.len() % self.channels != 0 {
+            return Err(PAErr::from(Code::Invalid));
+        }
+        self.scale(data);
+        Ok(data.len() / self.channels)
+    }
+
+    fn frame_size(&self) -> usize {
+        convert::sample_size(self.format).unwrap() * self.channels
+    }
+
+    fn scale(&mut self, data: &mut [f32]) {
+        let channels = self.channels;
+        let mut frames = data.chunks_exact_mut(channels);
+
+        // Ramp portion
+        while self.remaining > 0 {
+            let frame = match frames.next() {
+                Some(frame) => frame,
+                None => return,
+            };
+            for (c, v) in frame.iter_mut().enumerate() {
+                *v *= self.current[c];
+                self.current[c] += self.step[c];
+            }
+            self.remaining -= 1;
+            if self.remaining == 0 {
+                self.current = self.target;
+            }
+        }
+
+        // Constant portion
+        let gains = &self.current[..channels];
+        for frame in frames {
+            for (v, g) in frame.iter_mut().zip(gains) {
+                *v *= *g;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{front_map, volumes};
+    use crate::volume::{Volume, VOLUME_MUTED, VOLUME_NORM};
+
+    fn s16(values: &[i16]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes().to_vec()).collect()
+    }
+
+    #[test]
+    fn cubic_mapping() {
+        assert_eq!(sw_to_linear(VOLUME_MUTED), 0.0);
+        assert_eq!(sw_to_linear(VOLUME_NORM), 1.0);
+        assert_eq!(sw_to_linear(Volume(VOLUME_NORM.0 / 2)), 0.125);
+    }
+
+    #[test]
+    fn per_channel() {
+        let mut data = s16(&[8000, 8000, -8000, -8000]);
+        let volume = volumes(&[Volume(VOLUME_NORM.0 / 2), VOLUME_NORM]);
+        apply(&mut data, Format::S16le, &front_map(2), &volume).unwrap();
+        assert_eq!(data, s16(&[1000, 8000, -1000, -8000]));
+
+        let mut data = vec![0u8, 255, 200, 100];
+        let volume = volumes(&[VOLUME_MUTED, VOLUME_MUTED]);
+        apply(&mut data, Format::U8, &front_map(2), &volume).unwrap();
+        assert_eq!(data, vec![128u8; 4]);
+    }
+
+    #[test]
+    fn clipping() {
+        let mut data = s16(&[20000, -20000]);
+        let volume = volumes(&[Volume(VOLUME_NORM.0 * 2)]);
+        apply(&mut data, Format::S16le, &front_map(1), &volume).unwrap();
+        assert_eq!(data, s16(&[32767, -32768]));
+    }
+
+    #[test]
+    fn ramping() {
+        let mut data = vec![1.0f32; 8];
+        let mut scaler = Scaler::new(Format::F32le, &front_map(1), &volumes(&[VOLUME_MUTED])).unwrap();
+        scaler.set_volume(&volumes(&[VOLUME_NORM]), 4).unwrap();
+        assert!(scaler.is_ramping());
+        // Ramp spanning two buffers
+        scaler.process_f32(&mut data[..2]).unwrap();
+        scaler.process_f32(&mut data[2..]).unwrap();
+        assert!(!scaler.is_ramping());
+        assert_eq!(data, vec![0.0, 0.25, 0.5, 0.75, 1.0, 1.0, 1.0, 1.0]);
+
+        let mut data = s16(&[16384; 4]);
+        apply_ramp(&mut data, Format::S16le, &front_map(1), &volumes(&[VOLUME_NORM]),
+            &volumes(&[VOLUME_MUTED])).unwrap();
+        assert_eq!(data, s16(&[16384, 12288, 8192, 4096]));
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(Scaler::new(Format::Invalid, &front_map(1), &volumes(&[VOLUME_NORM])).is_err());
+        assert!(Scaler::new(Format::S16le, &front_map(2), &volumes(&[VOLUME_NORM])).is_err());
+        let mut scaler = Scaler::new(Format::S16le, &front_map(1), &volumes(&[VOLUME_NORM])).unwrap();
+        assert!(scaler.set_volume(&volumes(&[Volume(VOLUME_MAX.0 + 1)]), 0).is_err());
+        assert!(scaler.process(&mut [0u8; 3]).is_err());
+    }
+}
@@ -0,0 +1,449 @@
+// Copyright 2020 Lyndon Brown
+//
+// This file is part of the PulseAudio Rust language binding.
+//
+// Licensed under the MIT license or the Apache license (version 2.0), at your option. You may not
+// copy, modify, or distribute this file except in compliance with said license. You can find copies
+// of these licenses either in the LICENSE-MIT and LICENSE-APACHE files, or alternatively at
+// <http://opensource.org/licenses/MIT> and <http://www.apache.org/licenses/LICENSE-2.0>
+// respectively.
+//
+// Portions of documentation are copied from the LGPL 2.1+ licensed PulseAudio C headers on a
+// fair-use basis, as discussed in the overall project readme (available in the git repository).
+
+//! Helpers for presenting volumes in user interfaces.
+//!
+//! # Overview
+//!
+//! This module provides the pieces commonly needed by volume controls:
+//!
+//! * Mapping between a slider position (from `0.0` to `1.0`) and a [`Volume`], with
+//!   [`slider_to_volume`] and [`volume_to_slider`], on one of several perceptual scales
+//!   ([`SliderCurve`]).
+//! * Stepping a volume up or down by a number of decibels or percentage points, as done for
+//!   keyboard shortcuts or scroll wheels, with [`Volume::step_db`] and [`Volume::step_percent`] and
+//!   their [`ChannelVolumes`] equivalents. Stepping up stops at [`Volume::ui_max`]; stepping a
+//!   [`ChannelVolumes`] keeps the balance between its channels.
+//! * Formatting volumes as percentages and decibels, with [`format_volume`] and
+//!   [`format_channel_volumes`], according to a [`Style`].
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use pulse::volume::{ChannelVolumes, Volume};
+//! use pulse::volume::ui::{self, SliderCurve, Style};
+//!
+//! let volume = ui::slider_to_volume(0.5, SliderCurve::Cubic, Volume::ui_max());
+//!
+//! let mut channels = ChannelVolumes::default();
+//! channels.set(2, volume);
+//! channels.step_db(3.0).unwrap();
+//!
+//! let style = Style { decimal_separator: ',', ..Style::default() };
+//! println!("{}", ui::format_channel_volumes(&channels, Some(&map), &style));
+//! ```
+//!
+//! [`Volume`]: ../struct.Volume.html
+//! [`ChannelVolumes`]: ../struct.ChannelVolumes.html
+//! [`Volume::step_db`]: ../struct.Volume.html#method.step_db
+//! [`Volume::step_percent`]: ../struct.Volume.html#method.step_percent
+//! [`Volume::ui_max`]: ../struct.Volume.html#method.ui_max
+//! [`slider_to_volume`]: fn.slider_to_volume.html
+//! [`volume_to_slider`]: fn.volume_to_slider.html
+//! [`SliderCurve`]: enum.SliderCurve.html
+//! [`format_volume`]: fn.format_volume.html
+//! [`format_channel_volumes`]: fn.format_channel_volumes.html
+//! [`Style`]: struct.Style.html
+
+use crate::channelmap::{Map, Position};
+use crate::sample;
+use super::{ChannelVolumes, Volume, VolumeDB, VOLUME_MAX, VOLUME_MUTED, VOLUME_NORM};
+use super::{sw_from_db, sw_from_linear, sw_to_db, sw_to_linear};
+
+/// Lowest level reached when stepping by decibels.
+///
+/// Stepping up from muted starts from this level; stepping down below it mutes.
+pub const STEP_FLOOR: VolumeDB = VolumeDB(-60.0);
+
+/// The scale on which slider positions map to volumes.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SliderCurve {
+    /// Linear in [`Volume`](../struct.Volume.html) units.
+    ///
+    /// Since volumes are cubic, this already gives a reasonable perceptual scale, and matches what
+    /// the standard PulseAudio tools display as a percentage.
+    Cubic,
+    /// Logarithmic in amplitude, reaching down to `floor` (relative to 0 dB) at the low end.
+    ///
+    /// This is steep near the top and shallow near the bottom, but unlike [`Decibel`] reaches
+    /// muted smoothly at position zero.
+    ///
+    /// [`Decibel`]: #variant.Decibel
+    Logarithmic {
+        /// Level of the (near) bottom of the slider.
+        floor: VolumeDB,
+    },
+    /// Linear in decibels, from `floor` (relative to 0 dB) at the low end up to the maximum.
+    ///
+    /// Position zero is muted.
+    Decibel {
+        /// Level of the (near) bottom of the slider.
+        floor: VolumeDB,
+    },
+}
+
+impl Default for SliderCurve {
+    fn default() -> Self {
+        SliderCurve::Cubic
+    }
+}
+
+/// Converts a slider position (from `0.0` to `1.0`) to a volume, on the given curve.
+///
+/// Position `1.0` corresponds to `max`, typically [`Volume::ui_max`]. Positions outside of the
+/// range are clamped.
+///
+/// [`Volume::ui_max`]: ../struct.Volume.html#method.ui_max
+pub fn slider_to_volume(position: f64, curve: SliderCurve, max: Volume) -> Volume {
+    let position = match position > 0.0 {
+        true => position.min(1.0),
+        false => return VOLUME_MUTED,
+    };
+    let max = std::cmp::min(max, VOLUME_MAX);
+    match curve {
+        SliderCurve::Cubic => Volume((max.0 as f64 * position).round() as u32),
+        SliderCurve::Logarithmic { floor } => {
+            let r = match floor_ratio(floor, max) {
+                Some(r) => r,
+                None => return max,
+            };
+            let linear = (r.powf(1.0 - position) - r) / (1.0 - r) * sw_to_linear(max);
+            std::cmp::min(sw_from_linear(linear), max)
+        },
+        SliderCurve::Decibel { floor } => {
+            let top = sw_to_db(max);
+            if !(top > floor.0) {
+                return max;
+            }
+            std::cmp::min(sw_from_db(floor.0 + (top - floor.0) * position), max)
+        },
+    }
+}
+
+/// Converts a volume to a slider position (from `0.0` to `1.0`), on the given curve.
+///
+/// This is the inverse of [`slider_to_volume`]. Volumes above `max` give position `1.0`, and on
+/// the [`Decibel`] curve, volumes below the floor give position `0.0`.
+///
+/// [`slider_to_volume`]: fn.slider_to_volume.html
+/// [`Decibel`]: enum.SliderCurve.html#variant.Decibel
+pub fn volume_to_slider(volume: Volume, curve: SliderCurve, max: Volume) -> f64 {
+    let max = std::cmp::min(max, VOLUME_MAX);
+    if volume <= VOLUME_MUTED || max <= VOLUME_MUTED {
+        return 0.0;
+    }
+    if volume >= max {
+        return 1.0;
+    }
+    let position = match curve {
+        SliderCurve::Cubic => volume.0 as f64 / max.0 as f64,
+        SliderCurve::Logarithmic { floor } => {
+            let r = match floor_ratio(floor, max) {
+                Some(r) => r,
+                None => return 1.0,
+            };
+            let x = sw_to_linear(volume) / sw_to_linear(max) * (1.0 - r) + r;
+            1.0 - x.ln() / r.ln()
+        },
+        SliderCurve::Decibel { floor } => {
+            let top = sw_to_db(max);
+            if !(top > floor.0) {
+                return 1.0;
+            }
+            (sw_to_db(volume) - floor.0) / (top - floor.0)
+        },
+    };
+    position.max(0.0).min(1.0)
+}
+
+/// Gets the amplitude ratio of the floor to the maximum, if it is below the maximum.
+fn floor_ratio(floor: VolumeDB, max: Volume) -> Option<f64> {
+    let r = 10.0f64.powf((floor.0 - sw_to_db(max)) / 20.0);
+    match r > 0.0 && r < 1.0 {
+        true => Some(r),
+        false => None,
+    }
+}
+
+impl Volume {
+    /// Steps the volume by the given number of decibels (negative to step down), returning the
+    /// result.
+    ///
+    /// Stepping up from muted starts from [`STEP_FLOOR`], and stepping down below it mutes.
+    /// Stepping up stops at [`Volume::ui_max`], though a volume already above that is left alone
+    /// rather than being pulled down to it.
+    ///
+    /// This is only valid for software volumes!
+    ///
+    /// [`STEP_FLOOR`]: ui/constant.STEP_FLOOR.html
+    /// [`Volume::ui_max`]: #method.ui_max
+    pub fn step_db(self, db: f64) -> Self {
+        step_db_limited(self, db, Volume::ui_max())
+    }
+
+    /// Steps the volume by the given number of percentage points (negative to step down),
+    /// returning the result.
+    ///
+    /// One percentage point is one hundredth of [`VOLUME_NORM`], as displayed by [`print`].
+    /// Stepping down stops at muted. Stepping up stops at [`Volume::ui_max`], though a volume
+    /// already above that is left alone rather than being pulled down to it.
+    ///
+    /// [`VOLUME_NORM`]: constant.VOLUME_NORM.html
+    /// [`print`]: #method.print
+    /// [`Volume::ui_max`]: #method.ui_max
+    pub fn step_percent(self, percent: f64) -> Self {
+        step_percent_limited(self, percent, Volume::ui_max())
+    }
+}
+
+impl ChannelVolumes {
+    /// Steps the loudest channel by the given number of decibels (negative to step down), as with
+    /// [`Volume::step_db`], scaling the other channels to keep the balance between them.
+    ///
+    /// Returns pointer to self, or `None` on error.
+    ///
+    /// [`Volume::step_db`]: struct.Volume.html#method.step_db
+    pub fn step_db(&mut self, db: f64) -> Option<&mut Self> {
+        let limit = Volume::ui_max();
+        self.step_by(|v| step_db_limited(v, db, limit))
+    }
+
+    /// Steps the loudest channel by the given number of percentage points (negative to step down),
+    /// as with [`Volume::step_percent`], scaling the other channels to keep the balance between
+    /// them.
+    ///
+    /// Returns pointer to self, or `None` on error.
+    ///
+    /// [`Volume::step_percent`]: struct.Volume.html#method.step_percent
+    pub fn step_percent(&mut self, percent: f64) -> Option<&mut Self> {
+        let limit = Volume::ui_max();
+        self.step_by(|v| step_percent_limited(v, percent, limit))
+    }
+
+    /// Decreases the volume passed in by `dec`, but not lower than `limit`.
+    ///
+    /// The proportions between the channels are kept. This is the counterpart of
+    /// [`inc_clamp`](#method.inc_clamp).
+    ///
+    /// Returns pointer to self, or `None` on error.
+    pub fn dec_clamp(&mut self, dec: Volume, limit: Volume) -> Option<&mut Self> {
+        self.step_by(|v| match v > limit {
+            true => std::cmp::max(Volume(v.0.saturating_sub(dec.0)), limit),
+            false => v,
+        })
+    }
+
+    /// Sets the loudest channel to the volume calculated from it by `f`, scaling the other channels
+    /// in proportion.
+    fn step_by<F>(&mut self, f: F) -> Option<&mut Self>
+        where F: FnOnce(Volume) -> Volume
+    {
+        let channels = self.len() as usize;
+        if channels == 0 || channels > sample::CHANNELS_MAX ||
+            self.get().iter().any(|v| *v > VOLUME_MAX)
+        {
+            return None;
+        }
+        let old = self.get().iter().copied().max().unwrap();
+        let new = f(old);
+        for v in self.get_mut() {
+            *v = match old == VOLUME_MUTED {
+                true => new,
+                false => Volume((v.0 as u64 * new.0 as u64 / old.0 as u64) as u32),
+            };
+        }
+        Some(self)
+    }
+}
+
+fn step_db_limited(volume: Volume, db: f64, limit: Volume) -> Volume {
+    if db > 0.0 && volume >= limit {
+        return volume;
+    }
+    let level = sw_to_db(volume).max(STEP_FLOOR.0) + db;
+    let stepped = match level < STEP_FLOOR.0 {
+        true => VOLUME_MUTED,
+        false => sw_from_db(level),
+    };
+    match db > 0.0 {
+        true => std::cmp::min(stepped, limit),
+        false => std::cmp::min(stepped, volume),
+    }
+}
+
+fn step_percent_limited(volume: Volume, percent: f64, limit: Volume) -> Volume {
+    if percent > 0.0 && volume >= limit {
+        return volume;
+    }
+    let step = (VOLUME_NORM.0 as f64 * percent / 100.0).round();
+    let stepped = (volume.0 as f64 + step).max(0.0);
+    match percent > 0.0 {
+        true => Volume(stepped.min(limit.0 as f64) as u32),
+        false => Volume(stepped as u32),
+    }
+}
+
+/// Formatting options for [`format_volume`] and [`format_channel_volumes`].
+///
+/// [`format_volume`]: fn.format_volume.html
+/// [`format_channel_volumes`]: fn.format_channel_volumes.html
+#[derive(Debug, Clone, PartialEq)]
+pub struct Style {
+    /// Character placed between the integer and fractional parts of decibel values, as appropriate
+    /// for the user’s locale.
+    pub decimal_separator: char,
+    /// Whether to show the decibel value after the percentage.
+    pub show_db: bool,
+    /// Number of decimal places of decibel values.
+    pub db_decimals: usize,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self {
+            decimal_separator: '.',
+            show_db: true,
+            db_decimals: 2,
+        }
+    }
+}
+
+/// Formats a volume as a percentage, optionally followed by its decibel value, e.g.
+/// “75% / -7.50 dB”.
+///
+/// The percentage is rounded to the nearest whole number, as by [`Volume::print`].
+///
+/// [`Volume::print`]: ../struct.Volume.html#method.print
+pub fn format_volume(volume: Volume, style: &Style) -> String {
+    let percent = (volume.0 as u64 * 100 + VOLUME_NORM.0 as u64 / 2) / VOLUME_NORM.0 as u64;
+    if !style.show_db {
+        return format!("{}%", percent);
+    }
+    let db = sw_to_db(volume);
+    let db = match db.is_finite() {
+        true => format!("{:.*}", style.db_decimals, db)
+            .replace('.', &style.decimal_separator.to_string()),
+        false => String::from("-inf"),
+    };
+    format!("{}% / {} dB", percent, db)
+}
+
+/// Formats each channel of a volume with [`format_volume`], prefixed by its label.
+///
+/// If `map` is provided (and matches the volume), the localised pretty name of each channel
+/// position is used as its label; otherwise channels are labelled by number.
+///
+/// [`format_volume`]: fn.format_volume.html
+pub fn format_channel_volumes(volume: &ChannelVolumes, map: Option<&Map>, style: &Style)
+    -> String
+{
+    let map = map.filter(|m| m.len() == volume.len());
+    let separator = match style.decimal_separator {
+        ',' => "; ",
+        _ => ", ",
+    };
+    volume.get().iter().enumerate()
+        .map(|(i, v)| {
+            let label = match map {
+                Some(map) => {
+                    let position = map.get()[i];
+                    Position::to_pretty_string(position).unwrap_or(position.to_string())
+                },
+                None => i.to_string(),
+            };
+            format!("{}: {}", label, format_volume(*v, style))
+        })
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::volumes;
+
+    #[test]
+    fn slider_curves() {
+        let curves = [
+            SliderCurve::Cubic,
+            SliderCurve::Logarithmic { floor: VolumeDB(-60.0) },
+            SliderCurve::Decibel { floor: VolumeDB(-60.0) },
+        ];
+        for curve in curves.iter() {
+            assert_eq!(slider_to_volume(0.0, *curve, VOLUME_NORM), VOLUME_MUTED);
+            assert_eq!(slider_to_volume(1.0, *curve, VOLUME_NORM), VOLUME_NORM);
+            assert_eq!(slider_to_volume(2.0, *curve, VOLUME_NORM), VOLUME_NORM);
+            assert_eq!(volume_to_slider(VOLUME_MUTED, *curve, VOLUME_NORM), 0.0);
+            assert_eq!(volume_to_slider(VOLUME_NORM, *curve, VOLUME_NORM), 1.0);
+            for i in 1..10 {
+                let position = i as f64 / 10.0;
+                let volume = slider_to_volume(position, *curve, VOLUME_NORM);
+                assert!(volume > VOLUME_MUTED && volume < VOLUME_NORM);
+                assert!((volume_to_slider(volume, *curve, VOLUME_NORM) - position).abs() < 1e-4);
+            }
+        }
+
+        assert_eq!(slider_to_volume(0.5, SliderCurve::Cubic, VOLUME_NORM).0, VOLUME_NORM.0 / 2);
+        let db = SliderCurve::Decibel { floor: VolumeDB(-60.0) };
+        assert!((sw_to_db(slider_to_volume(0.5, db, VOLUME_NORM)) + 30.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn stepping() {
+        let limit = Volume(VOLUME_NORM.0 * 3 / 2);
+        let half = Volume(VOLUME_NORM.0 / 2);
+
+        assert_eq!(step_percent_limited(half, 10.0, limit).0, VOLUME_NORM.0 / 2 + 6554);
+        assert_eq!(step_percent_limited(half, -60.0, limit), VOLUME_MUTED);
+        assert_eq!(step_percent_limited(VOLUME_NORM, 100.0, limit), limit);
+        let above = Volume(VOLUME_NORM.0 * 2);
+        assert_eq!(step_percent_limited(above, 5.0, limit), above);
+        assert!(step_percent_limited(above, -5.0, limit) < above);
+
+        assert!((sw_to_db(step_db_limited(VOLUME_NORM, -6.0, limit)) + 6.0).abs() < 0.01);
+        assert_eq!(step_db_limited(VOLUME_NORM, 20.0, limit), limit);
+        let quiet = step_db_limited(VOLUME_MUTED, 3.0, limit);
+        assert!((sw_to_db(quiet) - STEP_FLOOR.0 - 3.0).abs() < 0.01);
+        assert_eq!(step_db_limited(quiet, -6.0, limit), VOLUME_MUTED);
+        assert_eq!(step_db_limited(VOLUME_MUTED, -3.0, limit), VOLUME_MUTED);
+    }
+
+    #[test]
+    fn channel_balance() {
+        let mut cv = volumes(&[VOLUME_NORM, Volume(VOLUME_NORM.0 / 2)]);
+        cv.dec_clamp(Volume(VOLUME_NORM.0 / 2), Volume(VOLUME_NORM.0 / 4)).unwrap();
+        assert_eq!(cv.get(), &[Volume(VOLUME_NORM.0 / 2), Volume(VOLUME_NORM.0 / 4)][..]);
+        cv.dec_clamp(Volume(VOLUME_NORM.0 / 2), Volume(VOLUME_NORM.0 / 4)).unwrap();
+        assert_eq!(cv.get(), &[Volume(VOLUME_NORM.0 / 4), Volume(VOLUME_NORM.0 / 8)][..]);
+
+        let mut cv = volumes(&[VOLUME_MUTED, VOLUME_MUTED]);
+        cv.step_by(|_| VOLUME_NORM).unwrap();
+        assert_eq!(cv.get(), &[VOLUME_NORM, VOLUME_NORM][..]);
+
+        assert!(volumes(&[Volume(VOLUME_MAX.0 + 1)]).step_by(|v| v).is_none());
+        assert!(ChannelVolumes::default().step_by(|v| v).is_none());
+    }
+
+    #[test]
+    fn formatting() {
+        let style = Style::default();
+        assert_eq!(format_volume(VOLUME_NORM, &style), "100% / 0.00 dB");
+        assert_eq!(format_volume(VOLUME_MUTED, &style), "0% / -inf dB");
+        let style = Style { decimal_separator: ',', show_db: true, db_decimals: 1 };
+        assert_eq!(format_volume(Volume(VOLUME_NORM.0 / 2), &style), "50% / -18,1 dB");
+
+        let style = Style { show_db: false, ..Style::default() };
+        let cv = volumes(&[VOLUME_NORM, Volume(VOLUME_NORM.0 * 3 / 4)]);
+        assert_eq!(format_channel_volumes(&cv, None, &style), "0: 100%, 1: 75%");
+    }
+}